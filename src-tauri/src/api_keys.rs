@@ -0,0 +1,585 @@
+//! Finding a provider's API key. Sources are tried in this order:
+//!
+//! 1. the environment (`OPENAI_API_KEY`, `GROQ_API_KEY`)
+//! 2. the provider's key command from the settings, e.g. `op read ...`
+//! 3. the provider's key file from the settings
+//! 4. the secret store (normally the OS keychain), where the settings
+//!    window stores keys
+//!
+//! A configured source that fails is logged and skipped.
+
+use crate::secrets::SecretStore;
+use crate::settings::{ApiKeySourceConfig, Settings, DEFAULT_CREDENTIAL};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+
+/// Providers whose keys are resolved and cached.
+const PROVIDERS: [&str; 2] = ["openai", "groq"];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeySource {
+    Env,
+    Command,
+    File,
+    /// The secret store, whichever backend is configured
+    Keychain,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedKey {
+    pub key: String,
+    pub source: ApiKeySource,
+}
+
+/// Environment variable holding a provider's key, e.g. `OPENAI_API_KEY`.
+pub fn env_var_name(provider_id: &str) -> String {
+    format!("{}_API_KEY", provider_id.to_uppercase())
+}
+
+/// Resolve a key from all sources. The keychain is only read when nothing
+/// else has a key, so keys from the environment never cause keychain prompts.
+pub fn resolve(
+    provider_id: &str,
+    config: Option<&ApiKeySourceConfig>,
+    keychain: impl FnOnce() -> Option<String>,
+) -> Option<ResolvedKey> {
+    resolve_external(provider_id, config).or_else(|| {
+        non_empty(&keychain()?).map(|key| ResolvedKey {
+            key,
+            source: ApiKeySource::Keychain,
+        })
+    })
+}
+
+/// Resolve a key from the sources that take precedence over the keychain.
+pub fn resolve_external(
+    provider_id: &str,
+    config: Option<&ApiKeySourceConfig>,
+) -> Option<ResolvedKey> {
+    resolve_external_with(provider_id, config, |name| std::env::var(name).ok())
+}
+
+/// Read a key from the secret store; a store that fails counts as having none.
+pub fn read_stored(store: &dyn SecretStore, provider_id: &str) -> Option<String> {
+    store.get(provider_id).unwrap_or_else(|e| {
+        tracing::warn!("Stored API key for {} unreadable: {}", provider_id, e);
+        None
+    })
+}
+
+/// Check that a key looks like one for the provider before storing it.
+pub fn validate_key_format(provider_id: &str, key: &str) -> Result<(), String> {
+    match provider_id {
+        "openai" if !key.starts_with("sk-") => {
+            Err("OpenAI API keys should start with 'sk-'".to_string())
+        }
+        "groq" if !key.starts_with("gsk_") => {
+            Err("Groq API keys should start with 'gsk_'".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Secret store account for a provider's credential. The default credential
+/// uses the bare provider id, where keys were stored before credentials had
+/// names.
+pub fn credential_account(provider_id: &str, credential: &str) -> String {
+    if credential == DEFAULT_CREDENTIAL {
+        provider_id.to_string()
+    } else {
+        format!("{}:{}", provider_id, credential)
+    }
+}
+
+/// Check a name for a new credential.
+pub fn validate_credential_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 32 {
+        return Err("Credential names must be 1 to 32 characters long".to_string());
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == ' ')
+    {
+        return Err(format!(
+            "Credential name \"{}\" may only contain letters, digits, spaces, '-' and '_'",
+            name
+        ));
+    }
+    if name == DEFAULT_CREDENTIAL {
+        return Err(format!("\"{}\" is always available", DEFAULT_CREDENTIAL));
+    }
+    Ok(())
+}
+
+/// In-memory cache for API keys so the secret store is only read once (at
+/// first setup or, for signed builds, at first launch after storing).  Every
+/// subsequent access — status checks, provider lists, transcriptions — reads
+/// from this cache instead of hitting the keychain again.
+pub struct ApiKeyCache {
+    store: Arc<dyn SecretStore>,
+    /// Keys from the environment, a key command or a key file; these take
+    /// precedence over every stored credential
+    external: HashMap<String, ResolvedKey>,
+    /// Secret store entries read so far, keyed by account (see
+    /// `credential_account`); `None` when the store has no key
+    stored: HashMap<String, Option<String>>,
+}
+
+impl ApiKeyCache {
+    /// Build the cache by resolving each provider once. The store is only
+    /// read for providers without a key from another source.
+    pub fn load(store: Arc<dyn SecretStore>, settings: &Settings) -> Self {
+        let mut cache = Self::new(store);
+        cache.reload_external(settings);
+        cache
+    }
+
+    fn new(store: Arc<dyn SecretStore>) -> Self {
+        Self {
+            store,
+            external: HashMap::new(),
+            stored: HashMap::new(),
+        }
+    }
+
+    /// Re-resolve the non-keychain sources, e.g. after the settings changed,
+    /// and read any credentials not seen before.
+    pub fn reload_external(&mut self, settings: &Settings) {
+        self.reload_external_with(settings, |name| std::env::var(name).ok())
+    }
+
+    fn reload_external_with(&mut self, settings: &Settings, env: impl Fn(&str) -> Option<String>) {
+        for provider in PROVIDERS {
+            let config = settings.api_key_sources.get(provider);
+            match resolve_external_with(provider, config, &env) {
+                Some(resolved) => {
+                    self.external.insert(provider.to_string(), resolved);
+                }
+                None => {
+                    self.external.remove(provider);
+                    let names = settings
+                        .credentials
+                        .get(provider)
+                        .map(|credentials| credentials.names.as_slice())
+                        .unwrap_or_default();
+                    for credential in
+                        std::iter::once(DEFAULT_CREDENTIAL).chain(names.iter().map(String::as_str))
+                    {
+                        let account = credential_account(provider, credential);
+                        if !self.stored.contains_key(&account) {
+                            let key = read_stored(self.store.as_ref(), &account);
+                            self.stored.insert(account, key);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Switch to another secret store, forgetting what was read from the old one.
+    pub fn replace_store(&mut self, store: Arc<dyn SecretStore>, settings: &Settings) {
+        self.store = store;
+        self.stored.clear();
+        self.reload_external(settings);
+    }
+
+    /// The key to use for `provider` when `credential` is selected.
+    pub fn get(&self, provider: &str, credential: &str) -> Option<String> {
+        self.external
+            .get(provider)
+            .map(|resolved| resolved.key.clone())
+            .or_else(|| self.stored_key(provider, credential))
+    }
+
+    pub fn has(&self, provider: &str, credential: &str) -> bool {
+        self.source(provider, credential).is_some()
+    }
+
+    /// Where the key in use for `provider` comes from.
+    pub fn source(&self, provider: &str, credential: &str) -> Option<ApiKeySource> {
+        match self.external.get(provider) {
+            Some(resolved) => Some(resolved.source),
+            None => self
+                .stored_key(provider, credential)
+                .map(|_| ApiKeySource::Keychain),
+        }
+    }
+
+    /// Whether the secret store holds a key for the credential, regardless of
+    /// keys from other sources.
+    pub fn has_stored(&self, provider: &str, credential: &str) -> bool {
+        self.stored_key(provider, credential).is_some()
+    }
+
+    fn stored_key(&self, provider: &str, credential: &str) -> Option<String> {
+        self.stored
+            .get(&credential_account(provider, credential))
+            .cloned()
+            .flatten()
+    }
+
+    /// Store `api_key` for the credential, or delete the stored key when it
+    /// is blank. The cache only changes once the store has.
+    pub fn save(&mut self, provider: &str, credential: &str, api_key: &str) -> Result<(), String> {
+        let account = credential_account(provider, credential);
+        let trimmed = api_key.trim();
+        if trimmed.is_empty() {
+            self.store.delete(&account)?;
+            self.stored.insert(account, None);
+        } else {
+            validate_key_format(provider, trimmed)?;
+            self.store.set(&account, trimmed)?;
+            self.stored.insert(account, Some(trimmed.to_string()));
+        }
+        Ok(())
+    }
+}
+
+fn resolve_external_with(
+    provider_id: &str,
+    config: Option<&ApiKeySourceConfig>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Option<ResolvedKey> {
+    if let Some(key) = env(&env_var_name(provider_id)).and_then(|v| non_empty(&v)) {
+        return Some(ResolvedKey {
+            key,
+            source: ApiKeySource::Env,
+        });
+    }
+
+    let config = config?;
+    if let Some(command) = &config.command {
+        match run_key_command(command) {
+            Ok(key) => {
+                return Some(ResolvedKey {
+                    key,
+                    source: ApiKeySource::Command,
+                })
+            }
+            Err(e) => tracing::warn!("API key command for {} failed: {}", provider_id, e),
+        }
+    }
+    if let Some(path) = &config.file {
+        match read_key_file(path) {
+            Ok(key) => {
+                return Some(ResolvedKey {
+                    key,
+                    source: ApiKeySource::File,
+                })
+            }
+            Err(e) => tracing::warn!("API key file for {} unusable: {}", provider_id, e),
+        }
+    }
+    None
+}
+
+fn read_key_file(path: &Path) -> Result<String, String> {
+    // Settings are often shared between machines, so allow `~/...`
+    let path = match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    };
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    non_empty(&content).ok_or_else(|| format!("{} is empty", path.display()))
+}
+
+/// Run `command` through the shell and take its trimmed stdout as the key.
+/// Errors leave out the command and its output, which can hold the key, as
+/// they end up in the log.
+fn run_key_command(command: &str) -> Result<String, String> {
+    #[cfg(windows)]
+    let output = Command::new("cmd").args(["/C", command]).output();
+    #[cfg(not(windows))]
+    let output = Command::new("sh").args(["-c", command]).output();
+
+    let output = output.map_err(|e| format!("Failed to run the key command: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("The key command exited with {}", output.status));
+    }
+    non_empty(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| "The key command printed nothing".to_string())
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let trimmed = value.trim();
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::MemoryStore;
+    use crate::settings::ProviderCredentials;
+    use std::path::PathBuf;
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn test_env_var_name() {
+        assert_eq!(env_var_name("openai"), "OPENAI_API_KEY");
+        assert_eq!(env_var_name("groq"), "GROQ_API_KEY");
+    }
+
+    #[test]
+    fn test_env_wins_over_configured_sources() {
+        let config = ApiKeySourceConfig {
+            file: Some(PathBuf::from("/nonexistent/key")),
+            command: Some("echo from-command".to_string()),
+        };
+        let env = |name: &str| (name == "GROQ_API_KEY").then(|| " gsk_env\n".to_string());
+        let resolved = resolve_external_with("groq", Some(&config), env).unwrap();
+        assert_eq!(resolved.key, "gsk_env");
+        assert_eq!(resolved.source, ApiKeySource::Env);
+
+        // Blank variables don't count
+        let blank = |_: &str| Some("  ".to_string());
+        assert!(resolve_external_with("groq", None, blank).is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_command_then_file() {
+        let path = std::env::temp_dir().join(format!("scrivano_key_{}", std::process::id()));
+        std::fs::write(&path, "sk-from-file\n").unwrap();
+
+        let mut config = ApiKeySourceConfig {
+            file: Some(path.clone()),
+            command: Some("printf 'sk-from-command\\n'".to_string()),
+        };
+        let resolved = resolve_external_with("openai", Some(&config), no_env).unwrap();
+        assert_eq!(resolved.key, "sk-from-command");
+        assert_eq!(resolved.source, ApiKeySource::Command);
+
+        // A failing command falls through to the file
+        config.command = Some("exit 3".to_string());
+        let resolved = resolve_external_with("openai", Some(&config), no_env).unwrap();
+        assert_eq!(resolved.key, "sk-from-file");
+        assert_eq!(resolved.source, ApiKeySource::File);
+
+        std::fs::write(&path, "\n").unwrap();
+        assert!(resolve_external_with("openai", Some(&config), no_env).is_none());
+
+        // Neither the command nor what it printed is in the error
+        let error = run_key_command("echo sk-leaked >&2; exit 3 # sk-inline").unwrap_err();
+        assert!(error.contains('3'));
+        assert!(!error.contains("sk-"), "{}", error);
+
+        assert_eq!(
+            read_key_file(Path::new("~/scrivano-missing-key")).unwrap_err(),
+            format!(
+                "Failed to read {}: No such file or directory (os error 2)",
+                dirs::home_dir()
+                    .unwrap()
+                    .join("scrivano-missing-key")
+                    .display()
+            )
+        );
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_keychain_only_read_as_last_resort() {
+        let config = ApiKeySourceConfig::default();
+        let resolved = resolve("scrivano_test_provider", Some(&config), || {
+            Some("sk-keychain".to_string())
+        })
+        .unwrap();
+        assert_eq!(resolved.source, ApiKeySource::Keychain);
+        assert!(resolve("scrivano_test_provider", None, || None).is_none());
+    }
+
+    /// A store whose writes always fail, e.g. a locked keychain.
+    struct ReadOnlyStore(MemoryStore);
+
+    impl SecretStore for ReadOnlyStore {
+        fn get(&self, account: &str) -> Result<Option<String>, String> {
+            self.0.get(account)
+        }
+
+        fn set(&self, _account: &str, _secret: &str) -> Result<(), String> {
+            Err("keychain is locked".to_string())
+        }
+
+        fn delete(&self, _account: &str) -> Result<(), String> {
+            Err("keychain is locked".to_string())
+        }
+    }
+
+    fn cache_with(store: Arc<dyn SecretStore>, settings: &Settings) -> ApiKeyCache {
+        let mut cache = ApiKeyCache::new(store);
+        cache.reload_external_with(settings, no_env);
+        cache
+    }
+
+    #[test]
+    fn test_cache_reads_store_once() {
+        let store = Arc::new(MemoryStore::default());
+        store.set("openai", "sk-stored").unwrap();
+        let mut cache = cache_with(store.clone(), &Settings::default());
+        assert_eq!(
+            cache.get("openai", DEFAULT_CREDENTIAL).as_deref(),
+            Some("sk-stored")
+        );
+        assert_eq!(
+            cache.source("openai", DEFAULT_CREDENTIAL),
+            Some(ApiKeySource::Keychain)
+        );
+        assert!(!cache.has("groq", DEFAULT_CREDENTIAL));
+
+        // Later changes to the store aren't picked up on reload
+        store.delete("openai").unwrap();
+        cache.reload_external_with(&Settings::default(), no_env);
+        assert_eq!(
+            cache.get("openai", DEFAULT_CREDENTIAL).as_deref(),
+            Some("sk-stored")
+        );
+
+        // ...but switching stores starts over
+        cache.replace_store(Arc::new(MemoryStore::default()), &Settings::default());
+        assert!(cache.get("openai", DEFAULT_CREDENTIAL).is_none());
+    }
+
+    #[test]
+    fn test_cache_prefers_external_keys() {
+        let store = Arc::new(MemoryStore::default());
+        store.set("groq", "gsk_stored").unwrap();
+        let mut cache = cache_with(store, &Settings::default());
+
+        let env = |name: &str| (name == "GROQ_API_KEY").then(|| "gsk_env".to_string());
+        cache.reload_external_with(&Settings::default(), env);
+        assert_eq!(
+            cache.get("groq", DEFAULT_CREDENTIAL).as_deref(),
+            Some("gsk_env")
+        );
+        assert_eq!(
+            cache.source("groq", DEFAULT_CREDENTIAL),
+            Some(ApiKeySource::Env)
+        );
+
+        // Once the variable is gone, the stored key is back in use
+        cache.reload_external_with(&Settings::default(), no_env);
+        assert_eq!(
+            cache.get("groq", DEFAULT_CREDENTIAL).as_deref(),
+            Some("gsk_stored")
+        );
+    }
+
+    #[test]
+    fn test_save_and_clear_key() {
+        let store = Arc::new(MemoryStore::default());
+        let mut cache = cache_with(store.clone(), &Settings::default());
+
+        assert_eq!(
+            cache
+                .save("openai", DEFAULT_CREDENTIAL, "gsk_wrong")
+                .unwrap_err(),
+            "OpenAI API keys should start with 'sk-'"
+        );
+        assert_eq!(
+            cache
+                .save("groq", DEFAULT_CREDENTIAL, "sk-wrong")
+                .unwrap_err(),
+            "Groq API keys should start with 'gsk_'"
+        );
+        assert!(store.get("openai").unwrap().is_none());
+
+        cache
+            .save("openai", DEFAULT_CREDENTIAL, "  sk-new\n")
+            .unwrap();
+        assert_eq!(store.get("openai").unwrap().as_deref(), Some("sk-new"));
+        assert_eq!(
+            cache.get("openai", DEFAULT_CREDENTIAL).as_deref(),
+            Some("sk-new")
+        );
+
+        cache.save("openai", DEFAULT_CREDENTIAL, "  ").unwrap();
+        assert!(store.get("openai").unwrap().is_none());
+        assert!(!cache.has("openai", DEFAULT_CREDENTIAL));
+        // Clearing a key that was never stored is fine
+        cache.save("groq", DEFAULT_CREDENTIAL, "").unwrap();
+    }
+
+    #[test]
+    fn test_named_credentials() {
+        let store = Arc::new(MemoryStore::default());
+        store.set("openai", "sk-default").unwrap();
+        store.set("openai:work", "sk-work").unwrap();
+        let mut settings = Settings::default();
+        settings.credentials.insert(
+            "openai".to_string(),
+            ProviderCredentials {
+                names: vec!["work".to_string(), "personal".to_string()],
+                ..ProviderCredentials::default()
+            },
+        );
+        let mut cache = cache_with(store.clone(), &settings);
+        assert_eq!(cache.get("openai", "work").as_deref(), Some("sk-work"));
+        assert_eq!(
+            cache.get("openai", DEFAULT_CREDENTIAL).as_deref(),
+            Some("sk-default")
+        );
+        assert!(!cache.has("openai", "personal"));
+
+        cache.save("openai", "personal", "sk-personal").unwrap();
+        assert_eq!(
+            store.get("openai:personal").unwrap().as_deref(),
+            Some("sk-personal")
+        );
+        cache.save("openai", "work", "").unwrap();
+        assert!(store.get("openai:work").unwrap().is_none());
+        assert_eq!(store.get("openai").unwrap().as_deref(), Some("sk-default"));
+
+        // A key from the environment is used whichever credential is selected,
+        // but the stored ones are still known
+        let env = |name: &str| (name == "OPENAI_API_KEY").then(|| "sk-env".to_string());
+        cache.reload_external_with(&settings, env);
+        assert_eq!(cache.get("openai", "personal").as_deref(), Some("sk-env"));
+        assert!(cache.has_stored("openai", "personal"));
+        assert!(!cache.has_stored("openai", "work"));
+    }
+
+    #[test]
+    fn test_credential_names() {
+        assert_eq!(credential_account("groq", DEFAULT_CREDENTIAL), "groq");
+        assert_eq!(credential_account("groq", "work"), "groq:work");
+        assert!(validate_credential_name("Client A_2").is_ok());
+        assert!(validate_credential_name("").is_err());
+        assert!(validate_credential_name("a:b").is_err());
+        assert!(validate_credential_name(DEFAULT_CREDENTIAL).is_err());
+        assert!(validate_credential_name(&"x".repeat(33)).is_err());
+    }
+
+    #[test]
+    fn test_failed_save_leaves_cache_alone() {
+        let inner = MemoryStore::default();
+        inner.set("openai", "sk-old").unwrap();
+        let mut cache = cache_with(Arc::new(ReadOnlyStore(inner)), &Settings::default());
+
+        assert_eq!(
+            cache
+                .save("openai", DEFAULT_CREDENTIAL, "sk-new")
+                .unwrap_err(),
+            "keychain is locked"
+        );
+        assert_eq!(
+            cache.get("openai", DEFAULT_CREDENTIAL).as_deref(),
+            Some("sk-old")
+        );
+        assert_eq!(
+            cache.save("openai", DEFAULT_CREDENTIAL, "").unwrap_err(),
+            "keychain is locked"
+        );
+        assert_eq!(
+            cache.get("openai", DEFAULT_CREDENTIAL).as_deref(),
+            Some("sk-old")
+        );
+    }
+}
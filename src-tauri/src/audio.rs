@@ -1,27 +1,124 @@
+use crate::decode;
+use crate::dsp::DspChain;
+use crate::meter::{self, AudioLevels, LevelMeter};
+use crate::settings::{AudioDeviceConfig, AudioProcessingConfig, Settings};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use hound::{WavSpec, WavWriter};
 use parking_lot::Mutex;
-use std::path::PathBuf;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
+
+mod pre_roll;
+#[cfg(test)]
+pub mod signal;
+
+use pre_roll::PreRollBuffer;
+pub use pre_roll::{start_pre_roll, PreRollHandle, DEFAULT_PRE_ROLL_MS};
 
 pub enum RecordingCommand {
-    Stop(Sender<Result<PathBuf, String>>),
+    Stop(Sender<Result<Recording, String>>),
+}
+
+/// A finished recording: the WAV file plus a loudness summary of its samples.
+#[derive(Debug)]
+pub struct Recording {
+    pub path: PathBuf,
+    pub speech: SpeechStats,
+    /// Input devices captured from, in order (more than one after a fallback).
+    pub devices: Vec<String>,
+}
+
+/// Frame length used when measuring speech activity.
+const SPEECH_FRAME_MS: u32 = 20;
+
+/// Frame RMS above which a frame counts as active (~ -40 dBFS).
+/// Room tone and a quiet laptop fan sit well below this; speech sits above it.
+const SPEECH_FRAME_RMS: f32 = 0.01;
+
+/// Loudness summary of a recording, used to tell real speech from silence.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SpeechStats {
+    /// RMS over the whole recording.
+    pub rms: f32,
+    /// Largest absolute sample.
+    pub peak: f32,
+    /// Fraction (0–1) of 20 ms frames loud enough to contain speech.
+    pub active_ratio: f32,
+}
+
+/// Measure overall loudness and how much of the recording is active.
+pub fn measure_speech(samples: &[f32], sample_rate: u32) -> SpeechStats {
+    if samples.is_empty() {
+        return SpeechStats::default();
+    }
+
+    let rms = (samples.iter().map(|&s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    let peak = samples.iter().fold(0.0f32, |max, &s| max.max(s.abs()));
+
+    let frames = samples.chunks(speech_frame_len(sample_rate));
+    let total = frames.len();
+    let active = frames
+        .filter(|frame| frame_rms(frame) > SPEECH_FRAME_RMS)
+        .count();
+
+    SpeechStats {
+        rms,
+        peak,
+        active_ratio: active as f32 / total as f32,
+    }
+}
+
+fn speech_frame_len(sample_rate: u32) -> usize {
+    ((sample_rate * SPEECH_FRAME_MS / 1000) as usize).max(1)
+}
+
+fn frame_rms(frame: &[f32]) -> f32 {
+    (frame.iter().map(|&s| s * s).sum::<f32>() / frame.len() as f32).sqrt()
+}
+
+/// Frame-level percentiles taken as the speech and the background level.
+const SNR_SPEECH_PERCENTILE: f32 = 0.95;
+const SNR_NOISE_PERCENTILE: f32 = 0.1;
+
+/// Lowest noise level used for SNR (-100 dBFS), so digital silence between
+/// words doesn't make it infinite.
+const SNR_MIN_NOISE_RMS: f32 = 1e-5;
+
+/// Estimate the signal-to-noise ratio in dB: the loudest 20 ms frames
+/// (speech) against the quietest (background noise between words).
+pub fn measure_snr(samples: &[f32], sample_rate: u32) -> f32 {
+    let mut levels: Vec<f32> = samples
+        .chunks(speech_frame_len(sample_rate))
+        .map(frame_rms)
+        .collect();
+    if levels.is_empty() {
+        return 0.0;
+    }
+    levels.sort_by(f32::total_cmp);
+    let percentile = |p: f32| levels[((levels.len() - 1) as f32 * p).round() as usize];
+
+    let noise = percentile(SNR_NOISE_PERCENTILE).max(SNR_MIN_NOISE_RMS);
+    let speech = percentile(SNR_SPEECH_PERCENTILE).max(noise);
+    20.0 * (speech / noise).log10()
 }
 
 pub struct RecordingHandle {
     command_sender: Sender<RecordingCommand>,
-    audio_levels: Arc<Mutex<Vec<f32>>>,
+    audio_levels: Arc<Mutex<AudioLevels>>,
 }
 
 impl RecordingHandle {
-    pub fn get_audio_levels_arc(&self) -> Arc<Mutex<Vec<f32>>> {
+    pub fn get_audio_levels_arc(&self) -> Arc<Mutex<AudioLevels>> {
         Arc::clone(&self.audio_levels)
     }
 
-    pub fn stop(self) -> Result<PathBuf, String> {
+    pub fn stop(self) -> Result<Recording, String> {
         let (result_sender, result_receiver) = mpsc::channel();
         self.command_sender
             .send(RecordingCommand::Stop(result_sender))
@@ -52,53 +149,361 @@ pub fn default_input_device_name() -> Option<String> {
     host.default_input_device().and_then(|d| d.name().ok())
 }
 
-/// Find an input device by name, falling back to the default.
-/// Logs a warning when a named device is not found.
-fn find_input_device(device_name: Option<&str>) -> Option<cpal::Device> {
+/// An input device and the format it captures in by default, for diagnostics.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputDeviceDetails {
+    pub name: String,
+    pub id: String,
+    pub is_default: bool,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub sample_format: Option<String>,
+    /// Why the default format couldn't be read
+    pub error: Option<String>,
+}
+
+/// Every input device with its default capture format.
+pub fn describe_input_devices() -> Vec<InputDeviceDetails> {
     let host = cpal::default_host();
-    if let Some(name) = device_name {
-        if let Ok(devices) = host.input_devices() {
-            for device in devices {
-                if let Ok(n) = device.name() {
-                    if n == name {
-                        return Some(device);
-                    }
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    let Ok(devices) = host.input_devices() else {
+        return Vec::new();
+    };
+    devices
+        .filter_map(|device| {
+            let name = device.name().ok()?;
+            let mut details = InputDeviceDetails {
+                id: device_id(&name),
+                is_default: default_name.as_deref() == Some(&name),
+                name,
+                sample_rate: None,
+                channels: None,
+                sample_format: None,
+                error: None,
+            };
+            match device.default_input_config() {
+                Ok(config) => {
+                    details.sample_rate = Some(config.sample_rate().0);
+                    details.channels = Some(config.channels());
+                    details.sample_format = Some(config.sample_format().to_string());
                 }
+                Err(e) => details.error = Some(e.to_string()),
             }
+            Some(details)
+        })
+        .collect()
+}
+
+/// Words naming the bus a device is connected over rather than which device
+/// it is. Dropped when comparing names so a headset matches over USB or
+/// Bluetooth. Profile words ("Hands-Free", "Analog") stay: they tell apart
+/// inputs of the same device.
+const CONNECTION_WORDS: &[&str] = &["usb", "bluetooth", "wireless"];
+
+/// Strip a Windows-style instance prefix ("2- Jabra Evolve 65").
+fn strip_instance_prefix(name: &str) -> &str {
+    match name.split_once("- ") {
+        Some((n, rest)) if !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) => rest,
+        _ => name,
+    }
+}
+
+/// Stable identity for a device: its name reduced to the part that survives
+/// reconnection. Lowercase, without instance numbers ("(2)", "#2", "2- "),
+/// bracketed driver details ("[hw:1,0]") or connection-type words.
+/// Per-device settings are keyed by this.
+pub fn device_id(name: &str) -> String {
+    let lower = name.to_lowercase();
+    let mut kept = String::with_capacity(lower.len());
+    let mut rest = strip_instance_prefix(&lower);
+
+    while let Some(open) = rest.find(['(', '[']) {
+        let Some(len) = rest[open..].find([')', ']']) else {
+            break;
+        };
+        kept.push_str(&rest[..open]);
+        let inner = strip_instance_prefix(rest[open + 1..open + len].trim());
+        let is_instance = inner
+            .trim_start_matches('#')
+            .chars()
+            .all(|c| c.is_ascii_digit());
+        if !is_instance && !inner.contains(':') {
+            kept.push(' ');
+            kept.push_str(inner);
+            kept.push(' ');
         }
-        eprintln!(
-            "[Scrivano] Audio device '{}' not found, falling back to system default",
-            name
+        rest = &rest[open + len + 1..];
+    }
+    kept.push_str(rest);
+
+    let is_instance_number =
+        |w: &str| w.len() > 1 && w.starts_with('#') && w[1..].chars().all(|c| c.is_ascii_digit());
+    kept.split(|c: char| !c.is_alphanumeric() && c != '#')
+        .filter(|w| !w.is_empty() && !is_instance_number(w) && !CONNECTION_WORDS.contains(w))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Index into `available` of the first preferred device that is connected.
+/// Each preference is matched by exact name first, then by `device_id`.
+fn match_preferred(preferred: &[String], available: &[&str]) -> Option<usize> {
+    preferred.iter().find_map(|wanted| {
+        available.iter().position(|n| n == wanted).or_else(|| {
+            let key = device_id(wanted);
+            available.iter().position(|n| device_id(n) == key)
+        })
+    })
+}
+
+/// Find the first connected device from `preferred`, falling back to the
+/// system default and then to any input. `exclude` skips a device that has
+/// just disappeared but may still be listed.
+fn find_input_device(preferred: &[String], exclude: Option<&str>) -> Option<cpal::Device> {
+    let host = cpal::default_host();
+    let mut devices: Vec<(String, cpal::Device)> = host
+        .input_devices()
+        .map(|devices| {
+            devices
+                .filter_map(|d| Some((d.name().ok()?, d)))
+                .filter(|(name, _)| Some(name.as_str()) != exclude)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let names: Vec<&str> = devices.iter().map(|(name, _)| name.as_str()).collect();
+    if let Some(i) = match_preferred(preferred, &names) {
+        return Some(devices.swap_remove(i).1);
+    }
+    if !preferred.is_empty() {
+        tracing::warn!(
+            "None of the preferred audio devices {:?} are connected, falling back to system default",
+            preferred
         );
     }
+
     host.default_input_device()
+        .filter(|d| d.name().ok().as_deref() != exclude)
+        .or_else(|| devices.into_iter().next().map(|(_, d)| d))
 }
 
-/// Push a mono sample into the level window and flush to audio_levels when full.
-fn push_level_sample(
-    mono_abs: f32,
-    level_window: &mut Vec<f32>,
-    audio_levels: &Arc<Mutex<Vec<f32>>>,
-) {
-    level_window.push(mono_abs);
-    if level_window.len() >= 512 {
-        update_audio_levels(level_window, audio_levels);
-        level_window.clear();
+/// Reported when the recording device disappears mid-recording and capture
+/// moves to another device (`to` is `None` when no input device is left).
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceSwitch {
+    pub from: String,
+    pub to: Option<String>,
+}
+
+/// How often the recording thread checks that its device is still present
+/// and reports the elapsed time.
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Default for `Settings::max_recording_secs`.
+pub const DEFAULT_MAX_RECORDING_SECS: u32 = 300;
+
+/// Providers reject uploads over 25 MB, so a recording is also stopped before
+/// its WAV would exceed this, whatever the time limit.
+pub const MAX_UPLOAD_BYTES: u64 = 25 * 1024 * 1024;
+
+/// `write_wav` output: a 44-byte header plus 16-bit mono samples.
+const WAV_HEADER_BYTES: u64 = 44;
+const WAV_BYTES_PER_SAMPLE: u64 = 2;
+
+/// How long before the limit `RecordingEvent::LimitWarning` is sent.
+const LIMIT_WARNING_LEAD: Duration = Duration::from_secs(10);
+
+/// Size of the WAV `write_wav` produces for `samples` samples.
+pub fn wav_size(samples: u64) -> u64 {
+    WAV_HEADER_BYTES + samples * WAV_BYTES_PER_SAMPLE
+}
+
+/// Most samples a recording at `sample_rate` may hold: `max_secs` (0 for no
+/// time limit), capped so the WAV stays within `MAX_UPLOAD_BYTES`.
+fn max_recording_samples(max_secs: u32, sample_rate: u32) -> usize {
+    let by_size = ((MAX_UPLOAD_BYTES - WAV_HEADER_BYTES) / WAV_BYTES_PER_SAMPLE) as usize;
+    if max_secs == 0 {
+        by_size
+    } else {
+        by_size.min(max_secs as usize * sample_rate as usize)
+    }
+}
+
+/// Progress of a running recording, for the indicator's timer.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingElapsed {
+    pub seconds: f64,
+    /// Size of the WAV upload if the recording stopped now.
+    pub estimated_bytes: u64,
+    /// When the recording will be stopped automatically.
+    pub limit_seconds: f64,
+}
+
+/// Notifications from the recording thread while it runs.
+#[derive(Debug, Clone)]
+pub enum RecordingEvent {
+    /// The input stream is open and capturing.
+    Started,
+    DeviceSwitched(DeviceSwitch),
+    Elapsed(RecordingElapsed),
+    /// The limit is `remaining_secs` away.
+    LimitWarning {
+        remaining_secs: f64,
+    },
+    /// Capture stopped at the limit; `RecordingHandle::stop` returns what
+    /// was recorded.
+    LimitReached,
+}
+
+/// How often `watch_input_devices` re-enumerates input devices.
+/// cpal has no hot-plug notifications, so changes are detected by polling.
+const DEVICE_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Format a capture stream was opened with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Keeps a started stream running; dropping it stops capture.
+pub type StreamGuard = Box<dyn std::any::Any>;
+
+/// Where captured audio comes from. `CpalSource` is the system's sound
+/// devices; tests use `signal::SignalSource`, which replays files or tones.
+pub trait AudioSource: Send + Sync {
+    /// Names of the input devices currently connected.
+    fn list_devices(&self) -> Vec<String>;
+
+    /// The first connected device from `preferred`, falling back to the
+    /// default and then to any input. `exclude` skips a device that has just
+    /// disappeared but may still be listed.
+    fn find_device(
+        &self,
+        preferred: &[String],
+        exclude: Option<&str>,
+    ) -> Option<Box<dyn InputDevice>>;
+}
+
+/// An input device that has been chosen but isn't streaming yet.
+pub trait InputDevice {
+    fn name(&self) -> String;
+
+    /// Pick the stream format, applying the per-device preferences in
+    /// `config` where the device supports them.
+    fn configure(&mut self, config: &AudioDeviceConfig) -> Result<StreamFormat, String>;
+
+    /// Start delivering frames in the configured format to `sink`. `failed`
+    /// is set when the device stops for good (e.g. it was unplugged).
+    fn start(
+        self: Box<Self>,
+        sink: CaptureSink,
+        failed: Arc<AtomicBool>,
+    ) -> Result<StreamGuard, String>;
+}
+
+/// The system's input devices, via cpal.
+pub struct CpalSource;
+
+impl AudioSource for CpalSource {
+    fn list_devices(&self) -> Vec<String> {
+        list_input_devices()
+    }
+
+    fn find_device(
+        &self,
+        preferred: &[String],
+        exclude: Option<&str>,
+    ) -> Option<Box<dyn InputDevice>> {
+        find_input_device(preferred, exclude).map(|device| {
+            Box::new(CpalDevice {
+                device,
+                config: None,
+            }) as Box<dyn InputDevice>
+        })
+    }
+}
+
+struct CpalDevice {
+    device: cpal::Device,
+    config: Option<(cpal::StreamConfig, cpal::SampleFormat)>,
+}
+
+impl InputDevice for CpalDevice {
+    fn name(&self) -> String {
+        self.device
+            .name()
+            .unwrap_or_else(|_| "Unknown device".to_string())
+    }
+
+    fn configure(&mut self, config: &AudioDeviceConfig) -> Result<StreamFormat, String> {
+        let (stream_config, sample_format) = choose_stream_config(&self.device, config)?;
+        let format = StreamFormat {
+            sample_rate: stream_config.sample_rate.0,
+            channels: stream_config.channels,
+        };
+        self.config = Some((stream_config, sample_format));
+        Ok(format)
+    }
+
+    fn start(
+        self: Box<Self>,
+        sink: CaptureSink,
+        failed: Arc<AtomicBool>,
+    ) -> Result<StreamGuard, String> {
+        let (config, sample_format) = self
+            .config
+            .ok_or_else(|| "Input device was not configured".to_string())?;
+        let device = self.device;
+
+        let err_fn = move |err: cpal::StreamError| {
+            tracing::error!("Audio stream error: {}", err);
+            if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+                failed.store(true, Ordering::Relaxed);
+            }
+        };
+
+        let stream = match sample_format {
+            cpal::SampleFormat::I8 => build_input_stream::<i8>(&device, &config, sink, err_fn),
+            cpal::SampleFormat::I16 => build_input_stream::<i16>(&device, &config, sink, err_fn),
+            cpal::SampleFormat::I32 => build_input_stream::<i32>(&device, &config, sink, err_fn),
+            cpal::SampleFormat::I64 => build_input_stream::<i64>(&device, &config, sink, err_fn),
+            cpal::SampleFormat::U8 => build_input_stream::<u8>(&device, &config, sink, err_fn),
+            cpal::SampleFormat::U16 => build_input_stream::<u16>(&device, &config, sink, err_fn),
+            cpal::SampleFormat::U32 => build_input_stream::<u32>(&device, &config, sink, err_fn),
+            cpal::SampleFormat::U64 => build_input_stream::<u64>(&device, &config, sink, err_fn),
+            cpal::SampleFormat::F32 => build_input_stream::<f32>(&device, &config, sink, err_fn),
+            cpal::SampleFormat::F64 => build_input_stream::<f64>(&device, &config, sink, err_fn),
+            other => return Err(format!("Unsupported sample format: {}", other)),
+        };
+
+        let stream = stream.map_err(|e| format!("Failed to build stream: {}", e))?;
+        stream
+            .play()
+            .map_err(|e| format!("Failed to start stream: {}", e))?;
+        Ok(Box::new(stream))
     }
 }
 
-pub fn start_recording(device_name: Option<&str>) -> Result<RecordingHandle, String> {
+/// Start recording on a background thread. `on_event` is called from that
+/// thread with device switches, elapsed time and limit notifications.
+pub fn start_recording(
+    source: Arc<dyn AudioSource>,
+    input: &InputSettings,
+    on_event: impl Fn(RecordingEvent) + Send + 'static,
+) -> Result<RecordingHandle, String> {
     let (command_sender, command_receiver): (Sender<RecordingCommand>, Receiver<RecordingCommand>) =
         mpsc::channel();
-    let audio_levels: Arc<Mutex<Vec<f32>>> = Arc::new(Mutex::new(vec![0.2; 3]));
+    let audio_levels = Arc::new(Mutex::new(AudioLevels::idle(input.meter_bars)));
     let audio_levels_clone = Arc::clone(&audio_levels);
 
-    let device_name_owned = device_name.map(|s| s.to_string());
+    let input = input.clone();
     thread::spawn(move || {
         run_recording(
+            source.as_ref(),
             command_receiver,
             audio_levels_clone,
-            device_name_owned.as_deref(),
+            &input,
+            on_event,
         );
     });
 
@@ -108,223 +513,575 @@ pub fn start_recording(device_name: Option<&str>) -> Result<RecordingHandle, Str
     })
 }
 
-fn run_recording(
-    command_receiver: Receiver<RecordingCommand>,
-    audio_levels: Arc<Mutex<Vec<f32>>>,
-    device_name: Option<&str>,
-) {
-    let device = match find_input_device(device_name) {
-        Some(d) => d,
-        None => {
-            if let Ok(RecordingCommand::Stop(sender)) = command_receiver.recv() {
-                let _ = sender.send(Err("No input device available".to_string()));
-            }
-            return;
+/// A running input stream and the mono samples it has captured so far.
+struct Capture {
+    // Held only to keep the stream running; dropping it stops capture
+    _stream: StreamGuard,
+    device_name: String,
+    sample_rate: u32,
+    samples: Arc<Mutex<Vec<f32>>>,
+    dsp: Arc<Mutex<DspChain>>,
+    failed: Arc<AtomicBool>,
+}
+
+impl Capture {
+    /// Whether the device has errored out or is no longer listed.
+    fn is_lost(&self, source: &dyn AudioSource) -> bool {
+        self.failed.load(Ordering::Relaxed) || !source.list_devices().contains(&self.device_name)
+    }
+
+    /// Stop the stream and return its samples converted to `sample_rate`.
+    fn finish(self, sample_rate: u32) -> Vec<f32> {
+        drop(self._stream);
+        let mut samples = std::mem::take(&mut *self.samples.lock());
+        self.dsp.lock().flush(&mut samples);
+        decode::resample(&samples, self.sample_rate, sample_rate)
+    }
+}
+
+/// Input settings shared by recording and preview.
+#[derive(Debug, Clone)]
+pub struct InputSettings {
+    /// Preferred devices, most preferred first.
+    pub preferred_devices: Vec<String>,
+    /// Per-device capture settings keyed by `device_id`.
+    pub device_configs: BTreeMap<String, AudioDeviceConfig>,
+    /// Number of level meter bars.
+    pub meter_bars: usize,
+    /// Filters applied to the mono signal before it is stored or metered.
+    pub processing: AudioProcessingConfig,
+    /// Recordings stop automatically after this long; 0 for no time limit.
+    pub max_recording_secs: u32,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        Self {
+            preferred_devices: Vec::new(),
+            device_configs: BTreeMap::new(),
+            meter_bars: meter::DEFAULT_BAR_COUNT,
+            processing: AudioProcessingConfig::default(),
+            max_recording_secs: DEFAULT_MAX_RECORDING_SECS,
+        }
+    }
+}
+
+impl InputSettings {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            preferred_devices: settings.audio_input_devices.clone(),
+            device_configs: settings.audio_device_configs.clone(),
+            meter_bars: settings.level_meter_bars,
+            processing: settings.audio_processing.clone(),
+            max_recording_secs: settings.max_recording_secs,
         }
+    }
+
+    fn config_for(&self, device_name: &str) -> AudioDeviceConfig {
+        self.device_configs
+            .get(&device_id(device_name))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Mono downmix and gain for one device, built from its `AudioDeviceConfig`.
+struct ChannelMix {
+    weights: Vec<f32>,
+    gain: f32,
+}
+
+impl ChannelMix {
+    fn new(config: &AudioDeviceConfig) -> Self {
+        Self {
+            weights: config.channel_mix.clone(),
+            gain: 10f32.powf(config.gain_db / 20.0),
+        }
+    }
+
+    /// Mix one interleaved frame down to a single sample. Without weights all
+    /// channels are averaged; channels beyond the weights are dropped.
+    fn mono(&self, frame: impl Iterator<Item = f32>) -> f32 {
+        let mut sum = 0.0;
+        let mut count = 0;
+        for (i, sample) in frame.enumerate() {
+            count += 1;
+            sum += if self.weights.is_empty() {
+                sample
+            } else {
+                sample * self.weights.get(i).copied().unwrap_or(0.0)
+            };
+        }
+        let mixed = if self.weights.is_empty() {
+            sum / count.max(1) as f32
+        } else {
+            sum
+        };
+        (mixed * self.gain).clamp(-1.0, 1.0)
+    }
+}
+
+/// Stream config for `device`: its default, with the preferred sample rate
+/// and buffer size from `config` applied where the device supports them.
+fn choose_stream_config(
+    device: &cpal::Device,
+    config: &AudioDeviceConfig,
+) -> Result<(cpal::StreamConfig, cpal::SampleFormat), String> {
+    let default = device
+        .default_input_config()
+        .map_err(|e| format!("Failed to get input config: {}", e))?;
+    let (channels, format) = (default.channels(), default.sample_format());
+
+    let supported = match config.sample_rate {
+        Some(rate) => device
+            .supported_input_configs()
+            .ok()
+            .and_then(|mut ranges| {
+                ranges.find(|r| {
+                    r.channels() == channels
+                        && r.sample_format() == format
+                        && (r.min_sample_rate().0..=r.max_sample_rate().0).contains(&rate)
+                })
+            })
+            .map(|r| r.with_sample_rate(cpal::SampleRate(rate)))
+            .unwrap_or_else(|| {
+                tracing::warn!(
+                    "Sample rate {} Hz not supported by device, using {} Hz",
+                    rate,
+                    default.sample_rate().0
+                );
+                default
+            }),
+        None => default,
     };
 
-    let config = match device.default_input_config() {
-        Ok(c) => c,
-        Err(e) => {
-            if let Ok(RecordingCommand::Stop(sender)) = command_receiver.recv() {
-                let _ = sender.send(Err(format!("Failed to get input config: {}", e)));
-            }
-            return;
+    let buffer_size = match (config.buffer_size, supported.buffer_size()) {
+        (Some(frames), cpal::SupportedBufferSize::Range { min, max }) => {
+            cpal::BufferSize::Fixed(frames.clamp(*min, *max))
         }
+        (Some(frames), cpal::SupportedBufferSize::Unknown) => cpal::BufferSize::Fixed(frames),
+        (None, _) => cpal::BufferSize::Default,
     };
 
-    let sample_rate = config.sample_rate().0;
-    let channels = config.channels();
-    let samples: Arc<Mutex<Vec<f32>>> = Arc::new(Mutex::new(Vec::new()));
+    let sample_format = supported.sample_format();
+    let mut stream_config: cpal::StreamConfig = supported.into();
+    stream_config.buffer_size = buffer_size;
+    Ok((stream_config, sample_format))
+}
 
-    // For computing audio levels - we'll track RMS over recent samples
-    let level_window: Arc<Mutex<Vec<f32>>> = Arc::new(Mutex::new(Vec::new()));
-
-    let err_fn = |err| tracing::error!("Audio stream error: {}", err);
-
-    /// Process mono samples: store for WAV output and track levels for the indicator.
-    fn process_mono_samples(
-        mono: f32,
-        samples: &mut Vec<f32>,
-        level_window: &mut Vec<f32>,
-        audio_levels: &Arc<Mutex<Vec<f32>>>,
-    ) {
-        samples.push(mono);
-        push_level_sample(mono.abs(), level_window, audio_levels);
-    }
-
-    let stream = match config.sample_format() {
-        cpal::SampleFormat::F32 => {
-            let samples_clone = Arc::clone(&samples);
-            let level_window_clone = Arc::clone(&level_window);
-            let audio_levels_clone = Arc::clone(&audio_levels);
-            device.build_input_stream(
-                &config.into(),
-                move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                    let mut s = samples_clone.lock();
-                    let mut lw = level_window_clone.lock();
-                    for chunk in data.chunks(channels as usize) {
-                        let mono = chunk.iter().sum::<f32>() / chunk.len() as f32;
-                        process_mono_samples(mono, &mut s, &mut lw, &audio_levels_clone);
-                    }
-                },
-                err_fn,
-                None,
-            )
+/// Where one callback's worth of frames goes: mixed down to mono, then
+/// either processed or held in a pre-roll buffer.
+pub struct CaptureSink {
+    mix: ChannelMix,
+    mono: Vec<f32>,
+    output: SinkOutput,
+}
+
+enum SinkOutput {
+    Process(Box<Processor>),
+    PreRoll(Arc<Mutex<PreRollBuffer>>),
+}
+
+impl CaptureSink {
+    /// Add one interleaved frame of samples in -1..1.
+    pub fn push_frame(&mut self, frame: impl Iterator<Item = f32>) {
+        self.mono.push(self.mix.mono(frame));
+    }
+
+    /// Process and deliver the frames pushed since the last block.
+    pub fn end_block(&mut self) {
+        match &mut self.output {
+            SinkOutput::Process(processor) => processor.process(&self.mono),
+            SinkOutput::PreRoll(buffer) => buffer.lock().push(&self.mono),
         }
-        cpal::SampleFormat::I16 => {
-            let samples_clone = Arc::clone(&samples);
-            let level_window_clone = Arc::clone(&level_window);
-            let audio_levels_clone = Arc::clone(&audio_levels);
-            device.build_input_stream(
-                &config.into(),
-                move |data: &[i16], _: &cpal::InputCallbackInfo| {
-                    let mut s = samples_clone.lock();
-                    let mut lw = level_window_clone.lock();
-                    for chunk in data.chunks(channels as usize) {
-                        let mono: f32 = chunk
-                            .iter()
-                            .map(|&sample| sample as f32 / i16::MAX as f32)
-                            .sum::<f32>()
-                            / chunk.len() as f32;
-                        process_mono_samples(mono, &mut s, &mut lw, &audio_levels_clone);
-                    }
-                },
-                err_fn,
-                None,
-            )
+        self.mono.clear();
+    }
+}
+
+/// Runs mono audio through the DSP chain, then into the stored samples, the
+/// level meter and the preview monitor.
+struct Processor {
+    dsp: Arc<Mutex<DspChain>>,
+    samples: Option<Arc<Mutex<Vec<f32>>>>,
+    monitor: Option<Arc<Mutex<VecDeque<f32>>>>,
+    monitor_capacity: usize,
+    meter: LevelMeter,
+    processed: Vec<f32>,
+}
+
+impl Processor {
+    fn process(&mut self, mono: &[f32]) {
+        self.processed.clear();
+        self.dsp.lock().process(mono, &mut self.processed);
+
+        if let Some(samples) = &self.samples {
+            samples.lock().extend_from_slice(&self.processed);
         }
-        cpal::SampleFormat::U16 => {
-            let samples_clone = Arc::clone(&samples);
-            let level_window_clone = Arc::clone(&level_window);
-            let audio_levels_clone = Arc::clone(&audio_levels);
-            device.build_input_stream(
-                &config.into(),
-                move |data: &[u16], _: &cpal::InputCallbackInfo| {
-                    let mut s = samples_clone.lock();
-                    let mut lw = level_window_clone.lock();
-                    for chunk in data.chunks(channels as usize) {
-                        let mono: f32 = chunk
-                            .iter()
-                            .map(|&sample| (sample as f32 - 32768.0) / 32768.0)
-                            .sum::<f32>()
-                            / chunk.len() as f32;
-                        process_mono_samples(mono, &mut s, &mut lw, &audio_levels_clone);
-                    }
-                },
-                err_fn,
-                None,
-            )
+        for &sample in &self.processed {
+            self.meter.push(sample);
         }
-        _ => {
-            if let Ok(RecordingCommand::Stop(sender)) = command_receiver.recv() {
-                let _ = sender.send(Err("Unsupported sample format".to_string()));
-            }
-            return;
+        if let Some(monitor) = &self.monitor {
+            let mut queue = monitor.lock();
+            queue.extend(self.processed.iter().copied());
+            // Drop the oldest audio rather than let the monitor lag behind
+            let excess = queue.len().saturating_sub(self.monitor_capacity);
+            queue.drain(..excess);
         }
+    }
+}
+
+/// Convert one device sample to f32 in -1..1. Signed and float formats keep
+/// their zero; unsigned formats are centred on their midpoint.
+fn sample_to_f32<T: cpal::Sample>(sample: T) -> f32
+where
+    f32: cpal::FromSample<T>,
+{
+    sample.to_sample::<f32>()
+}
+
+/// Convert an f32 sample to device format `T`, clamping to full scale.
+fn f32_to_sample<T: cpal::Sample + cpal::FromSample<f32>>(sample: f32) -> T {
+    T::from_sample(sample.clamp(-1.0, 1.0))
+}
+
+/// Build an input stream for sample type `T`, feeding each callback's
+/// frames to `sink`.
+fn build_input_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut sink: CaptureSink,
+    err_fn: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    let channels = config.channels as usize;
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            for frame in data.chunks(channels) {
+                sink.push_frame(frame.iter().map(|&sample| sample_to_f32(sample)));
+            }
+            sink.end_block();
+        },
+        err_fn,
+        None,
+    )
+}
+
+/// Open `device` and start capturing mono samples, feeding the level meter.
+/// Samples are stored when `keep_samples` is set, which the preview does too
+/// so `record_test` can take a test recording from its stream; processed
+/// audio is also queued on `monitor` when given.
+fn open_capture(
+    mut device: Box<dyn InputDevice>,
+    input: &InputSettings,
+    audio_levels: &Arc<Mutex<AudioLevels>>,
+    keep_samples: bool,
+    monitor: Option<Arc<Mutex<VecDeque<f32>>>>,
+) -> Result<Capture, String> {
+    let device_name = device.name();
+    let device_config = input.config_for(&device_name);
+    let format = device.configure(&device_config)?;
+    tracing::info!(
+        "Capturing from '{}' at {} Hz, {} channel(s)",
+        device_name,
+        format.sample_rate,
+        format.channels
+    );
+
+    let sample_rate = format.sample_rate;
+    let samples: Arc<Mutex<Vec<f32>>> = Arc::new(Mutex::new(Vec::new()));
+    let dsp = Arc::new(Mutex::new(DspChain::new(sample_rate, &input.processing)));
+    let failed = Arc::new(AtomicBool::new(false));
+
+    let sink = CaptureSink {
+        mix: ChannelMix::new(&device_config),
+        mono: Vec::new(),
+        output: SinkOutput::Process(Box::new(Processor {
+            dsp: Arc::clone(&dsp),
+            samples: keep_samples.then(|| Arc::clone(&samples)),
+            monitor,
+            monitor_capacity: (sample_rate / 5) as usize,
+            meter: LevelMeter::new(sample_rate, input.meter_bars, Arc::clone(audio_levels)),
+            processed: Vec::new(),
+        })),
     };
+    let stream = device.start(sink, Arc::clone(&failed))?;
 
-    let stream = match stream {
-        Ok(s) => s,
+    Ok(Capture {
+        _stream: stream,
+        device_name,
+        sample_rate,
+        samples,
+        dsp,
+        failed,
+    })
+}
+
+fn run_recording(
+    source: &dyn AudioSource,
+    command_receiver: Receiver<RecordingCommand>,
+    audio_levels: Arc<Mutex<AudioLevels>>,
+    input: &InputSettings,
+    on_event: impl Fn(RecordingEvent),
+) {
+    let preferred = &input.preferred_devices;
+    let capture = source
+        .find_device(preferred, None)
+        .ok_or_else(|| "No input device available".to_string())
+        .and_then(|device| open_capture(device, input, &audio_levels, true, None));
+    let capture = match capture {
+        Ok(c) => c,
         Err(e) => {
             if let Ok(RecordingCommand::Stop(sender)) = command_receiver.recv() {
-                let _ = sender.send(Err(format!("Failed to build stream: {}", e)));
+                let _ = sender.send(Err(e));
             }
             return;
         }
     };
+    on_event(RecordingEvent::Started);
+
+    // Everything is kept at the first device's rate; audio from fallback
+    // devices is resampled to it when their capture ends.
+    let sample_rate = capture.sample_rate;
+    let max_samples = max_recording_samples(input.max_recording_secs, sample_rate);
+    let limit = Duration::from_secs_f64(max_samples as f64 / sample_rate as f64);
+    let started = Instant::now();
+    let mut warned = false;
+    let mut last_device = capture.device_name.clone();
+    let mut devices = vec![last_device.clone()];
+    let mut capture = Some(capture);
+    let mut recorded: Vec<f32> = Vec::new();
 
-    if let Err(e) = stream.play() {
-        if let Ok(RecordingCommand::Stop(sender)) = command_receiver.recv() {
-            let _ = sender.send(Err(format!("Failed to start stream: {}", e)));
+    // Wait for stop command, moving to another device if ours disappears
+    let sender = loop {
+        let until_limit = limit.saturating_sub(started.elapsed());
+        match command_receiver.recv_timeout(DEVICE_CHECK_INTERVAL.min(until_limit)) {
+            Ok(RecordingCommand::Stop(sender)) => break sender,
+            Err(RecvTimeoutError::Disconnected) => return,
+            Err(RecvTimeoutError::Timeout) => {}
         }
-        return;
-    }
 
-    // Wait for stop command
-    if let Ok(RecordingCommand::Stop(sender)) = command_receiver.recv() {
-        // Give a moment for final samples to arrive
-        std::thread::sleep(std::time::Duration::from_millis(100));
+        let elapsed = started.elapsed();
+        on_event(RecordingEvent::Elapsed(RecordingElapsed {
+            seconds: elapsed.as_secs_f64(),
+            estimated_bytes: wav_size((elapsed.as_secs_f64() * sample_rate as f64) as u64),
+            limit_seconds: limit.as_secs_f64(),
+        }));
 
-        // Stop the stream by dropping it
-        drop(stream);
+        if elapsed >= limit {
+            // Stop capturing so a stuck key can't grow the recording; it is
+            // handed over when the stop command arrives
+            if let Some(current) = capture.take() {
+                recorded.extend(current.finish(sample_rate));
+            }
+            tracing::warn!(
+                "Recording reached its {:.0} s limit, stopping",
+                limit.as_secs_f64()
+            );
+            on_event(RecordingEvent::LimitReached);
+            match command_receiver.recv() {
+                Ok(RecordingCommand::Stop(sender)) => break sender,
+                Err(_) => return,
+            }
+        }
+        if !warned && limit - elapsed <= LIMIT_WARNING_LEAD {
+            warned = true;
+            on_event(RecordingEvent::LimitWarning {
+                remaining_secs: (limit - elapsed).as_secs_f64(),
+            });
+        }
 
-        let samples_data = samples.lock();
+        match capture.take() {
+            Some(current) if current.is_lost(source) => {
+                recorded.extend(current.finish(sample_rate));
+                tracing::warn!(
+                    "Audio device '{}' disappeared during recording",
+                    last_device
+                );
 
-        if samples_data.len() < 1000 {
-            let _ = sender.send(Err("Recording too short - hold the key longer".to_string()));
-            return;
+                capture = source
+                    .find_device(preferred, Some(&last_device))
+                    .and_then(|device| {
+                        open_capture(device, input, &audio_levels, true, None)
+                            .map_err(|e| tracing::error!("Failed to open fallback device: {}", e))
+                            .ok()
+                    });
+                on_event(RecordingEvent::DeviceSwitched(DeviceSwitch {
+                    from: last_device.clone(),
+                    to: capture.as_ref().map(|c| c.device_name.clone()),
+                }));
+            }
+            Some(current) => capture = Some(current),
+            // Nothing to record from until a device shows up again
+            None => {
+                capture = source
+                    .find_device(preferred, None)
+                    .and_then(|device| open_capture(device, input, &audio_levels, true, None).ok());
+                if let Some(c) = &capture {
+                    on_event(RecordingEvent::DeviceSwitched(DeviceSwitch {
+                        from: last_device.clone(),
+                        to: Some(c.device_name.clone()),
+                    }));
+                }
+            }
+        }
+        if let Some(c) = capture.as_ref().filter(|c| c.device_name != last_device) {
+            last_device.clone_from(&c.device_name);
+            devices.push(last_device.clone());
         }
+    };
 
-        // Create unique temp file path to prevent symlink attacks and race conditions
-        let temp_dir = std::env::temp_dir();
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_nanos();
-        let pid = std::process::id();
-        let file_path = temp_dir.join(format!("scrivano_{}_{}.wav", timestamp, pid));
-
-        // Write WAV file
-        let spec = WavSpec {
-            channels: 1,
-            sample_rate,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
-        };
+    // Give a moment for final samples to arrive
+    std::thread::sleep(std::time::Duration::from_millis(100));
 
-        let result = (|| -> Result<PathBuf, String> {
-            let mut writer = WavWriter::create(&file_path, spec)
-                .map_err(|e| format!("Failed to create WAV file: {}", e))?;
+    // Stop the stream by dropping it
+    if let Some(current) = capture {
+        recorded.extend(current.finish(sample_rate));
+    }
+    recorded.truncate(max_samples);
 
-            for &sample in samples_data.iter() {
-                let amplitude = (sample * i16::MAX as f32) as i16;
-                writer
-                    .write_sample(amplitude)
-                    .map_err(|e| format!("Failed to write sample: {}", e))?;
+    if recorded.len() < 1000 {
+        let _ = sender.send(Err("Recording too short - hold the key longer".to_string()));
+        return;
+    }
+
+    let result = write_wav(&recorded, sample_rate).map(|path| Recording {
+        path,
+        speech: measure_speech(&recorded, sample_rate),
+        devices,
+    });
+    let _ = sender.send(result);
+}
+
+/// Watch for input devices being added or removed, calling `on_change` with
+/// the new device list. Runs for the lifetime of the process.
+pub fn watch_input_devices(on_change: impl Fn(Vec<String>) + Send + 'static) {
+    thread::spawn(move || {
+        let mut known = list_input_devices();
+        loop {
+            thread::sleep(DEVICE_WATCH_INTERVAL);
+            let current = list_input_devices();
+            if current != known {
+                tracing::info!("Audio input devices changed: {:?}", current);
+                on_change(current.clone());
+                known = current;
             }
+        }
+    });
+}
 
-            writer
-                .finalize()
-                .map_err(|e| format!("Failed to finalize WAV: {}", e))?;
+/// Create a unique temp file path for a recording.
+/// Unique per process and call to prevent symlink attacks and race conditions.
+fn temp_wav_path() -> PathBuf {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let pid = std::process::id();
+    std::env::temp_dir().join(format!("scrivano_{}_{}.wav", timestamp, pid))
+}
 
-            Ok(file_path)
-        })();
+/// Write mono f32 samples to a new 16-bit PCM WAV file in the temp directory.
+pub fn write_wav(samples: &[f32], sample_rate: u32) -> Result<PathBuf, String> {
+    let file_path = temp_wav_path();
 
-        let _ = sender.send(result);
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let mut writer = WavWriter::create(&file_path, spec)
+        .map_err(|e| format!("Failed to create WAV file: {}", e))?;
+
+    for &sample in samples {
+        let amplitude = (sample * i16::MAX as f32) as i16;
+        writer
+            .write_sample(amplitude)
+            .map_err(|e| format!("Failed to write sample: {}", e))?;
     }
+
+    writer
+        .finalize()
+        .map_err(|e| format!("Failed to finalize WAV: {}", e))?;
+
+    Ok(file_path)
+}
+
+/// Length of the microphone test recording.
+pub const MIC_TEST_DURATION: Duration = Duration::from_secs(5);
+
+/// A short recording made during preview to check a microphone setup.
+#[derive(Debug)]
+pub struct MicTest {
+    pub path: PathBuf,
+    pub speech: SpeechStats,
+    /// Speech level over the background noise in dB (see `measure_snr`).
+    pub snr_db: f32,
+}
+
+/// Asks the preview thread to keep the next `duration` of audio.
+struct TestRequest {
+    duration: Duration,
+    reply: Sender<Result<MicTest, String>>,
 }
 
 /// Handle for a running audio preview that monitors input levels.
+#[derive(Clone)]
 pub struct AudioPreviewHandle {
     stop_flag: Arc<AtomicBool>,
+    test_requests: Sender<TestRequest>,
 }
 
 impl AudioPreviewHandle {
     pub fn stop(&self) {
         self.stop_flag.store(true, Ordering::Relaxed);
     }
+
+    /// Record the next `duration` of previewed input, with the same device
+    /// and processing a real recording would use. Blocks until it is done.
+    pub fn record_test(&self, duration: Duration) -> Result<MicTest, String> {
+        let (reply, result) = mpsc::channel();
+        self.test_requests
+            .send(TestRequest { duration, reply })
+            .map_err(|_| "Audio preview is not running".to_string())?;
+        result
+            .recv()
+            .map_err(|_| "Audio preview stopped before the test finished".to_string())?
+    }
 }
 
 /// Start an audio preview that streams level data to the provided callback.
+/// With `monitor` set, the processed input is also played on the default
+/// output device so the effect of the filters can be heard.
 /// Returns a handle that can be used to stop the preview.
 pub fn start_preview(
-    device_name: Option<&str>,
-    audio_levels: Arc<Mutex<Vec<f32>>>,
+    source: Arc<dyn AudioSource>,
+    input: &InputSettings,
+    audio_levels: Arc<Mutex<AudioLevels>>,
+    monitor: bool,
 ) -> Result<AudioPreviewHandle, String> {
     let stop_flag = Arc::new(AtomicBool::new(false));
     let stop_flag_clone = Arc::clone(&stop_flag);
-    let device_name_owned = device_name.map(|s| s.to_string());
+    let input = input.clone();
 
     // cpal Stream is !Send on macOS, so we must create and own it on one thread.
     // Use a channel to report whether setup succeeded before entering the keep-alive loop.
     let (ready_tx, ready_rx) = mpsc::channel::<Result<(), String>>();
+    let (test_requests, test_receiver) = mpsc::channel();
 
     thread::spawn(move || {
         run_preview(
-            device_name_owned.as_deref(),
+            source.as_ref(),
+            &input,
             audio_levels,
+            monitor,
             stop_flag_clone,
+            test_receiver,
             ready_tx,
         );
     });
@@ -332,135 +1089,700 @@ pub fn start_preview(
     ready_rx
         .recv()
         .map_err(|_| "Preview thread failed to start".to_string())?
-        .map(|_| AudioPreviewHandle { stop_flag })
+        .map(|_| AudioPreviewHandle {
+            stop_flag,
+            test_requests,
+        })
 }
 
 fn run_preview(
-    device_name: Option<&str>,
-    audio_levels: Arc<Mutex<Vec<f32>>>,
+    source: &dyn AudioSource,
+    input: &InputSettings,
+    audio_levels: Arc<Mutex<AudioLevels>>,
+    monitor: bool,
     stop_flag: Arc<AtomicBool>,
+    test_requests: Receiver<TestRequest>,
     ready_tx: Sender<Result<(), String>>,
 ) {
-    let device = match find_input_device(device_name) {
-        Some(d) => d,
-        None => {
-            let _ = ready_tx.send(Err("No input device available".to_string()));
-            return;
-        }
-    };
+    let queue = Arc::new(Mutex::new(VecDeque::new()));
 
-    let config = match device.default_input_config() {
+    // Same device selection, per-device mix/gain and processing as recording,
+    // so the preview shows what will actually be captured
+    let capture = source
+        .find_device(&input.preferred_devices, None)
+        .ok_or_else(|| "No input device available".to_string())
+        .and_then(|device| {
+            let monitor_queue = monitor.then(|| Arc::clone(&queue));
+            open_capture(device, input, &audio_levels, true, monitor_queue)
+        });
+    let capture = match capture {
         Ok(c) => c,
         Err(e) => {
-            let _ = ready_tx.send(Err(format!("Failed to get input config: {}", e)));
+            let _ = ready_tx.send(Err(e));
             return;
         }
     };
-
-    let channels = config.channels();
-    let level_window: Arc<Mutex<Vec<f32>>> = Arc::new(Mutex::new(Vec::new()));
-
-    let stream = {
-        let level_window_clone = Arc::clone(&level_window);
-        let audio_levels_clone = Arc::clone(&audio_levels);
-
-        match config.sample_format() {
-            cpal::SampleFormat::F32 => device.build_input_stream(
-                &config.into(),
-                move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                    let mut lw = level_window_clone.lock();
-                    for chunk in data.chunks(channels as usize) {
-                        let mono = chunk.iter().sum::<f32>() / chunk.len() as f32;
-                        push_level_sample(mono.abs(), &mut lw, &audio_levels_clone);
-                    }
-                },
-                |err| eprintln!("Audio preview stream error: {}", err),
-                None,
-            ),
-            cpal::SampleFormat::I16 => {
-                let level_window_clone2 = Arc::clone(&level_window);
-                let audio_levels_clone2 = Arc::clone(&audio_levels);
-                device.build_input_stream(
-                    &config.into(),
-                    move |data: &[i16], _: &cpal::InputCallbackInfo| {
-                        let mut lw = level_window_clone2.lock();
-                        for chunk in data.chunks(channels as usize) {
-                            let mono: f32 = chunk
-                                .iter()
-                                .map(|&s| s as f32 / i16::MAX as f32)
-                                .sum::<f32>()
-                                / chunk.len() as f32;
-                            push_level_sample(mono.abs(), &mut lw, &audio_levels_clone2);
-                        }
-                    },
-                    |err| eprintln!("Audio preview stream error: {}", err),
-                    None,
-                )
-            }
-            _ => {
-                let _ = ready_tx.send(Err("Unsupported sample format".to_string()));
+    let output = if monitor {
+        match open_monitor(capture.sample_rate, queue) {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
                 return;
             }
         }
+    } else {
+        None
     };
 
-    let stream = match stream {
-        Ok(s) => s,
-        Err(e) => {
-            let _ = ready_tx.send(Err(format!("Failed to build preview stream: {}", e)));
-            return;
-        }
-    };
-
-    if let Err(e) = stream.play() {
-        let _ = ready_tx.send(Err(format!("Failed to start preview stream: {}", e)));
-        return;
-    }
-
     // Signal success — stream is running
     let _ = ready_tx.send(Ok(()));
 
-    // Keep the stream alive until stop is signaled
+    // Keep the stream alive until stop is signaled. Captured audio is only
+    // kept while a test recording runs.
+    let mut test: Option<(Instant, TestRequest)> = None;
     while !stop_flag.load(Ordering::Relaxed) {
         thread::sleep(std::time::Duration::from_millis(50));
+        test = match test.take() {
+            None => {
+                capture.samples.lock().clear();
+                test_requests
+                    .try_recv()
+                    .ok()
+                    .map(|request| (Instant::now(), request))
+            }
+            Some((started, request)) if started.elapsed() >= request.duration => {
+                let mut samples = std::mem::take(&mut *capture.samples.lock());
+                samples.truncate(
+                    (request.duration.as_secs_f64() * capture.sample_rate as f64) as usize,
+                );
+                let _ = request
+                    .reply
+                    .send(finish_test(&samples, capture.sample_rate));
+                None
+            }
+            running => running,
+        };
     }
-    drop(stream);
+    drop(output);
+    drop(capture);
 }
 
-/// Compute 3 audio level bars from recent samples
-/// Each bar represents a different frequency-ish band (simulated via sample position)
-fn update_audio_levels(samples: &[f32], audio_levels: &Arc<Mutex<Vec<f32>>>) {
+fn finish_test(samples: &[f32], sample_rate: u32) -> Result<MicTest, String> {
     if samples.is_empty() {
-        return;
+        return Err("No audio was captured during the test".to_string());
     }
+    Ok(MicTest {
+        path: write_wav(samples, sample_rate)?,
+        speech: measure_speech(samples, sample_rate),
+        snr_db: measure_snr(samples, sample_rate),
+    })
+}
 
-    let chunk_size = samples.len() / 3;
-    if chunk_size == 0 {
-        return;
+/// Play an audio file on the default output device, blocking until it ends.
+pub fn play_file(path: &Path) -> Result<(), String> {
+    let audio = decode::decode_file(path)?;
+    let length = Duration::from_secs_f64(audio.samples.len() as f64 / audio.sample_rate as f64);
+    let queue = Arc::new(Mutex::new(VecDeque::from(audio.samples)));
+    let stream = open_monitor(audio.sample_rate, Arc::clone(&queue))?;
+
+    // The monitor holds back its last sample to interpolate towards
+    let deadline = Instant::now() + length + Duration::from_secs(2);
+    while queue.lock().len() > 1 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
     }
+    // Let the output buffer drain before stopping
+    thread::sleep(Duration::from_millis(200));
+    drop(stream);
+    Ok(())
+}
 
-    let mut levels = Vec::with_capacity(3);
+/// Play mono samples queued at `input_rate` on the default output device.
+fn open_monitor(input_rate: u32, queue: Arc<Mutex<VecDeque<f32>>>) -> Result<cpal::Stream, String> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or_else(|| "No output device available for monitoring".to_string())?;
+    let supported = device
+        .default_output_config()
+        .map_err(|e| format!("Failed to get output config: {}", e))?;
+    let config: cpal::StreamConfig = supported.config();
 
-    for i in 0..3 {
-        let start = i * chunk_size;
-        let end = if i == 2 {
-            samples.len()
-        } else {
-            (i + 1) * chunk_size
+    let stream = match supported.sample_format() {
+        cpal::SampleFormat::I8 => build_monitor_stream::<i8>(&device, &config, input_rate, queue),
+        cpal::SampleFormat::I16 => build_monitor_stream::<i16>(&device, &config, input_rate, queue),
+        cpal::SampleFormat::I32 => build_monitor_stream::<i32>(&device, &config, input_rate, queue),
+        cpal::SampleFormat::I64 => build_monitor_stream::<i64>(&device, &config, input_rate, queue),
+        cpal::SampleFormat::U8 => build_monitor_stream::<u8>(&device, &config, input_rate, queue),
+        cpal::SampleFormat::U16 => build_monitor_stream::<u16>(&device, &config, input_rate, queue),
+        cpal::SampleFormat::U32 => build_monitor_stream::<u32>(&device, &config, input_rate, queue),
+        cpal::SampleFormat::U64 => build_monitor_stream::<u64>(&device, &config, input_rate, queue),
+        cpal::SampleFormat::F32 => build_monitor_stream::<f32>(&device, &config, input_rate, queue),
+        cpal::SampleFormat::F64 => build_monitor_stream::<f64>(&device, &config, input_rate, queue),
+        other => return Err(format!("Unsupported output sample format: {}", other)),
+    };
+    let stream = stream.map_err(|e| format!("Failed to build output stream: {}", e))?;
+    stream
+        .play()
+        .map_err(|e| format!("Failed to start output stream: {}", e))?;
+    Ok(stream)
+}
+
+fn build_monitor_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    input_rate: u32,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let step = input_rate as f64 / config.sample_rate.0 as f64;
+    // Fractional read position between queue[0] and queue[1]
+    let mut position = 0.0f64;
+
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let mut queue = queue.lock();
+            for frame in data.chunks_mut(channels) {
+                let sample = if queue.len() >= 2 {
+                    let t = position as f32;
+                    let value = queue[0] * (1.0 - t) + queue[1] * t;
+                    position += step;
+                    let consumed = (position as usize).min(queue.len());
+                    queue.drain(..consumed);
+                    position = position.fract();
+                    value
+                } else {
+                    0.0
+                };
+                frame.fill(f32_to_sample(sample));
+            }
+        },
+        |err| tracing::error!("Monitor stream error: {}", err),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure_speech_silence() {
+        let stats = measure_speech(&vec![0.0; 16000], 16000);
+        assert_eq!(stats.rms, 0.0);
+        assert_eq!(stats.active_ratio, 0.0);
+    }
+
+    #[test]
+    fn test_measure_speech_half_active() {
+        // One second of a -10 dBFS-ish tone followed by one second of silence
+        let mut samples: Vec<f32> = (0..16000)
+            .map(|i| 0.3 * (i as f32 * 440.0 * std::f32::consts::TAU / 16000.0).sin())
+            .collect();
+        samples.extend(std::iter::repeat_n(0.0, 16000));
+
+        let stats = measure_speech(&samples, 16000);
+        assert!((stats.active_ratio - 0.5).abs() < 0.01, "{:?}", stats);
+        assert!((stats.peak - 0.3).abs() < 0.01);
+        assert!(stats.rms > 0.1 && stats.rms < 0.2);
+    }
+
+    #[test]
+    fn test_measure_speech_empty() {
+        assert_eq!(measure_speech(&[], 16000), SpeechStats::default());
+    }
+
+    /// `secs` of a 440 Hz tone at `amplitude`.
+    fn tone(amplitude: f32, secs: f32) -> Vec<f32> {
+        (0..(16000.0 * secs) as usize)
+            .map(|i| amplitude * (i as f32 * 440.0 * std::f32::consts::TAU / 16000.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_measure_snr() {
+        // Words at -9 dBFS RMS between pauses with hiss at -49 dBFS
+        let mut samples = Vec::new();
+        for _ in 0..4 {
+            samples.extend(tone(0.5, 0.3));
+            samples.extend(tone(0.005, 0.2));
+        }
+        let snr = measure_snr(&samples, 16000);
+        assert!((snr - 40.0).abs() < 1.0, "{}", snr);
+
+        // A steady signal has nothing to stand out from
+        assert!(measure_snr(&tone(0.5, 1.0), 16000).abs() < 0.5);
+        // Digital silence between words is capped rather than infinite
+        let mut gated = tone(0.5, 0.5);
+        gated.extend(std::iter::repeat_n(0.0, 8000));
+        assert!((measure_snr(&gated, 16000) - 91.0).abs() < 1.0);
+        assert_eq!(measure_snr(&[], 16000), 0.0);
+    }
+
+    #[test]
+    fn test_channel_mix_average() {
+        let mix = ChannelMix::new(&AudioDeviceConfig::default());
+        assert!((mix.mono([0.2, 0.4].into_iter()) - 0.3).abs() < 1e-6);
+        assert_eq!(mix.mono([0.5].into_iter()), 0.5);
+    }
+
+    #[test]
+    fn test_channel_mix_selects_channel() {
+        let mix = ChannelMix::new(&AudioDeviceConfig {
+            channel_mix: vec![0.0, 1.0],
+            ..AudioDeviceConfig::default()
+        });
+        // Mic on channel 2 only; channel 1 noise is ignored at full level
+        assert_eq!(mix.mono([0.9, 0.25].into_iter()), 0.25);
+        // Extra channels beyond the weights are dropped
+        assert_eq!(mix.mono([0.0, 0.25, 0.9, 0.9].into_iter()), 0.25);
+    }
+
+    #[test]
+    fn test_channel_mix_gain() {
+        let mix = ChannelMix::new(&AudioDeviceConfig {
+            gain_db: 6.0,
+            ..AudioDeviceConfig::default()
+        });
+        assert!((mix.mono([0.25].into_iter()) - 0.5).abs() < 0.01);
+        // Gain never pushes samples past full scale
+        assert_eq!(mix.mono([0.9].into_iter()), 1.0);
+
+        let quieter = ChannelMix::new(&AudioDeviceConfig {
+            gain_db: -20.0,
+            ..AudioDeviceConfig::default()
+        });
+        assert!((quieter.mono([0.5].into_iter()) - 0.05).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_input_settings_config_for_matches_device_id() {
+        let mut input = InputSettings::default();
+        input.device_configs.insert(
+            device_id("Scarlett 2i2 USB"),
+            AudioDeviceConfig {
+                gain_db: 3.0,
+                ..AudioDeviceConfig::default()
+            },
+        );
+        assert_eq!(input.config_for("Scarlett 2i2 (2)").gain_db, 3.0);
+        assert_eq!(input.config_for("Built-in Microphone").gain_db, 0.0);
+    }
+
+    #[test]
+    fn test_device_id() {
+        assert_eq!(device_id("Jabra Evolve 65 (2)"), "jabra evolve 65");
+        assert_eq!(device_id("2- Jabra Evolve 65"), "jabra evolve 65");
+        assert_eq!(device_id("Jabra Evolve 65 USB"), "jabra evolve 65");
+        assert_eq!(
+            device_id("WH-1000XM4 Hands-Free AG Audio"),
+            "wh 1000xm4 hands free ag audio"
+        );
+        assert_ne!(
+            device_id("Scarlett 2i2 Analog Input"),
+            device_id("Scarlett 2i2 Digital Input")
+        );
+        assert_eq!(device_id("Blue Yeti [hw:2,0]"), device_id("Blue Yeti"));
+        assert_eq!(device_id("Microphone #2"), "microphone");
+        assert_eq!(
+            device_id("Microphone (2- Jabra Evolve 65)"),
+            device_id("Microphone (Jabra Evolve 65)")
+        );
+    }
+
+    #[test]
+    fn test_match_preferred_order() {
+        let preferred = vec!["Dock Mic".to_string(), "MacBook Pro Microphone".to_string()];
+        let available = ["MacBook Pro Microphone", "Dock Mic"];
+        assert_eq!(match_preferred(&preferred, &available), Some(1));
+
+        // First preference missing: fall through to the second
+        assert_eq!(
+            match_preferred(&preferred, &["MacBook Pro Microphone"]),
+            Some(0)
+        );
+        assert_eq!(match_preferred(&preferred, &["Other"]), None);
+        assert_eq!(match_preferred(&[], &available), None);
+    }
+
+    #[test]
+    fn test_match_preferred_fuzzy() {
+        let preferred = vec!["Jabra Evolve 65".to_string()];
+        assert_eq!(
+            match_preferred(&preferred, &["Built-in", "Jabra Evolve 65 (2)"]),
+            Some(1)
+        );
+        // Exact names win over fuzzy ones
+        assert_eq!(
+            match_preferred(&preferred, &["Jabra Evolve 65 (2)", "Jabra Evolve 65"]),
+            Some(1)
+        );
+        // A different model number is a different device
+        assert_eq!(match_preferred(&preferred, &["Jabra Evolve 75"]), None);
+    }
+
+    #[test]
+    fn test_match_preferred_keeps_profiles_apart() {
+        let preferred = vec!["WH-1000XM4 Hands-Free AG Audio".to_string()];
+        let available = ["WH-1000XM4 Stereo", "WH-1000XM4 Hands-Free AG Audio (2)"];
+        assert_eq!(match_preferred(&preferred, &available), Some(1));
+        assert_eq!(match_preferred(&preferred, &["WH-1000XM4 Stereo"]), None);
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_sample_to_f32_signed() {
+        assert_close(sample_to_f32(i8::MIN), -1.0);
+        assert_close(sample_to_f32(0i8), 0.0);
+        assert_close(sample_to_f32(i8::MAX), 1.0 - 1.0 / 128.0);
+        assert_close(sample_to_f32(i16::MIN), -1.0);
+        assert_close(sample_to_f32(0i16), 0.0);
+        assert_close(sample_to_f32(i16::MAX), 1.0);
+        assert_close(sample_to_f32(i16::MIN / 2), -0.5);
+        assert_close(sample_to_f32(i32::MIN), -1.0);
+        assert_close(sample_to_f32(i32::MAX / 2), 0.5);
+        assert_close(sample_to_f32(i64::MIN), -1.0);
+        assert_close(sample_to_f32(0i64), 0.0);
+    }
+
+    #[test]
+    fn test_sample_to_f32_unsigned() {
+        assert_close(sample_to_f32(u8::MIN), -1.0);
+        assert_close(sample_to_f32(128u8), 0.0);
+        assert_close(sample_to_f32(u8::MAX), 1.0 - 1.0 / 128.0);
+        assert_close(sample_to_f32(u16::MIN), -1.0);
+        assert_close(sample_to_f32(32768u16), 0.0);
+        assert_close(sample_to_f32(49152u16), 0.5);
+        assert_close(sample_to_f32(u32::MIN), -1.0);
+        assert_close(sample_to_f32(1u32 << 31), 0.0);
+        assert_close(sample_to_f32(1u64 << 63), 0.0);
+        assert_close(sample_to_f32(u64::MAX), 1.0);
+    }
+
+    #[test]
+    fn test_sample_to_f32_float() {
+        assert_eq!(sample_to_f32(0.25f32), 0.25);
+        assert_eq!(sample_to_f32(-0.75f64), -0.75);
+    }
+
+    #[test]
+    fn test_f32_to_sample_round_trips() {
+        for value in [-1.0f32, -0.5, 0.0, 0.25, 0.5] {
+            assert_close(sample_to_f32(f32_to_sample::<i8>(value)), value);
+            assert_close(sample_to_f32(f32_to_sample::<i16>(value)), value);
+            assert_close(sample_to_f32(f32_to_sample::<i32>(value)), value);
+            assert_close(sample_to_f32(f32_to_sample::<u8>(value)), value);
+            assert_close(sample_to_f32(f32_to_sample::<u16>(value)), value);
+            assert_close(sample_to_f32(f32_to_sample::<u32>(value)), value);
+            assert_close(sample_to_f32(f32_to_sample::<f64>(value)), value);
+        }
+    }
+
+    #[test]
+    fn test_f32_to_sample_clamps_overs() {
+        assert_eq!(f32_to_sample::<i16>(1.5), i16::MAX);
+        assert_eq!(f32_to_sample::<i16>(-1.5), i16::MIN);
+        assert_eq!(f32_to_sample::<u8>(2.0), u8::MAX);
+        assert_eq!(f32_to_sample::<f32>(-3.0), -1.0);
+    }
+
+    use signal::{SignalInput, SignalSource};
+
+    const RATE: u32 = 16000;
+
+    fn record(
+        source: SignalSource,
+        input: &InputSettings,
+        duration: Duration,
+    ) -> Result<Recording, String> {
+        let handle = start_recording(Arc::new(source), input, |_| {})?;
+        thread::sleep(duration);
+        handle.stop()
+    }
+
+    fn read_wav(recording: &Recording) -> (u32, Vec<i16>) {
+        let mut reader = hound::WavReader::open(&recording.path).unwrap();
+        let rate = reader.spec().sample_rate;
+        let samples = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+        std::fs::remove_file(&recording.path).unwrap();
+        (rate, samples)
+    }
+
+    #[test]
+    fn test_recording_writes_signal_to_wav() {
+        let source = SignalSource::new(vec![SignalInput::tone("Test Mic", RATE, 440.0, 0.5, 0.5)]);
+        let recording = record(
+            source,
+            &InputSettings::default(),
+            Duration::from_millis(800),
+        )
+        .unwrap();
+        assert_eq!(recording.devices, vec!["Test Mic".to_string()]);
+        assert!(recording.speech.active_ratio > 0.9);
+
+        let (rate, samples) = read_wav(&recording);
+        assert_eq!(rate, RATE);
+        assert_eq!(samples.len(), RATE as usize / 2);
+        let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap();
+        assert!((16000..=16400).contains(&peak), "peak {}", peak);
+    }
+
+    #[test]
+    fn test_recording_replays_wav_file() {
+        let samples: Vec<f32> = (0..RATE / 2)
+            .map(|i| 0.25 * (std::f32::consts::TAU * 440.0 * i as f32 / RATE as f32).sin())
+            .collect();
+        let fixture = write_wav(&samples, RATE).unwrap();
+        let source = SignalSource::new(vec![SignalInput::file("File Mic", &fixture).unwrap()]);
+        std::fs::remove_file(&fixture).unwrap();
+
+        let recording = record(
+            source,
+            &InputSettings::default(),
+            Duration::from_millis(800),
+        )
+        .unwrap();
+        let (_, recorded) = read_wav(&recording);
+        assert_eq!(recorded.len(), samples.len());
+        let peak = recorded.iter().map(|s| s.unsigned_abs()).max().unwrap();
+        assert!((8000..=8300).contains(&peak), "peak {}", peak);
+    }
+
+    #[test]
+    fn test_recording_too_short_is_rejected() {
+        let source = SignalSource::new(vec![SignalInput::tone("Test Mic", RATE, 440.0, 0.5, 0.03)]);
+        let result = record(
+            source,
+            &InputSettings::default(),
+            Duration::from_millis(200),
+        );
+        assert!(result.unwrap_err().contains("too short"));
+    }
+
+    #[test]
+    fn test_stop_keeps_audio_up_to_stop() {
+        let source = SignalSource::new(vec![
+            SignalInput::tone("Test Mic", RATE, 440.0, 0.5, 0.1).looped()
+        ]);
+        let recording = record(
+            source,
+            &InputSettings::default(),
+            Duration::from_millis(400),
+        )
+        .unwrap();
+        let (_, samples) = read_wav(&recording);
+        // Everything up to the stop plus the short drain afterwards
+        let secs = samples.len() as f32 / RATE as f32;
+        assert!((0.35..0.8).contains(&secs), "recorded {} s", secs);
+    }
+
+    #[test]
+    fn test_recording_applies_device_mix() {
+        let source = SignalSource::new(vec![SignalInput::tone(
+            "Stereo Interface",
+            RATE,
+            440.0,
+            0.5,
+            0.5,
+        )
+        .with_channels(2)]);
+        let input = InputSettings {
+            device_configs: BTreeMap::from([(
+                device_id("Stereo Interface"),
+                AudioDeviceConfig {
+                    channel_mix: vec![0.0, 1.0],
+                    gain_db: -6.0,
+                    ..AudioDeviceConfig::default()
+                },
+            )]),
+            ..InputSettings::default()
+        };
+        let recording = record(source, &input, Duration::from_millis(800)).unwrap();
+        let (_, samples) = read_wav(&recording);
+        let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap();
+        // 0.5 at -6 dB is about 0.25 of full scale
+        assert!((8000..=8300).contains(&peak), "peak {}", peak);
+    }
+
+    #[test]
+    fn test_recording_levels_follow_signal() {
+        let source = SignalSource::new(vec![
+            SignalInput::tone("Test Mic", RATE, 440.0, 0.5, 0.1).looped()
+        ]);
+        let handle = start_recording(Arc::new(source), &InputSettings::default(), |_| {}).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let levels = handle.get_audio_levels_arc().lock().clone();
+        // A 0.5 sine: peak -6 dBFS, RMS -9 dBFS
+        assert!((levels.peak_dbfs + 6.0).abs() < 0.5, "{:?}", levels);
+        assert!((levels.rms_dbfs + 9.0).abs() < 0.5, "{:?}", levels);
+        assert!(!levels.clipping);
+        std::fs::remove_file(handle.stop().unwrap().path).unwrap();
+    }
+
+    #[test]
+    fn test_recording_falls_back_when_device_unplugged() {
+        let source = Arc::new(SignalSource::new(vec![
+            SignalInput::tone("USB Mic", RATE, 440.0, 0.5, 0.1).looped(),
+            SignalInput::tone("Built-in", RATE, 440.0, 0.5, 0.1).looped(),
+        ]));
+        let switches = Arc::new(Mutex::new(Vec::new()));
+        let switches_clone = Arc::clone(&switches);
+        let input = InputSettings {
+            preferred_devices: vec!["USB Mic".to_string()],
+            ..InputSettings::default()
         };
-        let chunk = &samples[start..end];
+        let handle = start_recording(source.clone(), &input, move |event| {
+            if let RecordingEvent::DeviceSwitched(switch) = event {
+                switches_clone.lock().push(switch)
+            }
+        })
+        .unwrap();
 
-        // Compute RMS for this chunk
-        let rms: f32 = (chunk.iter().map(|&s| s * s).sum::<f32>() / chunk.len() as f32).sqrt();
+        thread::sleep(Duration::from_millis(300));
+        source.unplug("USB Mic");
+        thread::sleep(DEVICE_CHECK_INTERVAL * 2);
+        let recording = handle.stop().unwrap();
 
-        // Scale to 0-1 range with some amplification for visibility
-        // Normal speech is around 0.01-0.1 RMS, so we amplify
-        let scaled = (rms * 10.0).min(1.0);
+        assert_eq!(recording.devices, vec!["USB Mic", "Built-in"]);
+        let switches = switches.lock();
+        assert_eq!(switches.len(), 1);
+        assert_eq!(switches[0].from, "USB Mic");
+        assert_eq!(switches[0].to.as_deref(), Some("Built-in"));
+        // Audio from both devices is kept
+        let (_, samples) = read_wav(&recording);
+        assert!(samples.len() as f32 / RATE as f32 > 1.0);
+    }
 
-        // Add some minimum height and smoothing
-        let level = 0.15 + scaled * 0.85;
-        levels.push(level);
+    #[test]
+    fn test_max_recording_samples() {
+        assert_eq!(max_recording_samples(60, RATE), 60 * RATE as usize);
+        // 25 MB of 16-bit samples is about 4.5 minutes at 48 kHz
+        let by_size = max_recording_samples(0, 48000);
+        assert!(wav_size(by_size as u64) <= MAX_UPLOAD_BYTES);
+        assert!(wav_size(by_size as u64 + 1) > MAX_UPLOAD_BYTES);
+        assert_eq!(max_recording_samples(600, 48000), by_size);
     }
 
-    *audio_levels.lock() = levels;
+    #[test]
+    fn test_recording_stops_at_limit() {
+        let source = SignalSource::new(vec![SignalInput::tone(
+            "Stuck Key Mic",
+            RATE,
+            440.0,
+            0.5,
+            0.1,
+        )
+        .looped()]);
+        let input = InputSettings {
+            max_recording_secs: 1,
+            ..InputSettings::default()
+        };
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_clone = Arc::clone(&events);
+        let handle = start_recording(Arc::new(source), &input, move |event| {
+            events_clone.lock().push(event)
+        })
+        .unwrap();
+
+        // Well past the limit, as if the key release was lost
+        thread::sleep(Duration::from_millis(1800));
+        let events = events.lock().clone();
+        let position = |matches: fn(&RecordingEvent) -> bool| events.iter().position(matches);
+        assert_eq!(position(|e| matches!(e, RecordingEvent::Started)), Some(0));
+        let warning = position(|e| matches!(e, RecordingEvent::LimitWarning { .. })).unwrap();
+        let reached = position(|e| matches!(e, RecordingEvent::LimitReached)).unwrap();
+        assert!(warning < reached);
+        assert_eq!(
+            reached,
+            events.len() - 1,
+            "events after the limit: {:?}",
+            events
+        );
+
+        let elapsed: Vec<&RecordingElapsed> = events
+            .iter()
+            .filter_map(|e| match e {
+                RecordingEvent::Elapsed(elapsed) => Some(elapsed),
+                _ => None,
+            })
+            .collect();
+        assert!(elapsed.windows(2).all(|w| w[0].seconds <= w[1].seconds));
+        let last = elapsed.last().unwrap();
+        assert_eq!(last.limit_seconds, 1.0);
+        assert!(last.seconds >= 1.0);
+        let expected_bytes = wav_size((last.seconds * RATE as f64) as u64);
+        assert_eq!(last.estimated_bytes, expected_bytes);
+
+        // Capture stopped at the limit, so nothing beyond it was kept
+        let recording = handle.stop().unwrap();
+        let (_, samples) = read_wav(&recording);
+        assert!(samples.len() <= RATE as usize);
+        assert!(samples.len() >= RATE as usize * 9 / 10, "{}", samples.len());
+    }
+
+    #[test]
+    fn test_recording_without_device_fails_on_stop() {
+        let result = record(
+            SignalSource::new(Vec::new()),
+            &InputSettings::default(),
+            Duration::from_millis(50),
+        );
+        assert_eq!(result.unwrap_err(), "No input device available");
+    }
+
+    #[test]
+    fn test_preview_publishes_levels() {
+        let source = SignalSource::new(vec![
+            SignalInput::tone("Test Mic", RATE, 440.0, 0.5, 0.1).looped()
+        ]);
+        let levels = Arc::new(Mutex::new(AudioLevels::idle(3)));
+        let handle = start_preview(
+            Arc::new(source),
+            &InputSettings::default(),
+            Arc::clone(&levels),
+            false,
+        )
+        .unwrap();
+        thread::sleep(Duration::from_millis(300));
+        handle.stop();
+        assert!((levels.lock().peak_dbfs + 6.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_preview_test_recording() {
+        let mut speech = tone(0.5, 0.3);
+        speech.extend(tone(0.005, 0.2));
+        let source = SignalSource::new(vec![SignalInput::new("Test Mic", RATE, speech).looped()]);
+        let handle = start_preview(
+            Arc::new(source),
+            &InputSettings::default(),
+            Arc::new(Mutex::new(AudioLevels::idle(3))),
+            false,
+        )
+        .unwrap();
+        // Audio from before the test is not part of it
+        thread::sleep(Duration::from_millis(300));
+
+        let test = handle.record_test(Duration::from_secs(1)).unwrap();
+        handle.stop();
+        assert!((test.snr_db - 40.0).abs() < 1.5, "{:?}", test);
+        assert!((test.speech.active_ratio - 0.6).abs() < 0.1, "{:?}", test);
+        let mut reader = hound::WavReader::open(&test.path).unwrap();
+        assert_eq!(reader.duration(), RATE);
+        assert_eq!(reader.samples::<i16>().count(), RATE as usize);
+        std::fs::remove_file(&test.path).unwrap();
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(handle.record_test(Duration::from_secs(1)).is_err());
+    }
 }
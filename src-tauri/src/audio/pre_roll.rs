@@ -0,0 +1,423 @@
+//! An always-open input stream that keeps the last few hundred milliseconds
+//! of audio, so a recording can start with what was said just before the
+//! key press instead of losing it to device startup. Opt-in, since the
+//! microphone stays open between recordings.
+
+use super::{
+    AudioSource, CaptureSink, ChannelMix, InputDevice, InputSettings, Processor, SinkOutput,
+    StreamFormat, StreamGuard, DEVICE_CHECK_INTERVAL,
+};
+use crate::settings::AudioDeviceConfig;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Pre-roll length used unless the settings say otherwise.
+pub const DEFAULT_PRE_ROLL_MS: u32 = 400;
+
+/// Mono input from the open stream. Between recordings the most recent
+/// audio is kept in a ring; while a recording uses the stream it goes to
+/// that recording's processor instead.
+pub struct PreRollBuffer {
+    ring: VecDeque<f32>,
+    capacity: usize,
+    recording: Option<(Processor, Arc<AtomicBool>)>,
+}
+
+impl PreRollBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            ring: VecDeque::with_capacity(capacity),
+            capacity,
+            recording: None,
+        }
+    }
+
+    pub fn push(&mut self, mono: &[f32]) {
+        match &mut self.recording {
+            Some((processor, _)) => processor.process(mono),
+            None => {
+                self.ring.extend(mono.iter().copied());
+                let excess = self.ring.len().saturating_sub(self.capacity);
+                self.ring.drain(..excess);
+            }
+        }
+    }
+}
+
+/// The open stream, shared between its thread and recordings that use it.
+struct OpenStream {
+    device_name: String,
+    format: StreamFormat,
+    buffer: Arc<Mutex<PreRollBuffer>>,
+    failed: Arc<AtomicBool>,
+}
+
+/// Handle for a running pre-roll stream.
+pub struct PreRollHandle {
+    stop_flag: Arc<AtomicBool>,
+    stream: Arc<Mutex<Option<OpenStream>>>,
+}
+
+impl PreRollHandle {
+    /// Close the stream, releasing the microphone.
+    pub fn stop(&self) {
+        self.stop_flag.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the microphone is currently held open.
+    pub fn is_open(&self) -> bool {
+        self.stream.lock().is_some()
+    }
+
+    /// Wrap `inner` so that recording from the pre-roll device takes over
+    /// the open stream, starting with the audio buffered before the call.
+    /// Other devices are opened through `inner` as usual.
+    pub fn source(&self, inner: Arc<dyn AudioSource>) -> Arc<dyn AudioSource> {
+        Arc::new(PreRollSource {
+            inner,
+            stream: Arc::clone(&self.stream),
+        })
+    }
+}
+
+/// Keep the preferred input device open on a background thread, holding the
+/// last `length` of its audio. If the device goes away, or another device
+/// becomes preferred, the stream moves to whichever device recording would
+/// use.
+pub fn start_pre_roll(
+    source: Arc<dyn AudioSource>,
+    input: &InputSettings,
+    length: Duration,
+) -> Result<PreRollHandle, String> {
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let stream = Arc::new(Mutex::new(None));
+    let input = input.clone();
+
+    // cpal Stream is !Send on macOS, so it is created and owned by one thread
+    let (ready_tx, ready_rx) = mpsc::channel::<Result<(), String>>();
+    let stop_flag_clone = Arc::clone(&stop_flag);
+    let stream_clone = Arc::clone(&stream);
+    thread::spawn(move || {
+        run_pre_roll(
+            source.as_ref(),
+            &input,
+            length,
+            stream_clone,
+            stop_flag_clone,
+            ready_tx,
+        );
+    });
+
+    ready_rx
+        .recv()
+        .map_err(|_| "Pre-roll thread failed to start".to_string())?
+        .map(|_| PreRollHandle { stop_flag, stream })
+}
+
+fn run_pre_roll(
+    source: &dyn AudioSource,
+    input: &InputSettings,
+    length: Duration,
+    stream: Arc<Mutex<Option<OpenStream>>>,
+    stop_flag: Arc<AtomicBool>,
+    ready_tx: Sender<Result<(), String>>,
+) {
+    let mut guard = match open_stream(source, input, length) {
+        Ok((guard, open)) => {
+            *stream.lock() = Some(open);
+            Some(guard)
+        }
+        Err(e) => {
+            let _ = ready_tx.send(Err(e));
+            return;
+        }
+    };
+    let _ = ready_tx.send(Ok(()));
+
+    let mut last_check = Instant::now();
+    while !stop_flag.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(50));
+
+        // While a recording uses the stream it handles a failed device
+        // itself, falling back to another one; leave the stream to it
+        if let Some(open) = stream.lock().as_ref() {
+            if let Some((_, recording_failed)) = &open.buffer.lock().recording {
+                if open.failed.load(Ordering::Relaxed) {
+                    recording_failed.store(true, Ordering::Relaxed);
+                }
+                continue;
+            }
+        }
+        if last_check.elapsed() < DEVICE_CHECK_INTERVAL {
+            continue;
+        }
+        last_check = Instant::now();
+
+        // Follow the device a recording would use
+        let wanted = source
+            .find_device(&input.preferred_devices, None)
+            .map(|device| device.name());
+        let healthy = stream
+            .lock()
+            .as_ref()
+            .filter(|open| !open.failed.load(Ordering::Relaxed))
+            .map(|open| open.device_name.clone());
+        if wanted == healthy {
+            continue;
+        }
+
+        {
+            let mut current = stream.lock();
+            if current
+                .as_ref()
+                .is_some_and(|open| open.buffer.lock().recording.is_some())
+            {
+                continue;
+            }
+            *current = None;
+        }
+        drop(guard.take());
+        if wanted.is_some() {
+            match open_stream(source, input, length) {
+                Ok((new_guard, open)) => {
+                    *stream.lock() = Some(open);
+                    guard = Some(new_guard);
+                }
+                Err(e) => tracing::error!("Failed to reopen pre-roll input: {}", e),
+            }
+        }
+    }
+
+    *stream.lock() = None;
+    drop(guard);
+}
+
+fn open_stream(
+    source: &dyn AudioSource,
+    input: &InputSettings,
+    length: Duration,
+) -> Result<(StreamGuard, OpenStream), String> {
+    let mut device = source
+        .find_device(&input.preferred_devices, None)
+        .ok_or_else(|| "No input device available".to_string())?;
+    let device_name = device.name();
+    let device_config = input.config_for(&device_name);
+    let format = device.configure(&device_config)?;
+
+    let capacity = (length.as_secs_f64() * format.sample_rate as f64) as usize;
+    let buffer = Arc::new(Mutex::new(PreRollBuffer::new(capacity)));
+    let failed = Arc::new(AtomicBool::new(false));
+    let sink = CaptureSink {
+        mix: ChannelMix::new(&device_config),
+        mono: Vec::new(),
+        output: SinkOutput::PreRoll(Arc::clone(&buffer)),
+    };
+    let guard = device.start(sink, Arc::clone(&failed))?;
+    tracing::info!(
+        "Holding {} ms of pre-roll from '{}'",
+        length.as_millis(),
+        device_name
+    );
+
+    Ok((
+        guard,
+        OpenStream {
+            device_name,
+            format,
+            buffer,
+            failed,
+        },
+    ))
+}
+
+/// An `AudioSource` that hands out the open pre-roll stream in place of its
+/// device.
+struct PreRollSource {
+    inner: Arc<dyn AudioSource>,
+    stream: Arc<Mutex<Option<OpenStream>>>,
+}
+
+impl AudioSource for PreRollSource {
+    fn list_devices(&self) -> Vec<String> {
+        self.inner.list_devices()
+    }
+
+    fn find_device(
+        &self,
+        preferred: &[String],
+        exclude: Option<&str>,
+    ) -> Option<Box<dyn InputDevice>> {
+        let device = self.inner.find_device(preferred, exclude)?;
+        match self.stream.lock().as_ref() {
+            Some(open)
+                if open.device_name == device.name() && !open.failed.load(Ordering::Relaxed) =>
+            {
+                Some(Box::new(PreRollDevice {
+                    name: open.device_name.clone(),
+                    format: open.format,
+                    buffer: Arc::clone(&open.buffer),
+                }))
+            }
+            _ => Some(device),
+        }
+    }
+}
+
+struct PreRollDevice {
+    name: String,
+    format: StreamFormat,
+    buffer: Arc<Mutex<PreRollBuffer>>,
+}
+
+impl InputDevice for PreRollDevice {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// The stream is already running, so it keeps the format (and channel
+    /// mix) it was opened with.
+    fn configure(&mut self, _config: &AudioDeviceConfig) -> Result<StreamFormat, String> {
+        Ok(self.format)
+    }
+
+    fn start(
+        self: Box<Self>,
+        sink: CaptureSink,
+        failed: Arc<AtomicBool>,
+    ) -> Result<StreamGuard, String> {
+        let SinkOutput::Process(mut processor) = sink.output else {
+            return Err("Pre-roll can only feed a capture".to_string());
+        };
+        let mut buffer = self.buffer.lock();
+        if buffer.recording.is_some() {
+            return Err(format!("'{}' is already being recorded", self.name));
+        }
+
+        // Start with the audio from before the key press
+        processor.process(buffer.ring.make_contiguous());
+        buffer.ring.clear();
+        buffer.recording = Some((*processor, failed));
+        drop(buffer);
+
+        Ok(Box::new(Detach {
+            buffer: self.buffer,
+        }))
+    }
+}
+
+/// Hands the stream back to the pre-roll ring when the capture ends.
+struct Detach {
+    buffer: Arc<Mutex<PreRollBuffer>>,
+}
+
+impl Drop for Detach {
+    fn drop(&mut self) {
+        self.buffer.lock().recording = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::signal::{SignalInput, SignalSource};
+    use super::super::{start_recording, Recording};
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn read_samples(recording: &Recording) -> Vec<i16> {
+        let mut reader = hound::WavReader::open(&recording.path).unwrap();
+        let samples = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+        std::fs::remove_file(&recording.path).unwrap();
+        samples
+    }
+
+    #[test]
+    fn test_buffer_keeps_most_recent_audio() {
+        let mut buffer = PreRollBuffer::new(4);
+        buffer.push(&[0.1, 0.2, 0.3]);
+        buffer.push(&[0.4, 0.5, 0.6]);
+        assert_eq!(buffer.ring, [0.3, 0.4, 0.5, 0.6]);
+    }
+
+    #[test]
+    fn test_recording_starts_with_pre_roll() {
+        // Speech that starts before the key press
+        let source: Arc<dyn AudioSource> = Arc::new(SignalSource::new(vec![SignalInput::tone(
+            "Test Mic", RATE, 440.0, 0.5, 1.0,
+        )]));
+        let pre_roll = start_pre_roll(
+            source.clone(),
+            &InputSettings::default(),
+            Duration::from_millis(300),
+        )
+        .unwrap();
+        assert!(pre_roll.is_open());
+        thread::sleep(Duration::from_millis(400));
+
+        let handle =
+            start_recording(pre_roll.source(source), &InputSettings::default(), |_| {}).unwrap();
+        thread::sleep(Duration::from_millis(400));
+        let recording = handle.stop().unwrap();
+        pre_roll.stop();
+
+        let samples = read_samples(&recording);
+        // The 300 ms before the press are kept, and the tone is there from
+        // the first sample rather than after the device starts
+        let secs = samples.len() as f32 / RATE as f32;
+        assert!(secs >= 0.75, "recorded {} s", secs);
+        let first = &samples[..RATE as usize / 10];
+        assert!(first.iter().any(|s| s.unsigned_abs() > 15000));
+    }
+
+    #[test]
+    fn test_other_devices_open_normally() {
+        let source: Arc<dyn AudioSource> = Arc::new(SignalSource::new(vec![
+            SignalInput::tone("Built-in", RATE, 440.0, 0.5, 0.1).looped(),
+            SignalInput::tone("USB Mic", RATE, 440.0, 0.5, 0.1).looped(),
+        ]));
+        let pre_roll = start_pre_roll(
+            source.clone(),
+            &InputSettings::default(),
+            Duration::from_millis(300),
+        )
+        .unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        let input = InputSettings {
+            preferred_devices: vec!["USB Mic".to_string()],
+            ..InputSettings::default()
+        };
+        let handle = start_recording(pre_roll.source(source), &input, |_| {}).unwrap();
+        thread::sleep(Duration::from_millis(400));
+        let recording = handle.stop().unwrap();
+        pre_roll.stop();
+
+        assert_eq!(recording.devices, vec!["USB Mic"]);
+        // No pre-roll: only the audio since the stream was opened
+        let secs = read_samples(&recording).len() as f32 / RATE as f32;
+        assert!(secs < 0.6, "recorded {} s", secs);
+    }
+
+    #[test]
+    fn test_stop_releases_device() {
+        let source: Arc<dyn AudioSource> = Arc::new(SignalSource::new(vec![SignalInput::tone(
+            "Test Mic", RATE, 440.0, 0.5, 0.1,
+        )
+        .looped()]));
+        let pre_roll = start_pre_roll(
+            source,
+            &InputSettings::default(),
+            Duration::from_millis(300),
+        )
+        .unwrap();
+        assert!(pre_roll.is_open());
+        pre_roll.stop();
+        thread::sleep(Duration::from_millis(150));
+        assert!(!pre_roll.is_open());
+    }
+}
@@ -0,0 +1,204 @@
+//! A fake `AudioSource` that replays a WAV file or generated tones at
+//! real-time pace, so the capture engine can be tested without a sound card.
+
+use super::{match_preferred, AudioSource, CaptureSink, InputDevice, StreamFormat, StreamGuard};
+use crate::decode;
+use crate::settings::AudioDeviceConfig;
+use parking_lot::Mutex;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often a replaying device delivers a block, like a driver callback.
+const BLOCK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// One simulated input device. The mono signal is copied to every channel.
+#[derive(Clone)]
+pub struct SignalInput {
+    pub name: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    samples: Arc<Vec<f32>>,
+    looping: bool,
+}
+
+impl SignalInput {
+    pub fn new(name: &str, sample_rate: u32, samples: Vec<f32>) -> Self {
+        Self {
+            name: name.to_string(),
+            sample_rate,
+            channels: 1,
+            samples: Arc::new(samples),
+            looping: false,
+        }
+    }
+
+    /// A sine tone of `amplitude` lasting `secs`.
+    pub fn tone(name: &str, sample_rate: u32, freq: f32, amplitude: f32, secs: f32) -> Self {
+        let len = (sample_rate as f32 * secs) as usize;
+        let samples = (0..len)
+            .map(|i| {
+                amplitude * (std::f32::consts::TAU * freq * i as f32 / sample_rate as f32).sin()
+            })
+            .collect();
+        Self::new(name, sample_rate, samples)
+    }
+
+    /// Replay an audio file (decoded to mono at the transcription rate).
+    pub fn file(name: &str, path: &Path) -> Result<Self, String> {
+        let audio = decode::decode_file(path)?;
+        Ok(Self::new(name, audio.sample_rate, audio.samples))
+    }
+
+    /// Repeat the signal forever instead of going quiet after it ends.
+    pub fn looped(mut self) -> Self {
+        self.looping = true;
+        self
+    }
+
+    pub fn with_channels(mut self, channels: u16) -> Self {
+        self.channels = channels;
+        self
+    }
+}
+
+/// A set of simulated devices; the first is the system default.
+pub struct SignalSource {
+    inputs: Arc<Mutex<Vec<SignalInput>>>,
+}
+
+impl SignalSource {
+    pub fn new(inputs: Vec<SignalInput>) -> Self {
+        Self {
+            inputs: Arc::new(Mutex::new(inputs)),
+        }
+    }
+
+    /// Simulate unplugging `name`: it stops being listed and its stream fails.
+    pub fn unplug(&self, name: &str) {
+        self.inputs.lock().retain(|input| input.name != name);
+    }
+}
+
+impl AudioSource for SignalSource {
+    fn list_devices(&self) -> Vec<String> {
+        self.inputs
+            .lock()
+            .iter()
+            .map(|input| input.name.clone())
+            .collect()
+    }
+
+    fn find_device(
+        &self,
+        preferred: &[String],
+        exclude: Option<&str>,
+    ) -> Option<Box<dyn InputDevice>> {
+        let inputs: Vec<SignalInput> = self
+            .inputs
+            .lock()
+            .iter()
+            .filter(|input| Some(input.name.as_str()) != exclude)
+            .cloned()
+            .collect();
+        let names: Vec<&str> = inputs.iter().map(|input| input.name.as_str()).collect();
+        let index = match_preferred(preferred, &names).unwrap_or(0);
+        let input = inputs.get(index)?.clone();
+        Some(Box::new(SignalDevice {
+            input,
+            connected: Arc::clone(&self.inputs),
+            block_frames: None,
+        }))
+    }
+}
+
+struct SignalDevice {
+    input: SignalInput,
+    connected: Arc<Mutex<Vec<SignalInput>>>,
+    block_frames: Option<usize>,
+}
+
+impl InputDevice for SignalDevice {
+    fn name(&self) -> String {
+        self.input.name.clone()
+    }
+
+    /// The signal's own rate and channels are used; a preferred buffer size
+    /// sets the block length.
+    fn configure(&mut self, config: &AudioDeviceConfig) -> Result<StreamFormat, String> {
+        self.block_frames = config.buffer_size.map(|frames| frames.max(1) as usize);
+        Ok(StreamFormat {
+            sample_rate: self.input.sample_rate,
+            channels: self.input.channels,
+        })
+    }
+
+    fn start(
+        self: Box<Self>,
+        mut sink: CaptureSink,
+        failed: Arc<AtomicBool>,
+    ) -> Result<StreamGuard, String> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = Arc::clone(&stop);
+        let SignalDevice {
+            input,
+            connected,
+            block_frames,
+        } = *self;
+        let block_frames = block_frames
+            .unwrap_or(input.sample_rate as usize / 100)
+            .max(1);
+        let end = if input.looping && !input.samples.is_empty() {
+            usize::MAX
+        } else {
+            input.samples.len()
+        };
+
+        let thread = thread::spawn(move || {
+            let started = Instant::now();
+            let mut position = 0;
+            while !stop_clone.load(Ordering::Relaxed) {
+                if !connected.lock().iter().any(|i| i.name == input.name) {
+                    failed.store(true, Ordering::Relaxed);
+                    break;
+                }
+
+                // Deliver everything that is due by now, in device-sized
+                // blocks. A one-shot signal goes quiet once it has played.
+                let due = (started.elapsed().as_secs_f64() * input.sample_rate as f64) as usize;
+                while position + block_frames <= due && position < end {
+                    let frames = block_frames.min(end - position);
+                    for i in position..position + frames {
+                        let sample = input.samples[i % input.samples.len()];
+                        sink.push_frame(std::iter::repeat_n(sample, input.channels as usize));
+                    }
+                    position += frames;
+                    sink.end_block();
+                }
+                thread::sleep(BLOCK_INTERVAL);
+            }
+        });
+
+        Ok(Box::new(SignalStream {
+            stop,
+            thread: Some(thread),
+        }))
+    }
+}
+
+/// Stops the replay thread when dropped, after its last block is delivered.
+struct SignalStream {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for SignalStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,424 @@
+//! Batch transcription of audio files from disk (e.g. voice memos or meeting
+//! recordings dropped onto the app), using the same provider setup as dictation.
+
+use crate::decode::{self, DecodedAudio};
+use crate::hallucination::{self, HallucinationFilter};
+use crate::transcription::{self, Segment, Timestamps, Transcript};
+use crate::{audio, export};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Jobs transcribed in parallel when the caller doesn't specify a limit.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 2;
+
+/// Long files are uploaded in chunks of this length. Ten minutes of 16 kHz
+/// 16-bit mono WAV is ~19 MB, safely under the providers' 25 MB upload limit.
+const MAX_CHUNK_SECS: usize = 600;
+
+/// Where finished transcripts go. Results are always reported in progress
+/// events; the other variants additionally write a file next to the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchOutput {
+    #[default]
+    Events,
+    Txt,
+    Srt,
+    Vtt,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchJobStatus {
+    Queued,
+    Decoding,
+    Transcribing,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+/// Payload of the `batch-progress` event, one per job status change.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchProgress {
+    pub batch_id: u64,
+    pub index: usize,
+    pub total: usize,
+    pub path: String,
+    pub status: BatchJobStatus,
+    pub text: Option<String>,
+    pub output_path: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Payload of the `batch-complete` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchSummary {
+    pub batch_id: u64,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+}
+
+pub struct BatchConfig {
+    /// Provider id, for usage tracking
+    pub provider: &'static str,
+    pub api_key: String,
+    pub endpoint: &'static str,
+    pub model: &'static str,
+    /// ISO-639-1 code sent with every file
+    pub language: String,
+    pub output: BatchOutput,
+    pub max_concurrency: usize,
+    pub filter: Arc<HallucinationFilter>,
+}
+
+/// What each upload of a file is sent with.
+pub struct UploadJob<'a> {
+    /// Provider id, for usage tracking
+    pub provider: &'a str,
+    pub api_key: &'a str,
+    pub endpoint: &'a str,
+    pub model: &'a str,
+    pub language: &'a str,
+    pub timestamps: Timestamps,
+    pub filter: &'a HallucinationFilter,
+}
+
+impl BatchConfig {
+    fn upload_job(&self) -> UploadJob<'_> {
+        UploadJob {
+            provider: self.provider,
+            api_key: &self.api_key,
+            endpoint: self.endpoint,
+            model: self.model,
+            language: &self.language,
+            timestamps: Timestamps::Segments,
+            filter: &self.filter,
+        }
+    }
+}
+
+pub type ProgressFn = Arc<dyn Fn(BatchProgress) + Send + Sync>;
+
+/// Transcribe `paths`, running at most `config.max_concurrency` jobs at once.
+/// Setting `cancel` stops jobs that haven't started yet; running uploads finish.
+pub async fn run_batch(
+    batch_id: u64,
+    paths: Vec<PathBuf>,
+    config: BatchConfig,
+    cancel: Arc<AtomicBool>,
+    on_progress: ProgressFn,
+) -> BatchSummary {
+    let total = paths.len();
+    let config = Arc::new(config);
+    let semaphore = Arc::new(Semaphore::new(config.max_concurrency.max(1)));
+    let mut jobs = JoinSet::new();
+
+    for (index, path) in paths.into_iter().enumerate() {
+        let report = {
+            let on_progress = Arc::clone(&on_progress);
+            let path = path.display().to_string();
+            move |status, text: Option<String>, output_path: Option<String>, error| {
+                on_progress(BatchProgress {
+                    batch_id,
+                    index,
+                    total,
+                    path: path.clone(),
+                    status,
+                    text,
+                    output_path,
+                    error,
+                })
+            }
+        };
+        report(BatchJobStatus::Queued, None, None, None);
+
+        let semaphore = Arc::clone(&semaphore);
+        let config = Arc::clone(&config);
+        let cancel = Arc::clone(&cancel);
+        jobs.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            if cancel.load(Ordering::Relaxed) {
+                report(BatchJobStatus::Cancelled, None, None, None);
+                return BatchJobStatus::Cancelled;
+            }
+
+            let result = async {
+                let transcript =
+                    transcribe_file(&path, &config, |status| report(status, None, None, None))
+                        .await?;
+                let output_path = write_output(&path, &transcript, config.output)?;
+                Ok::<_, String>((transcript, output_path))
+            }
+            .await;
+
+            match result {
+                Ok((transcript, output_path)) => {
+                    report(
+                        BatchJobStatus::Done,
+                        Some(transcript.text),
+                        output_path.map(|p| p.display().to_string()),
+                        None,
+                    );
+                    BatchJobStatus::Done
+                }
+                Err(e) => {
+                    tracing::warn!("Batch transcription of {} failed: {}", path.display(), e);
+                    report(BatchJobStatus::Failed, None, None, Some(e));
+                    BatchJobStatus::Failed
+                }
+            }
+        });
+    }
+
+    let mut summary = BatchSummary {
+        batch_id,
+        succeeded: 0,
+        failed: 0,
+        cancelled: 0,
+    };
+    while let Some(result) = jobs.join_next().await {
+        match result {
+            Ok(BatchJobStatus::Done) => summary.succeeded += 1,
+            Ok(BatchJobStatus::Cancelled) => summary.cancelled += 1,
+            _ => summary.failed += 1,
+        }
+    }
+    summary
+}
+
+/// Decode a file and transcribe it chunk by chunk.
+async fn transcribe_file(
+    path: &Path,
+    config: &BatchConfig,
+    on_status: impl Fn(BatchJobStatus),
+) -> Result<Transcript, String> {
+    on_status(BatchJobStatus::Decoding);
+    let owned_path = path.to_path_buf();
+    let decoded = tokio::task::spawn_blocking(move || decode::decode_file(&owned_path))
+        .await
+        .map_err(|e| format!("Decoder task failed: {}", e))??;
+
+    on_status(BatchJobStatus::Transcribing);
+    transcribe_decoded(&decoded, &config.upload_job()).await
+}
+
+/// Transcribe decoded audio in chunks small enough to upload, stitching the
+/// chunk transcripts back together on the audio's timeline. Each chunk goes
+/// through the hallucination filter with its measured speech.
+pub async fn transcribe_decoded(
+    decoded: &DecodedAudio,
+    job: &UploadJob<'_>,
+) -> Result<Transcript, String> {
+    let chunk_len = MAX_CHUNK_SECS * decoded.sample_rate as usize;
+    let mut transcript = Transcript {
+        duration: Some(decoded.duration_secs()),
+        ..Transcript::default()
+    };
+
+    for (i, chunk) in decoded.samples.chunks(chunk_len).enumerate() {
+        let wav_path = audio::write_wav(chunk, decoded.sample_rate)?;
+        let result = transcription::transcribe_audio(transcription::TranscriptionRequest {
+            audio_path: &wav_path,
+            provider: job.provider,
+            api_key: job.api_key,
+            endpoint: job.endpoint,
+            model: job.model,
+            language: job.language,
+            timestamps: job.timestamps,
+        })
+        .await;
+        let _ = std::fs::remove_file(&wav_path);
+
+        let start = (i * chunk_len) as f64 / decoded.sample_rate as f64;
+        let speech = audio::measure_speech(chunk, decoded.sample_rate);
+        match result.and_then(|t| job.filter.apply(t, Some(&speech))) {
+            Ok(mut chunk_transcript) => {
+                // Models without verbose_json support still get one cue per chunk
+                if chunk_transcript.segments.is_empty() && !chunk_transcript.text.is_empty() {
+                    chunk_transcript.segments.push(Segment {
+                        start: 0.0,
+                        end: chunk.len() as f64 / decoded.sample_rate as f64,
+                        text: chunk_transcript.text.clone(),
+                        avg_logprob: None,
+                        no_speech_prob: None,
+                        compression_ratio: None,
+                    });
+                }
+                transcript.append(chunk_transcript, start);
+            }
+            // A silent stretch of a long recording is not an error for the file
+            Err(e) if decoded.samples.len() > chunk_len && e == hallucination::NO_SPEECH_ERROR => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(transcript)
+}
+
+/// Write the transcript next to `source` in the requested format.
+/// Existing files are never overwritten; a numbered name is chosen instead.
+fn write_output(
+    source: &Path,
+    transcript: &Transcript,
+    output: BatchOutput,
+) -> Result<Option<PathBuf>, String> {
+    let (extension, content) = match output {
+        BatchOutput::Events => return Ok(None),
+        BatchOutput::Txt => ("txt", format!("{}\n", transcript.text)),
+        BatchOutput::Srt => ("srt", export::to_srt(&transcript.segments)),
+        BatchOutput::Vtt => ("vtt", export::to_vtt(&transcript.segments)),
+        BatchOutput::Json => ("json", export::to_json(transcript)?),
+    };
+
+    let path = unique_sibling_path(source, extension);
+    std::fs::write(&path, content)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(Some(path))
+}
+
+/// `dir/name.ext`, or `dir/name (1).ext`, `dir/name (2).ext`, ... if taken.
+fn unique_sibling_path(source: &Path, extension: &str) -> PathBuf {
+    let stem = source
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("transcript");
+    let candidate = source.with_file_name(format!("{}.{}", stem, extension));
+    if !candidate.exists() {
+        return candidate;
+    }
+    (1..)
+        .map(|n| source.with_file_name(format!("{} ({}).{}", stem, n, extension)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript(text: &str) -> Transcript {
+        Transcript {
+            text: text.to_string(),
+            segments: vec![Segment {
+                start: 0.0,
+                end: 1.0,
+                text: text.to_string(),
+                avg_logprob: None,
+                no_speech_prob: None,
+                compression_ratio: None,
+            }],
+            ..Transcript::default()
+        }
+    }
+
+    #[test]
+    fn test_write_output_does_not_overwrite() {
+        let dir = std::env::temp_dir().join(format!("scrivano_batch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("memo.m4a");
+        let hello = transcript("Hello");
+
+        let first = write_output(&source, &hello, BatchOutput::Txt)
+            .unwrap()
+            .unwrap();
+        let second = write_output(&source, &hello, BatchOutput::Txt)
+            .unwrap()
+            .unwrap();
+        assert_eq!(first, dir.join("memo.txt"));
+        assert_eq!(second, dir.join("memo (1).txt"));
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "Hello\n");
+        assert_eq!(write_output(&source, &hello, BatchOutput::Events), Ok(None));
+
+        let srt = write_output(&source, &hello, BatchOutput::Srt)
+            .unwrap()
+            .unwrap();
+        assert_eq!(srt, dir.join("memo.srt"));
+        assert!(std::fs::read_to_string(&srt)
+            .unwrap()
+            .contains("00:00:00,000 --> 00:00:01,000"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_batch_output_deserialization() {
+        let output: BatchOutput = serde_json::from_str("\"srt\"").unwrap();
+        assert_eq!(output, BatchOutput::Srt);
+    }
+
+    #[tokio::test]
+    async fn test_run_batch_reports_failures_and_cancellation() {
+        let events = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let events_clone = Arc::clone(&events);
+        let config = BatchConfig {
+            provider: "openai",
+            api_key: "test-key".to_string(),
+            endpoint: "https://api.example.com/transcribe",
+            model: "whisper-1",
+            language: "de".to_string(),
+            output: BatchOutput::Events,
+            max_concurrency: 1,
+            filter: Arc::new(HallucinationFilter::default()),
+        };
+
+        let summary = run_batch(
+            7,
+            vec![
+                PathBuf::from("missing_a.wav"),
+                PathBuf::from("missing_b.wav"),
+            ],
+            config,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(move |p| events_clone.lock().push((p.index, p.status))),
+        )
+        .await;
+        assert_eq!(summary.batch_id, 7);
+        assert_eq!(summary.failed, 2);
+        assert_eq!(summary.succeeded, 0);
+
+        {
+            let events = events.lock();
+            for index in 0..2 {
+                let statuses: Vec<_> = events
+                    .iter()
+                    .filter(|(i, _)| *i == index)
+                    .map(|(_, s)| *s)
+                    .collect();
+                assert_eq!(
+                    statuses,
+                    vec![
+                        BatchJobStatus::Queued,
+                        BatchJobStatus::Decoding,
+                        BatchJobStatus::Failed
+                    ]
+                );
+            }
+        }
+
+        let cancelled = run_batch(
+            8,
+            vec![PathBuf::from("missing.wav")],
+            BatchConfig {
+                provider: "openai",
+                api_key: String::new(),
+                endpoint: "",
+                model: "",
+                language: transcription::DEFAULT_LANGUAGE.to_string(),
+                output: BatchOutput::Events,
+                max_concurrency: 1,
+                filter: Arc::new(HallucinationFilter::default()),
+            },
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(|_| {}),
+        )
+        .await;
+        assert_eq!(cancelled.cancelled, 1);
+    }
+}
@@ -0,0 +1,4 @@
+fn main() -> std::process::ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    scrivano_lib::run_cli(&args)
+}
@@ -0,0 +1,510 @@
+//! Headless command-line interface (`scrivano-cli`).
+//!
+//! Transcribes audio files, or raw PCM piped on stdin, using the same
+//! settings file and API keys as the tray app.
+
+use crate::decode::{self, DecodedAudio};
+use crate::hallucination::HallucinationFilter;
+use crate::settings::{self, TranscriptionProvider};
+use crate::transcription::{self, Timestamps, Transcript};
+use crate::{api_keys, batch, export, usage};
+use std::io::Read;
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: scrivano-cli [OPTIONS] <FILE>...
+
+Transcribe audio files (wav, mp3, m4a, mp4, flac, ogg, oga); long files
+are uploaded in ten-minute chunks.
+Pass `-` as FILE to read raw PCM audio from stdin.
+
+Options:
+  --provider <openai|groq>   Override the provider from settings
+  --model <MODEL>            Override the provider's default model
+  --language <CODE>          Language hint (default: en)
+  --format <FORMAT>          text, json, srt or vtt (default: text)
+                             json prints one object per input, with segments;
+                             srt/vtt need a single input
+  --json                     Same as --format json
+  --words                    Add word timestamps to json output
+  --rate <HZ>                Stdin PCM sample rate (default: 16000)
+  --channels <N>             Stdin PCM channel count (default: 1)
+  --pcm-format <s16le|f32le> Stdin PCM sample encoding (default: s16le)
+  -h, --help                 Show this help";
+
+const STDIN_INPUT: &str = "-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PcmEncoding {
+    S16Le,
+    F32Le,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PcmFormat {
+    sample_rate: u32,
+    channels: u16,
+    encoding: PcmEncoding,
+}
+
+impl Default for PcmFormat {
+    fn default() -> Self {
+        Self {
+            sample_rate: 16000,
+            channels: 1,
+            encoding: PcmEncoding::S16Le,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Srt,
+    Vtt,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct CliOptions {
+    inputs: Vec<String>,
+    provider: Option<TranscriptionProvider>,
+    model: Option<String>,
+    language: Option<String>,
+    format: OutputFormat,
+    /// Request word timestamps as well as segments
+    words: bool,
+    pcm: PcmFormat,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Transcribe(CliOptions),
+    Help,
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut options = CliOptions::default();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        let mut value_for = |flag: &str| {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("{} requires a value", flag))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--json" => options.format = OutputFormat::Json,
+            "--words" => options.words = true,
+            "--format" => {
+                let value = value_for("--format")?;
+                options.format = match value.to_lowercase().as_str() {
+                    "text" => OutputFormat::Text,
+                    "json" => OutputFormat::Json,
+                    "srt" => OutputFormat::Srt,
+                    "vtt" => OutputFormat::Vtt,
+                    _ => return Err(format!("Unknown output format: {}", value)),
+                };
+            }
+            "--provider" => {
+                let id = value_for("--provider")?;
+                options.provider = Some(
+                    settings::parse_provider(&id)
+                        .ok_or_else(|| format!("Unknown provider: {}", id))?,
+                );
+            }
+            "--model" => options.model = Some(value_for("--model")?),
+            "--language" => options.language = Some(value_for("--language")?),
+            "--rate" => {
+                let value = value_for("--rate")?;
+                options.pcm.sample_rate = value
+                    .parse()
+                    .ok()
+                    .filter(|&rate| rate > 0)
+                    .ok_or_else(|| format!("Invalid sample rate: {}", value))?;
+            }
+            "--channels" => {
+                let value = value_for("--channels")?;
+                options.pcm.channels = value
+                    .parse()
+                    .ok()
+                    .filter(|&channels| channels > 0)
+                    .ok_or_else(|| format!("Invalid channel count: {}", value))?;
+            }
+            "--pcm-format" => {
+                let value = value_for("--pcm-format")?;
+                options.pcm.encoding = match value.to_lowercase().as_str() {
+                    "s16le" => PcmEncoding::S16Le,
+                    "f32le" => PcmEncoding::F32Le,
+                    _ => return Err(format!("Unknown PCM format: {}", value)),
+                };
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option: {}", flag)),
+            input => options.inputs.push(input.to_string()),
+        }
+    }
+
+    if options.inputs.is_empty() {
+        return Err("No input files given".to_string());
+    }
+    if options.inputs.iter().filter(|i| *i == STDIN_INPUT).count() > 1 {
+        return Err("stdin (`-`) can only be used once".to_string());
+    }
+    if matches!(options.format, OutputFormat::Srt | OutputFormat::Vtt) && options.inputs.len() > 1 {
+        return Err("srt/vtt output supports a single input".to_string());
+    }
+    if options.words && options.format != OutputFormat::Json {
+        return Err("--words needs json output".to_string());
+    }
+
+    Ok(Command::Transcribe(options))
+}
+
+/// Decode interleaved little-endian PCM into mono f32 samples.
+/// Channels are averaged, matching what the recorder does for live input.
+/// A trailing partial frame is ignored.
+fn decode_pcm(bytes: &[u8], format: &PcmFormat) -> Vec<f32> {
+    let samples: Vec<f32> = match format.encoding {
+        PcmEncoding::S16Le => bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32)
+            .collect(),
+        PcmEncoding::F32Le => bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+    };
+
+    samples
+        .chunks_exact(format.channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+/// Read raw PCM from stdin, converted to the rate decoded files have so it
+/// is chunked the same way.
+fn read_stdin(format: &PcmFormat) -> Result<DecodedAudio, String> {
+    let mut bytes = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to read stdin: {}", e))?;
+
+    let samples = decode_pcm(&bytes, format);
+    if samples.is_empty() {
+        return Err("No audio received on stdin".to_string());
+    }
+    Ok(DecodedAudio {
+        samples: decode::resample(&samples, format.sample_rate, decode::TARGET_SAMPLE_RATE),
+        sample_rate: decode::TARGET_SAMPLE_RATE,
+    })
+}
+
+/// Decode a file (or stdin) and transcribe it the way batch transcription
+/// does: in upload-sized chunks, filtered with measured speech.
+async fn transcribe_input(
+    input: &str,
+    job: &batch::UploadJob<'_>,
+    pcm: &PcmFormat,
+) -> Result<Transcript, String> {
+    let decoded = if input == STDIN_INPUT {
+        read_stdin(pcm)?
+    } else {
+        decode::decode_file(Path::new(input))?
+    };
+    batch::transcribe_decoded(&decoded, job).await
+}
+
+/// One line of json output. `words` is empty unless `--words` was given.
+fn json_line(
+    input: &str,
+    provider: &str,
+    model: &str,
+    transcript: &Transcript,
+) -> serde_json::Value {
+    serde_json::json!({
+        "input": input,
+        "provider": provider,
+        "model": model,
+        "text": transcript.text,
+        "language": transcript.language,
+        "duration": transcript.duration,
+        "segments": transcript.segments,
+        "words": transcript.words,
+    })
+}
+
+pub fn run(args: &[String]) -> ExitCode {
+    let options = match parse_args(args) {
+        Ok(Command::Transcribe(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("scrivano-cli: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let saved = settings::load_settings();
+    let provider = options
+        .provider
+        .clone()
+        .unwrap_or_else(|| saved.transcription.provider.clone());
+    let provider_id = settings::get_provider_id(&provider);
+
+    let api_key = match settings::get_api_key_for_provider(&saved, &provider) {
+        Some(key) => key,
+        None => {
+            eprintln!(
+                "scrivano-cli: No API key configured for {}. Set {} or add one in the Scrivano settings.",
+                provider_id,
+                api_keys::env_var_name(provider_id)
+            );
+            return ExitCode::from(2);
+        }
+    };
+
+    match usage::check_budget(&saved.usage) {
+        Ok(None) => {}
+        Ok(Some(warning)) => eprintln!("scrivano-cli: {}", warning),
+        Err(e) => {
+            eprintln!("scrivano-cli: {}", e);
+            return ExitCode::from(2);
+        }
+    }
+
+    let filter = HallucinationFilter::new(&saved.hallucination_phrases);
+    let job = batch::UploadJob {
+        provider: provider_id,
+        api_key: &api_key,
+        endpoint: settings::get_endpoint_for_provider(&provider),
+        model: options
+            .model
+            .as_deref()
+            .unwrap_or(settings::get_model_for_provider(&provider)),
+        language: options
+            .language
+            .as_deref()
+            .unwrap_or(transcription::DEFAULT_LANGUAGE),
+        timestamps: if options.words {
+            Timestamps::Words
+        } else {
+            Timestamps::Segments
+        },
+        filter: &filter,
+    };
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("scrivano-cli: Failed to start async runtime: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let multiple = options.inputs.len() > 1;
+    let mut failed = false;
+
+    for input in &options.inputs {
+        let result = runtime.block_on(transcribe_input(input, &job, &options.pcm));
+
+        let transcript = match result {
+            Ok(transcript) => transcript,
+            Err(e) => {
+                failed = true;
+                eprintln!("scrivano-cli: {}: {}", input, e);
+                if options.format == OutputFormat::Json {
+                    let line = serde_json::json!({
+                        "input": input,
+                        "provider": provider_id,
+                        "model": job.model,
+                        "error": e,
+                    });
+                    println!("{}", line);
+                }
+                continue;
+            }
+        };
+
+        match options.format {
+            OutputFormat::Text if multiple => println!("{}: {}", input, transcript.text),
+            OutputFormat::Text => println!("{}", transcript.text),
+            OutputFormat::Json => {
+                println!("{}", json_line(input, provider_id, job.model, &transcript))
+            }
+            OutputFormat::Srt => print!("{}", export::to_srt(&transcript.segments)),
+            OutputFormat::Vtt => print!("{}", export::to_vtt(&transcript.segments)),
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn options(list: &[&str]) -> CliOptions {
+        match parse_args(&args(list)).unwrap() {
+            Command::Transcribe(options) => options,
+            Command::Help => panic!("expected transcribe command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_args_defaults() {
+        let opts = options(&["meeting.wav"]);
+        assert_eq!(opts.inputs, vec!["meeting.wav"]);
+        assert_eq!(opts.provider, None);
+        assert_eq!(opts.format, OutputFormat::Text);
+        assert!(!opts.words);
+        assert_eq!(opts.pcm, PcmFormat::default());
+    }
+
+    #[test]
+    fn test_parse_args_overrides() {
+        let opts = options(&[
+            "--provider",
+            "groq",
+            "--model",
+            "whisper-large-v3",
+            "--language",
+            "de",
+            "--json",
+            "a.flac",
+            "b.wav",
+        ]);
+        assert_eq!(opts.provider, Some(TranscriptionProvider::Groq));
+        assert_eq!(opts.model.as_deref(), Some("whisper-large-v3"));
+        assert_eq!(opts.language.as_deref(), Some("de"));
+        assert_eq!(opts.format, OutputFormat::Json);
+        assert_eq!(opts.inputs, vec!["a.flac", "b.wav"]);
+    }
+
+    #[test]
+    fn test_parse_args_stdin_pcm() {
+        let opts = options(&[
+            "--rate",
+            "48000",
+            "--channels",
+            "2",
+            "--pcm-format",
+            "f32le",
+            "-",
+        ]);
+        assert_eq!(opts.inputs, vec!["-"]);
+        assert_eq!(
+            opts.pcm,
+            PcmFormat {
+                sample_rate: 48000,
+                channels: 2,
+                encoding: PcmEncoding::F32Le,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert!(parse_args(&args(&[])).is_err());
+        assert!(parse_args(&args(&["--provider", "whisper", "a.wav"])).is_err());
+        assert!(parse_args(&args(&["--model"])).is_err());
+        assert!(parse_args(&args(&["--rate", "0", "-"])).is_err());
+        assert!(parse_args(&args(&["--verbose", "a.wav"])).is_err());
+        assert!(parse_args(&args(&["-", "-"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_subtitle_formats() {
+        assert_eq!(
+            options(&["--format", "srt", "a.wav"]).format,
+            OutputFormat::Srt
+        );
+        assert_eq!(
+            options(&["--format", "VTT", "a.wav"]).format,
+            OutputFormat::Vtt
+        );
+        assert!(parse_args(&args(&["--format", "srt", "a.wav", "b.wav"])).is_err());
+        assert!(parse_args(&args(&["--format", "docx", "a.wav"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_words() {
+        assert!(options(&["--json", "--words", "a.wav"]).words);
+        assert!(parse_args(&args(&["--words", "a.wav"])).is_err());
+        assert!(parse_args(&args(&["--words", "--format", "srt", "a.wav"])).is_err());
+    }
+
+    #[test]
+    fn test_json_line_includes_words() {
+        let transcript = Transcript {
+            text: "hello there".to_string(),
+            words: vec![
+                transcription::Word {
+                    word: "hello".to_string(),
+                    start: 0.0,
+                    end: 0.4,
+                },
+                transcription::Word {
+                    word: "there".to_string(),
+                    start: 0.5,
+                    end: 0.9,
+                },
+            ],
+            ..Transcript::default()
+        };
+        let line = json_line("a.wav", "openai", "whisper-1", &transcript);
+        assert_eq!(line["text"], "hello there");
+        assert_eq!(line["words"][1]["word"], "there");
+        assert_eq!(line["words"][1]["end"], 0.9);
+    }
+
+    #[test]
+    fn test_parse_args_help() {
+        assert_eq!(parse_args(&args(&["a.wav", "--help"])), Ok(Command::Help));
+    }
+
+    #[test]
+    fn test_decode_pcm_s16le_stereo_downmix() {
+        let format = PcmFormat {
+            channels: 2,
+            ..PcmFormat::default()
+        };
+        let mut bytes = Vec::new();
+        for sample in [i16::MAX, 0, -i16::MAX, -i16::MAX] {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        // Trailing odd byte is ignored
+        bytes.push(0x7f);
+
+        let mono = decode_pcm(&bytes, &format);
+        assert_eq!(mono.len(), 2);
+        assert!((mono[0] - 0.5).abs() < 1e-6);
+        assert!((mono[1] + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_decode_pcm_f32le() {
+        let format = PcmFormat {
+            encoding: PcmEncoding::F32Le,
+            ..PcmFormat::default()
+        };
+        let bytes: Vec<u8> = [0.25f32, -0.5]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        assert_eq!(decode_pcm(&bytes, &format), vec![0.25, -0.5]);
+    }
+}
@@ -1,4 +1,4 @@
-//! Cursor position and frontmost app detection for macOS
+//! Cursor position and frontmost app detection for macOS and Linux (X11)
 
 #[cfg(target_os = "macos")]
 mod macos {
@@ -72,12 +72,12 @@ mod macos {
         unsafe {
             let cls = objc_getClass(c"NSApplication".as_ptr());
             if cls.is_null() {
-                eprintln!("[Scrivano] Failed to get NSApplication class for app activation");
+                tracing::warn!("Failed to get NSApplication class for app activation");
                 return;
             }
             let ns_app = objc_msgSend(cls, sel_registerName(c"sharedApplication".as_ptr()));
             if ns_app.is_null() {
-                eprintln!("[Scrivano] Failed to get NSApplication.sharedApplication");
+                tracing::warn!("Failed to get NSApplication.sharedApplication");
                 return;
             }
             // [NSApp activateIgnoringOtherApps:YES]
@@ -140,6 +140,104 @@ mod macos {
 
         Some((location.x as i32, location.y as i32))
     }
+
+    /// Get the main display size in points.
+    pub fn get_screen_size() -> Option<(i32, i32)> {
+        use core_graphics::display::CGDisplay;
+
+        let bounds = CGDisplay::main().bounds();
+        Some((bounds.size.width as i32, bounds.size.height as i32))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, Window};
+    use x11rb::rust_connection::RustConnection;
+
+    fn connect() -> Option<(RustConnection, Window)> {
+        let (conn, screen_num) = x11rb::connect(None).ok()?;
+        let root = conn.setup().roots[screen_num].root;
+        Some((conn, root))
+    }
+
+    fn intern_atom(conn: &RustConnection, name: &str) -> Option<u32> {
+        Some(
+            conn.intern_atom(false, name.as_bytes())
+                .ok()?
+                .reply()
+                .ok()?
+                .atom,
+        )
+    }
+
+    fn get_u32_property(
+        conn: &RustConnection,
+        window: Window,
+        name: &str,
+        kind: AtomEnum,
+    ) -> Option<u32> {
+        let atom = intern_atom(conn, name)?;
+        let reply = conn
+            .get_property(false, window, atom, kind, 0, 1)
+            .ok()?
+            .reply()
+            .ok()?;
+        let value = reply.value32()?.next();
+        value
+    }
+
+    /// Get the active top-level window. Prefers the EWMH `_NET_ACTIVE_WINDOW`
+    /// hint and falls back to the input focus when no window manager sets it.
+    /// Windows owned by this process are ignored so we never paste into ourselves.
+    pub fn get_active_window() -> Option<Window> {
+        let (conn, root) = connect()?;
+
+        let window = get_u32_property(&conn, root, "_NET_ACTIVE_WINDOW", AtomEnum::WINDOW)
+            .filter(|&w| w != 0)
+            .or_else(|| {
+                let focus = conn.get_input_focus().ok()?.reply().ok()?.focus;
+                // 0 = None, 1 = PointerRoot
+                (focus > 1 && focus != root).then_some(focus)
+            })?;
+
+        let pid = get_u32_property(&conn, window, "_NET_WM_PID", AtomEnum::CARDINAL);
+        if pid == Some(std::process::id()) {
+            return None;
+        }
+
+        Some(window)
+    }
+
+    /// Raw `WM_CLASS` of `window`: the instance and class names, each
+    /// followed by a NUL.
+    pub fn get_wm_class(window: Window) -> Option<Vec<u8>> {
+        let (conn, _) = connect()?;
+        let reply = conn
+            .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 64)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some(reply.value)
+    }
+
+    /// Get mouse cursor position relative to the root window.
+    pub fn get_mouse_position() -> Option<(i32, i32)> {
+        let (conn, root) = connect()?;
+        let reply = conn.query_pointer(root).ok()?.reply().ok()?;
+        Some((reply.root_x as i32, reply.root_y as i32))
+    }
+
+    /// Get the root window size in pixels.
+    pub fn get_screen_size() -> Option<(i32, i32)> {
+        let (conn, screen_num) = x11rb::connect(None).ok()?;
+        let screen = &conn.setup().roots[screen_num];
+        Some((
+            screen.width_in_pixels as i32,
+            screen.height_in_pixels as i32,
+        ))
+    }
 }
 
 /// Activate this app so windows can come to the foreground.
@@ -151,33 +249,99 @@ pub fn activate_self() {
 #[cfg(not(target_os = "macos"))]
 pub fn activate_self() {}
 
-/// Get the bundle identifier of the frontmost application via NSWorkspace.
+/// The app in front when recording started.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontmostApp {
+    /// Stable identity for per-app settings: the bundle id on macOS, the
+    /// `WM_CLASS` class on Linux
+    pub app_id: String,
+    /// What `paste::activate_app` brings back: the bundle id on macOS, the
+    /// X11 window id (e.g. `0x3a00007`) on Linux
+    pub target: String,
+}
+
+/// Get the frontmost application via NSWorkspace.
 #[cfg(target_os = "macos")]
-pub fn get_frontmost_bundle_id() -> Option<String> {
-    macos::get_frontmost_bundle_id()
+pub fn get_frontmost_app() -> Option<FrontmostApp> {
+    macos::get_frontmost_bundle_id().map(|bundle_id| FrontmostApp {
+        app_id: bundle_id.clone(),
+        target: bundle_id,
+    })
 }
 
-#[cfg(not(target_os = "macos"))]
-pub fn get_frontmost_bundle_id() -> Option<String> {
+/// Get the frontmost window on X11. Its window id changes whenever the app
+/// reopens it, so the app is identified by `WM_CLASS` instead.
+#[cfg(target_os = "linux")]
+pub fn get_frontmost_app() -> Option<FrontmostApp> {
+    let window = linux::get_active_window()?;
+    let target = format!("0x{:x}", window);
+    let app_id = linux::get_wm_class(window)
+        .and_then(|raw| parse_wm_class(&raw))
+        // Windows without a class can only be told apart by their id
+        .unwrap_or_else(|| target.clone());
+    Some(FrontmostApp { app_id, target })
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn get_frontmost_app() -> Option<FrontmostApp> {
     None
 }
 
+/// The class name from a raw `WM_CLASS` ("instance\0class\0"), or the
+/// instance name when the class is missing.
+#[cfg(target_os = "linux")]
+fn parse_wm_class(raw: &[u8]) -> Option<String> {
+    let mut names = raw
+        .split(|&b| b == 0)
+        .map(|name| String::from_utf8_lossy(name).trim().to_string());
+    let instance = names.next().filter(|n| !n.is_empty());
+    let class = names.next().filter(|n| !n.is_empty());
+    class.or(instance)
+}
+
+/// Parse a window id from `FrontmostApp::target` on Linux.
+#[cfg(target_os = "linux")]
+pub fn parse_window_id(id: &str) -> Option<u32> {
+    u32::from_str_radix(id.strip_prefix("0x")?, 16).ok()
+}
+
 /// Get the current mouse cursor position.
 #[cfg(target_os = "macos")]
 pub fn get_mouse_position() -> Option<(i32, i32)> {
     macos::get_mouse_position()
 }
 
-#[cfg(not(target_os = "macos"))]
+#[cfg(target_os = "linux")]
+pub fn get_mouse_position() -> Option<(i32, i32)> {
+    linux::get_mouse_position()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
 pub fn get_mouse_position() -> Option<(i32, i32)> {
     None
 }
 
+/// Get the size of the main screen, used to keep windows on-screen.
+#[cfg(target_os = "macos")]
+pub fn get_screen_size() -> Option<(i32, i32)> {
+    macos::get_screen_size()
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_screen_size() -> Option<(i32, i32)> {
+    linux::get_screen_size()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn get_screen_size() -> Option<(i32, i32)> {
+    None
+}
+
 /// Prompt for accessibility permission once at startup, only if not already granted.
 #[cfg(target_os = "macos")]
 pub fn prompt_accessibility_once() {
     if macos::is_accessibility_granted() {
-        eprintln!("[Scrivano] Accessibility permission already granted.");
+        tracing::info!("Accessibility permission already granted.");
         return;
     }
     let granted = macos::prompt_accessibility_permission();
@@ -190,3 +354,58 @@ pub fn prompt_accessibility_once() {
 
 #[cfg(not(target_os = "macos"))]
 pub fn prompt_accessibility_once() {}
+
+/// Whether accessibility permission is granted, without prompting.
+/// `None` where the OS has no such permission.
+#[cfg(target_os = "macos")]
+pub fn accessibility_granted() -> Option<bool> {
+    Some(macos::is_accessibility_granted())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn accessibility_granted() -> Option<bool> {
+    None
+}
+
+// The #[ignore] tests need an X server. Run them under Xvfb with:
+//   xvfb-run cargo test -- --ignored --test-threads=1
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_window_id() {
+        assert_eq!(parse_window_id("0x3a00007"), Some(0x3a00007));
+        assert_eq!(parse_window_id("3a00007"), None);
+        assert_eq!(parse_window_id("0xzz"), None);
+    }
+
+    #[test]
+    fn test_parse_wm_class() {
+        assert_eq!(parse_wm_class(b"slack\0Slack\0").as_deref(), Some("Slack"));
+        assert_eq!(parse_wm_class(b"xterm\0").as_deref(), Some("xterm"));
+        assert_eq!(parse_wm_class(b"").as_deref(), None);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_get_mouse_position_on_screen() {
+        let (x, y) = get_mouse_position().expect("X server should report the pointer");
+        let (w, h) = get_screen_size().expect("X server should report the screen size");
+        assert!((0..w).contains(&x), "x={} outside 0..{}", x, w);
+        assert!((0..h).contains(&y), "y={} outside 0..{}", y, h);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_frontmost_target_round_trips() {
+        // Xvfb without a window manager may have no focused window at all
+        if let Some(app) = get_frontmost_app() {
+            assert!(
+                parse_window_id(&app.target).is_some(),
+                "unparseable id {}",
+                app.target
+            );
+        }
+    }
+}
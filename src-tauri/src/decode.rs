@@ -0,0 +1,236 @@
+//! Decoding of audio files (wav, mp3, m4a, flac, ogg) into the mono f32 PCM
+//! the recording pipeline produces, so they can go through the same upload path.
+
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, Track};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Sample rate decoded files are converted to. Whisper resamples to 16 kHz
+/// internally, so anything higher only inflates the upload.
+pub const TARGET_SAMPLE_RATE: u32 = 16000;
+
+/// File extensions `decode_file` understands.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["wav", "mp3", "m4a", "mp4", "flac", "ogg", "oga"];
+
+#[derive(Debug)]
+pub struct DecodedAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+impl DecodedAudio {
+    pub fn duration_secs(&self) -> f64 {
+        self.samples.len() as f64 / self.sample_rate as f64
+    }
+}
+
+/// Whether the file extension is one we can decode.
+pub fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| SUPPORTED_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn open_format(path: &Path) -> Result<Box<dyn FormatReader>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| format!("Unsupported audio format: {}", e))?;
+    Ok(probed.format)
+}
+
+fn audio_track(format: &dyn FormatReader) -> Result<&Track, String> {
+    format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| "No audio track found".to_string())
+}
+
+/// Duration of a file's first audio track in seconds, read from its headers
+/// without decoding. `None` when the container doesn't record it.
+pub fn probe_duration(path: &Path) -> Option<f64> {
+    let format = open_format(path).ok()?;
+    let params = &audio_track(format.as_ref()).ok()?.codec_params;
+    let frames = params.n_frames?;
+    let sample_rate = params.sample_rate.filter(|&rate| rate > 0)?;
+    Some(frames as f64 / sample_rate as f64)
+}
+
+/// Decode the first audio track of a file into mono samples at `TARGET_SAMPLE_RATE`.
+pub fn decode_file(path: &Path) -> Result<DecodedAudio, String> {
+    let mut format = open_format(path)?;
+
+    let track = audio_track(format.as_ref())?;
+    let track_id = track.id;
+    let source_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| "Unknown sample rate".to_string())?;
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| format!("Unsupported codec: {}", e))?;
+
+    let mut mono = Vec::new();
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(e) => return Err(format!("Failed to read audio: {}", e)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Corrupt packets are skipped rather than failing the whole file
+            Err(SymphoniaError::DecodeError(e)) => {
+                tracing::warn!("Skipping undecodable packet in {}: {}", path.display(), e);
+                continue;
+            }
+            Err(e) => return Err(format!("Failed to decode audio: {}", e)),
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let needed = decoded.capacity() * channels;
+        if buffer.as_ref().is_none_or(|b| b.capacity() < needed) {
+            buffer = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+        }
+        let buffer = buffer.as_mut().unwrap();
+        buffer.copy_interleaved_ref(decoded);
+
+        for frame in buffer.samples().chunks(channels) {
+            mono.push(frame.iter().sum::<f32>() / frame.len() as f32);
+        }
+    }
+
+    if mono.is_empty() {
+        return Err("File contains no audio".to_string());
+    }
+
+    Ok(DecodedAudio {
+        samples: resample(&mono, source_rate, TARGET_SAMPLE_RATE),
+        sample_rate: TARGET_SAMPLE_RATE,
+    })
+}
+
+/// Convert between sample rates. Downsampling averages each output window
+/// (a crude low-pass that keeps aliasing out of the speech band); upsampling
+/// interpolates linearly.
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let ratio = from as f64 / to as f64;
+    let out_len = ((samples.len() as f64) / ratio).floor() as usize;
+    let mut out = Vec::with_capacity(out_len);
+
+    for i in 0..out_len {
+        let position = i as f64 * ratio;
+        if ratio > 1.0 {
+            let start = position as usize;
+            let end = (((i + 1) as f64 * ratio) as usize).clamp(start + 1, samples.len());
+            let window = &samples[start..end];
+            out.push(window.iter().sum::<f32>() / window.len() as f32);
+        } else {
+            let index = position as usize;
+            let frac = (position - index as f64) as f32;
+            let current = samples[index];
+            let next = samples.get(index + 1).copied().unwrap_or(current);
+            out.push(current + (next - current) * frac);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{WavSpec, WavWriter};
+
+    fn write_test_wav(path: &Path, sample_rate: u32, channels: u16, frames: usize) {
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(path, spec).unwrap();
+        for i in 0..frames {
+            let value = ((i as f32 / sample_rate as f32) * 440.0 * std::f32::consts::TAU).sin();
+            for _ in 0..channels {
+                writer.write_sample((value * 16000.0) as i16).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_is_supported() {
+        assert!(is_supported(Path::new("memo.M4A")));
+        assert!(is_supported(Path::new("talk.flac")));
+        assert!(!is_supported(Path::new("notes.txt")));
+        assert!(!is_supported(Path::new("no_extension")));
+    }
+
+    #[test]
+    fn test_decode_wav_stereo_48k() {
+        let path = std::env::temp_dir().join(format!("scrivano_decode_{}.wav", std::process::id()));
+        write_test_wav(&path, 48000, 2, 48000);
+
+        let decoded = decode_file(&path).unwrap();
+        let duration = probe_duration(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(decoded.sample_rate, TARGET_SAMPLE_RATE);
+        assert_eq!(decoded.samples.len(), 16000);
+        assert!((decoded.duration_secs() - 1.0).abs() < 1e-6);
+        assert_eq!(duration, Some(1.0));
+        let peak = decoded.samples.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak > 0.4 && peak < 0.6, "peak {}", peak);
+    }
+
+    #[test]
+    fn test_decode_missing_file() {
+        let result = decode_file(Path::new("nonexistent_file.mp3"));
+        assert!(result.unwrap_err().contains("Failed to open file"));
+    }
+
+    #[test]
+    fn test_resample_down_and_up() {
+        let ramp: Vec<f32> = (0..480).map(|i| i as f32).collect();
+        let down = resample(&ramp, 48000, 16000);
+        assert_eq!(down.len(), 160);
+        assert!((down[1] - 4.0).abs() < 1e-6); // mean of 3, 4, 5
+
+        let up = resample(&[0.0, 1.0], 8000, 16000);
+        assert_eq!(up, vec![0.0, 0.5, 1.0, 1.0]);
+    }
+}
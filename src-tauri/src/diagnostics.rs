@@ -0,0 +1,352 @@
+//! Diagnostics bundle for bug reports: a zip of the settings (with key
+//! sources redacted), the input devices, permissions, whether each provider
+//! has a key, recent timings and the latest log files. Never holds a key.
+
+use crate::api_keys::ApiKeySource;
+use crate::audio::InputDeviceDetails;
+use crate::settings::Settings;
+use crate::telemetry::{self, DictationTiming};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Most log data included, newest first; older lines are left out.
+const MAX_LOG_BYTES: u64 = 4 * 1024 * 1024;
+
+const REDACTED: &str = "<redacted>";
+
+/// The machine and app state that goes into `system.json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemReport {
+    pub app_version: String,
+    pub os: String,
+    pub arch: String,
+    /// Accessibility permission (needed to paste); `None` where the OS has none
+    pub accessibility: Option<bool>,
+    /// Keyed by provider id
+    pub api_keys: BTreeMap<String, ProviderKeyStatus>,
+    pub devices: Vec<InputDeviceDetails>,
+    /// Problems found in the settings file
+    pub settings_warnings: Vec<String>,
+}
+
+/// Whether a provider has a key, and where from. Never the key itself.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderKeyStatus {
+    /// Credential in use unless an app overrides it
+    pub selected: String,
+    /// Whether the selected credential has a key
+    pub configured: bool,
+    pub source: Option<ApiKeySource>,
+    /// Each named credential and whether a key is stored for it
+    pub credentials: BTreeMap<String, bool>,
+}
+
+/// Settings as JSON with key files and key commands replaced, since a
+/// command can carry a key inline.
+pub fn redacted_settings(settings: &Settings) -> Result<Value, String> {
+    let mut document = serde_json::to_value(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    let sources = document
+        .get_mut("api_key_sources")
+        .and_then(Value::as_object_mut);
+    for source in sources.into_iter().flat_map(|s| s.values_mut()) {
+        for value in source
+            .as_object_mut()
+            .into_iter()
+            .flat_map(|s| s.values_mut())
+        {
+            if !value.is_null() {
+                *value = Value::from(REDACTED);
+            }
+        }
+    }
+    Ok(document)
+}
+
+/// Where a new bundle is saved: the downloads folder, named by the time.
+pub fn default_bundle_path() -> PathBuf {
+    let now = time::OffsetDateTime::now_utc();
+    let name = format!(
+        "scrivano-diagnostics-{:04}{:02}{:02}-{:02}{:02}{:02}.zip",
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    );
+    dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(name)
+}
+
+/// Write the bundle to `path`, with the log files from `telemetry::log_dir`.
+pub fn write_bundle(
+    path: &Path,
+    report: &SystemReport,
+    settings: &Settings,
+    timings: &[DictationTiming],
+) -> Result<(), String> {
+    write_bundle_with_logs(path, report, settings, timings, &telemetry::log_dir())
+}
+
+fn write_bundle_with_logs(
+    path: &Path,
+    report: &SystemReport,
+    settings: &Settings,
+    timings: &[DictationTiming],
+    log_dir: &Path,
+) -> Result<(), String> {
+    let mut entries = vec![
+        ("system.json".to_string(), to_json(report)?),
+        (
+            "settings.json".to_string(),
+            to_json(&redacted_settings(settings)?)?,
+        ),
+        ("timings.json".to_string(), to_json(&timings)?),
+    ];
+    for (name, content) in recent_logs(log_dir, MAX_LOG_BYTES) {
+        entries.push((format!("logs/{}", name), content));
+    }
+
+    // Written under a temporary name so a failed export leaves no partial zip
+    let tmp_path = path.with_extension("zip.tmp");
+    let result = write_zip(&tmp_path, &entries)
+        .and_then(|()| fs::rename(&tmp_path, path).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        fs::remove_file(&tmp_path).ok();
+        return Err(format!("Failed to write {}: {}", path.display(), e));
+    }
+    Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(value).map_err(|e| format!("Failed to serialize: {}", e))
+}
+
+fn write_zip(path: &Path, entries: &[(String, Vec<u8>)]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in entries {
+        zip.start_file(name.as_str(), options)
+            .map_err(|e| e.to_string())?;
+        zip.write_all(content).map_err(|e| e.to_string())?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The newest log files, up to `max_bytes` in all. The oldest one included
+/// may be cut to its last lines.
+fn recent_logs(dir: &Path, max_bytes: u64) -> Vec<(String, Vec<u8>)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    // Daily files are named by date, so the newest sort last
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with(telemetry::LOG_FILE_PREFIX))
+        })
+        .collect();
+    paths.sort();
+
+    let mut logs = Vec::new();
+    let mut remaining = max_bytes;
+    for path in paths.iter().rev() {
+        if remaining == 0 {
+            break;
+        }
+        let Some(name) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        match read_tail(path, remaining) {
+            Ok(content) => {
+                remaining = remaining.saturating_sub(content.len() as u64);
+                logs.push((name, content));
+            }
+            Err(e) => tracing::warn!("Skipping log file {}: {}", path.display(), e),
+        }
+    }
+    logs
+}
+
+/// The last `max_bytes` of a file, starting at a line boundary when cut.
+fn read_tail(path: &Path, max_bytes: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len <= max_bytes {
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        return Ok(content);
+    }
+    // One byte early, to see whether the cut falls on a line boundary
+    file.seek(SeekFrom::Start(len - max_bytes - 1))?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    let start = content
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(content.len(), |i| i + 1);
+    Ok(content.split_off(start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::ApiKeySourceConfig;
+
+    fn report() -> SystemReport {
+        SystemReport {
+            app_version: "1.0.0".to_string(),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            accessibility: None,
+            api_keys: BTreeMap::from([(
+                "openai".to_string(),
+                ProviderKeyStatus {
+                    selected: "default".to_string(),
+                    configured: true,
+                    source: Some(ApiKeySource::Command),
+                    credentials: BTreeMap::from([("default".to_string(), false)]),
+                },
+            )]),
+            devices: Vec::new(),
+            settings_warnings: Vec::new(),
+        }
+    }
+
+    fn settings_with_key_command() -> Settings {
+        let mut settings = Settings::default();
+        settings.api_key_sources.insert(
+            "openai".to_string(),
+            ApiKeySourceConfig {
+                file: None,
+                command: Some("echo sk-secret".to_string()),
+            },
+        );
+        settings
+    }
+
+    #[test]
+    fn test_key_sources_are_redacted() {
+        let document = redacted_settings(&settings_with_key_command()).unwrap();
+        let source = &document["api_key_sources"]["openai"];
+        assert_eq!(source["command"], REDACTED);
+        assert!(source["file"].is_null());
+        assert!(!document.to_string().contains("sk-secret"));
+    }
+
+    #[test]
+    fn test_bundle_holds_reports_and_newest_logs() {
+        let dir = std::env::temp_dir().join(format!("scrivano_diagnostics_{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        let log_dir = dir.join("logs");
+        fs::create_dir_all(&log_dir).unwrap();
+        fs::write(log_dir.join("scrivano.2026-10-16.log"), "old\n").unwrap();
+        fs::write(log_dir.join("scrivano.2026-10-17.log"), "first\nsecond\n").unwrap();
+        fs::write(log_dir.join("scrivano.2026-10-18.log"), "newest\n").unwrap();
+        fs::write(log_dir.join("notes.txt"), "not a log\n").unwrap();
+
+        let logs = recent_logs(&log_dir, 14);
+        let names: Vec<&str> = logs.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["scrivano.2026-10-18.log", "scrivano.2026-10-17.log"]
+        );
+        // Cut at a line boundary to fit
+        assert_eq!(logs[1].1, b"second\n");
+
+        let path = dir.join("bundle.zip");
+        let settings = settings_with_key_command();
+        write_bundle_with_logs(&path, &report(), &settings, &[], &log_dir).unwrap();
+        assert!(!path.with_extension("zip.tmp").exists());
+
+        let mut zip = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut names: Vec<&str> = zip.file_names().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "logs/scrivano.2026-10-16.log",
+                "logs/scrivano.2026-10-17.log",
+                "logs/scrivano.2026-10-18.log",
+                "settings.json",
+                "system.json",
+                "timings.json",
+            ]
+        );
+        let mut system = String::new();
+        zip.by_name("system.json")
+            .unwrap()
+            .read_to_string(&mut system)
+            .unwrap();
+        assert!(system.contains("\"source\": \"command\""));
+        let mut settings_json = String::new();
+        zip.by_name("settings.json")
+            .unwrap()
+            .read_to_string(&mut settings_json)
+            .unwrap();
+        assert!(!settings_json.contains("sk-secret"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_bundle_never_holds_a_key_command() {
+        let dir = std::env::temp_dir().join(format!(
+            "scrivano_diagnostics_command_{}",
+            std::process::id()
+        ));
+        fs::remove_dir_all(&dir).ok();
+        let log_dir = dir.join("logs");
+        fs::create_dir_all(&log_dir).unwrap();
+
+        // A key command that fails is logged, as the app would log it
+        let command = "echo sk-secret >&2; exit 1";
+        let mut settings = Settings::default();
+        settings.api_key_sources.insert(
+            "scrivano_test".to_string(),
+            ApiKeySourceConfig {
+                file: None,
+                command: Some(command.to_string()),
+            },
+        );
+        let log = File::create(log_dir.join("scrivano.2026-10-18.log")).unwrap();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_writer(std::sync::Mutex::new(log))
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let config = settings.api_key_sources.get("scrivano_test");
+            assert!(crate::api_keys::resolve_external("scrivano_test", config).is_none());
+        });
+
+        let path = dir.join("bundle.zip");
+        write_bundle_with_logs(&path, &report(), &settings, &[], &log_dir).unwrap();
+
+        let mut zip = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut contents = String::new();
+        for i in 0..zip.len() {
+            zip.by_index(i)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+        }
+        assert!(contents.contains("API key command for scrivano_test failed"));
+        assert!(!contents.contains(command));
+        assert!(!contents.contains("sk-secret"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,502 @@
+//! Optional processing between capture and storage: high-pass filter,
+//! spectral-gating noise suppression and automatic gain control, applied in
+//! that order so the AGC never lifts noise the earlier stages removed.
+
+use crate::meter::fft;
+use crate::settings::AudioProcessingConfig;
+
+/// Second-order Butterworth high-pass (RBJ cookbook biquad).
+struct HighPass {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl HighPass {
+    fn new(sample_rate: u32, cutoff_hz: f32) -> Self {
+        let cutoff = cutoff_hz.clamp(10.0, sample_rate as f32 * 0.45);
+        let w0 = std::f32::consts::TAU * cutoff / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * std::f32::consts::FRAC_1_SQRT_2);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: (1.0 + cos) / 2.0 / a0,
+            b1: -(1.0 + cos) / a0,
+            b2: (1.0 + cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Frames the noise estimate is seeded from before gating starts.
+const NOISE_LEARN_FRAMES: usize = 8;
+/// How fast the noise estimate may rise, so speech isn't learned as noise
+/// while a genuinely louder background is still picked up within seconds.
+const NOISE_RISE_DB_PER_SEC: f32 = 6.0;
+/// How much of the noise estimate is subtracted from each bin.
+const OVER_SUBTRACTION: f32 = 2.0;
+/// Lowest per-bin gain (-20 dB); gating harder than this sounds watery.
+const GAIN_FLOOR: f32 = 0.1;
+/// Weight of the previous frame's gain, to soften "musical noise".
+const GAIN_SMOOTHING: f32 = 0.5;
+
+/// Spectral gating: a short-time FFT with 50% overlapping sqrt-Hann windows,
+/// where each bin is attenuated by how close it sits to a tracked noise floor.
+/// Output lags input by one hop; `flush` drains it.
+struct SpectralGate {
+    size: usize,
+    hop: usize,
+    window: Vec<f32>,
+    input: Vec<f32>,
+    overlap: Vec<f32>,
+    noise: Vec<f32>,
+    gains: Vec<f32>,
+    /// Per-frame multiplier limiting how fast the noise estimate rises.
+    noise_rise: f32,
+    frames_seen: usize,
+    /// Leading output samples that are only the zero padding.
+    skip: usize,
+    /// Input samples not yet emitted.
+    pending: usize,
+}
+
+impl SpectralGate {
+    fn new(sample_rate: u32) -> Self {
+        // ~32 ms frames: 512 at 16 kHz, 2048 at 44.1/48 kHz
+        let size = ((sample_rate / 32) as usize).next_power_of_two().max(64);
+        let hop = size / 2;
+        let window = (0..size)
+            .map(|i| {
+                let hann = 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / size as f32).cos();
+                hann.sqrt()
+            })
+            .collect();
+        Self {
+            size,
+            hop,
+            window,
+            input: vec![0.0; size - hop],
+            overlap: vec![0.0; size],
+            noise: vec![0.0; size / 2 + 1],
+            gains: vec![1.0; size / 2 + 1],
+            noise_rise: 10f32.powf(NOISE_RISE_DB_PER_SEC * hop as f32 / sample_rate as f32 / 10.0),
+            frames_seen: 0,
+            skip: size - hop,
+            pending: 0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        for &sample in samples {
+            self.input.push(sample);
+            self.pending += 1;
+            if self.input.len() == self.size {
+                self.process_frame(out);
+            }
+        }
+    }
+
+    fn flush(&mut self, out: &mut Vec<f32>) {
+        // Pad with silence until every input sample has come out
+        while self.pending > 0 {
+            self.input.resize(self.size, 0.0);
+            self.process_frame(out);
+        }
+    }
+
+    fn process_frame(&mut self, out: &mut Vec<f32>) {
+        let n = self.size;
+        let bins = n / 2 + 1;
+        let mut re: Vec<f32> = self
+            .input
+            .iter()
+            .zip(&self.window)
+            .map(|(s, w)| s * w)
+            .collect();
+        let mut im = vec![0.0; n];
+        fft(&mut re, &mut im);
+
+        let power: Vec<f32> = (0..bins).map(|k| re[k] * re[k] + im[k] * im[k]).collect();
+        self.frames_seen += 1;
+        if self.frames_seen <= NOISE_LEARN_FRAMES {
+            // Running mean over the first frames seeds the estimate
+            let weight = 1.0 / self.frames_seen as f32;
+            for (noise, &p) in self.noise.iter_mut().zip(&power) {
+                *noise += (p - *noise) * weight;
+            }
+        } else {
+            // Fall quickly to quieter frames, rise slowly through speech
+            for (noise, &p) in self.noise.iter_mut().zip(&power) {
+                *noise = if p < *noise {
+                    *noise + (p - *noise) * 0.2
+                } else {
+                    // The floor keeps a digitally silent start from pinning it at zero
+                    p.min(noise.max(1e-12) * self.noise_rise)
+                };
+            }
+        }
+
+        for k in 0..bins {
+            let gain = if power[k] > 0.0 {
+                (1.0 - OVER_SUBTRACTION * self.noise[k] / power[k]).max(GAIN_FLOOR)
+            } else {
+                GAIN_FLOOR
+            };
+            let gain = GAIN_SMOOTHING * self.gains[k] + (1.0 - GAIN_SMOOTHING) * gain;
+            self.gains[k] = gain;
+            re[k] *= gain;
+            im[k] *= gain;
+            // Keep the spectrum conjugate-symmetric so the output stays real
+            if k > 0 && k < n / 2 {
+                re[n - k] = re[k];
+                im[n - k] = -im[k];
+            }
+        }
+
+        // Inverse FFT via conjugation: ifft(x) = conj(fft(conj(x))) / n
+        for v in im.iter_mut() {
+            *v = -*v;
+        }
+        fft(&mut re, &mut im);
+        for (i, overlap) in self.overlap.iter_mut().enumerate() {
+            *overlap += re[i] / n as f32 * self.window[i];
+        }
+
+        let ready: Vec<f32> = self.overlap.drain(..self.hop).collect();
+        self.overlap.resize(n, 0.0);
+        self.input.drain(..self.hop);
+
+        let skipped = self.skip.min(ready.len());
+        self.skip -= skipped;
+        let emit = (ready.len() - skipped).min(self.pending);
+        out.extend_from_slice(&ready[skipped..skipped + emit]);
+        self.pending -= emit;
+    }
+}
+
+/// RMS time constant of the AGC level detector.
+const AGC_DETECTOR_SECS: f32 = 0.3;
+/// Gain moves down quickly and up slowly, so words don't pump.
+const AGC_ATTACK_SECS: f32 = 0.05;
+const AGC_RELEASE_SECS: f32 = 0.8;
+/// Range the AGC may apply: up to +24 dB, down to -12 dB.
+const AGC_MAX_GAIN: f32 = 15.85;
+const AGC_MIN_GAIN: f32 = 0.25;
+/// Below this level (dBFS) the input is treated as silence and gain is held.
+const AGC_GATE_DBFS: f32 = -55.0;
+/// Output ceiling; gain is cut instantly so a sample never exceeds it.
+const AGC_CEILING: f32 = 0.95;
+
+fn time_coefficient(secs: f32, sample_rate: u32) -> f32 {
+    1.0 - (-1.0 / (secs * sample_rate as f32)).exp()
+}
+
+struct Agc {
+    target_rms: f32,
+    gate_power: f32,
+    detector: f32,
+    attack: f32,
+    release: f32,
+    power: f32,
+    gain: f32,
+}
+
+impl Agc {
+    fn new(sample_rate: u32, target_dbfs: f32) -> Self {
+        Self {
+            target_rms: 10f32.powf(target_dbfs.min(-3.0) / 20.0),
+            gate_power: 10f32.powf(AGC_GATE_DBFS / 10.0),
+            detector: time_coefficient(AGC_DETECTOR_SECS, sample_rate),
+            attack: time_coefficient(AGC_ATTACK_SECS, sample_rate),
+            release: time_coefficient(AGC_RELEASE_SECS, sample_rate),
+            power: 0.0,
+            gain: 1.0,
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        self.power += (x * x - self.power) * self.detector;
+        if self.power > self.gate_power {
+            let wanted = (self.target_rms / self.power.sqrt()).clamp(AGC_MIN_GAIN, AGC_MAX_GAIN);
+            let rate = if wanted < self.gain {
+                self.attack
+            } else {
+                self.release
+            };
+            self.gain += (wanted - self.gain) * rate;
+        }
+        if (x * self.gain).abs() > AGC_CEILING {
+            self.gain = AGC_CEILING / x.abs();
+        }
+        x * self.gain
+    }
+}
+
+/// The configured processing stages for one capture stream.
+pub struct DspChain {
+    high_pass: Option<HighPass>,
+    gate: Option<SpectralGate>,
+    agc: Option<Agc>,
+    scratch: Vec<f32>,
+}
+
+impl DspChain {
+    pub fn new(sample_rate: u32, config: &AudioProcessingConfig) -> Self {
+        Self {
+            high_pass: config
+                .high_pass
+                .then(|| HighPass::new(sample_rate, config.high_pass_hz)),
+            gate: config
+                .noise_suppression
+                .then(|| SpectralGate::new(sample_rate)),
+            agc: config
+                .agc
+                .then(|| Agc::new(sample_rate, config.agc_target_dbfs)),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Process `samples`, appending the result to `out`. With noise
+    /// suppression on, output trails input by one frame until `flush`.
+    pub fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        self.scratch.clear();
+        self.scratch.extend_from_slice(samples);
+        if let Some(high_pass) = &mut self.high_pass {
+            for sample in &mut self.scratch {
+                *sample = high_pass.process(*sample);
+            }
+        }
+        let start = out.len();
+        match &mut self.gate {
+            Some(gate) => gate.process(&self.scratch, out),
+            None => out.extend_from_slice(&self.scratch),
+        }
+        self.finish(&mut out[start..]);
+    }
+
+    /// Emit whatever is still buffered (end of recording).
+    pub fn flush(&mut self, out: &mut Vec<f32>) {
+        let start = out.len();
+        if let Some(gate) = &mut self.gate {
+            gate.flush(out);
+        }
+        self.finish(&mut out[start..]);
+    }
+
+    fn finish(&mut self, samples: &mut [f32]) {
+        if let Some(agc) = &mut self.agc {
+            for sample in samples {
+                *sample = agc.process(*sample);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    /// Deterministic white noise (xorshift32) in -1..1.
+    fn noise(len: usize, amplitude: f32, seed: u32) -> Vec<f32> {
+        let mut state = seed.max(1);
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                amplitude * (state as f32 / u32::MAX as f32 * 2.0 - 1.0)
+            })
+            .collect()
+    }
+
+    fn tone(len: usize, freq: f32, amplitude: f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| amplitude * (std::f32::consts::TAU * freq * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    fn db(ratio: f32) -> f32 {
+        20.0 * ratio.log10()
+    }
+
+    /// Fan-like recording: steady hiss plus 50 Hz hum throughout, with a
+    /// "voice" tone in the middle second.
+    fn noisy_fixture() -> Vec<f32> {
+        let len = RATE as usize * 3;
+        let hiss = noise(len, 0.02, 7);
+        let hum = tone(len, 50.0, 0.05);
+        let voice = tone(RATE as usize, 440.0, 0.2);
+        (0..len)
+            .map(|i| {
+                let speaking = (RATE as usize..2 * RATE as usize).contains(&i);
+                hiss[i]
+                    + hum[i]
+                    + if speaking {
+                        voice[i - RATE as usize]
+                    } else {
+                        0.0
+                    }
+            })
+            .collect()
+    }
+
+    fn run(config: &AudioProcessingConfig, input: &[f32]) -> Vec<f32> {
+        let mut chain = DspChain::new(RATE, config);
+        let mut out = Vec::new();
+        // Feed in callback-sized pieces, like cpal does
+        for block in input.chunks(160) {
+            chain.process(block, &mut out);
+        }
+        chain.flush(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_disabled_chain_is_identity() {
+        let input = noisy_fixture();
+        assert_eq!(run(&AudioProcessingConfig::default(), &input), input);
+    }
+
+    #[test]
+    fn test_high_pass_removes_hum_keeps_voice() {
+        let config = AudioProcessingConfig {
+            high_pass: true,
+            ..AudioProcessingConfig::default()
+        };
+        let half_second = RATE as usize / 2;
+        let hum = run(&config, &tone(RATE as usize, 50.0, 0.5));
+        let voice = run(&config, &tone(RATE as usize, 1000.0, 0.5));
+
+        assert!(db(rms(&hum[half_second..]) / rms(&tone(half_second, 50.0, 0.5))) < -6.0);
+        let voice_change = db(rms(&voice[half_second..]) / rms(&tone(half_second, 1000.0, 0.5)));
+        assert!(voice_change.abs() < 0.5, "{} dB", voice_change);
+    }
+
+    #[test]
+    fn test_noise_suppression_gates_noise_keeps_voice() {
+        let config = AudioProcessingConfig {
+            noise_suppression: true,
+            ..AudioProcessingConfig::default()
+        };
+        let input = noisy_fixture();
+        let output = run(&config, &input);
+        assert_eq!(output.len(), input.len());
+
+        let second = RATE as usize;
+        // Noise-only tail, after the estimate has settled
+        let tail = 2 * second + second / 4..3 * second - second / 8;
+        let noise_reduction = db(rms(&output[tail.clone()]) / rms(&input[tail]));
+        assert!(
+            noise_reduction < -10.0,
+            "noise only reduced {} dB",
+            noise_reduction
+        );
+
+        let speech = second + second / 4..2 * second - second / 4;
+        let voice_change = db(rms(&output[speech.clone()]) / rms(&tone(second, 440.0, 0.2)));
+        assert!(
+            voice_change.abs() < 3.0,
+            "voice changed {} dB",
+            voice_change
+        );
+    }
+
+    #[test]
+    fn test_noise_suppression_passes_clean_signal() {
+        let config = AudioProcessingConfig {
+            noise_suppression: true,
+            ..AudioProcessingConfig::default()
+        };
+        let mut input = noise(RATE as usize / 2, 0.001, 3);
+        input.extend(tone(RATE as usize, 700.0, 0.3));
+        let output = run(&config, &input);
+
+        let steady = RATE as usize / 2 + RATE as usize / 4..input.len() - 1000;
+        for i in steady.step_by(97) {
+            assert!((output[i] - input[i]).abs() < 0.03, "sample {} differs", i);
+        }
+    }
+
+    #[test]
+    fn test_agc_raises_quiet_speech_to_target() {
+        let config = AudioProcessingConfig {
+            agc: true,
+            agc_target_dbfs: -20.0,
+            ..AudioProcessingConfig::default()
+        };
+        let quiet = tone(RATE as usize * 3, 300.0, 0.01 * std::f32::consts::SQRT_2);
+        let output = run(&config, &quiet);
+        let settled = db(rms(&output[RATE as usize * 2..]));
+        assert!((settled + 20.0).abs() < 1.5, "settled at {} dBFS", settled);
+    }
+
+    #[test]
+    fn test_agc_never_exceeds_ceiling_and_holds_on_silence() {
+        let config = AudioProcessingConfig {
+            agc: true,
+            ..AudioProcessingConfig::default()
+        };
+        let mut input = tone(RATE as usize, 300.0, 0.02);
+        input.extend(tone(RATE as usize / 10, 300.0, 0.9));
+        input.extend(noise(RATE as usize, 0.0005, 9));
+        let output = run(&config, &input);
+
+        assert!(output.iter().all(|s| s.abs() <= AGC_CEILING + 1e-6));
+        // Room tone below the gate is not pumped up to speech level
+        let silence = &output[output.len() - RATE as usize / 2..];
+        assert!(db(rms(silence)) < -30.0, "{} dBFS", db(rms(silence)));
+    }
+
+    #[test]
+    fn test_full_chain_on_noisy_fixture() {
+        let config = AudioProcessingConfig {
+            high_pass: true,
+            noise_suppression: true,
+            agc: true,
+            ..AudioProcessingConfig::default()
+        };
+        let input = noisy_fixture();
+        let output = run(&config, &input);
+        assert_eq!(output.len(), input.len());
+
+        let second = RATE as usize;
+        let voice = rms(&output[second + second / 2..2 * second - second / 8]);
+        let tail = rms(&output[2 * second + second / 2..3 * second - second / 8]);
+        // Voice-to-noise ratio improves well beyond the input's
+        let input_snr = db(0.2 / std::f32::consts::SQRT_2 / rms(&input[..second]));
+        assert!(
+            db(voice / tail) > input_snr + 10.0,
+            "{} vs {}",
+            db(voice / tail),
+            input_snr
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Subtitle (SRT, WebVTT) and JSON exporters for timestamped transcripts.
+
+use crate::transcription::{Segment, Transcript};
+
+/// Format seconds as `HH:MM:SS<sep>mmm` (SRT uses `,`, WebVTT uses `.`).
+fn format_timestamp(secs: f64, separator: char) -> String {
+    let total_ms = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        total_ms / 3_600_000,
+        (total_ms / 60_000) % 60,
+        (total_ms / 1000) % 60,
+        separator,
+        total_ms % 1000
+    )
+}
+
+/// Segments with text, in the form subtitle cues want.
+fn cues(segments: &[Segment]) -> impl Iterator<Item = (f64, f64, &str)> {
+    segments
+        .iter()
+        .map(|s| (s.start, s.end, s.text.trim()))
+        .filter(|(_, _, text)| !text.is_empty())
+}
+
+pub fn to_srt(segments: &[Segment]) -> String {
+    cues(segments)
+        .enumerate()
+        .map(|(i, (start, end, text))| {
+            format!(
+                "{}\n{} --> {}\n{}\n",
+                i + 1,
+                format_timestamp(start, ','),
+                format_timestamp(end, ','),
+                text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn to_vtt(segments: &[Segment]) -> String {
+    let mut out = String::from("WEBVTT\n");
+    for (start, end, text) in cues(segments) {
+        out.push_str(&format!(
+            "\n{} --> {}\n{}\n",
+            format_timestamp(start, '.'),
+            format_timestamp(end, '.'),
+            text
+        ));
+    }
+    out
+}
+
+/// Pretty-printed JSON with text, segments (including `avg_logprob` /
+/// `no_speech_prob`) and, when they were requested, word timestamps.
+pub fn to_json(transcript: &Transcript) -> Result<String, String> {
+    serde_json::to_string_pretty(transcript)
+        .map_err(|e| format!("Failed to serialize transcript: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f64, end: f64, text: &str) -> Segment {
+        Segment {
+            start,
+            end,
+            text: text.to_string(),
+            avg_logprob: Some(-0.2),
+            no_speech_prob: Some(0.01),
+            compression_ratio: None,
+        }
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0.0, ','), "00:00:00,000");
+        assert_eq!(format_timestamp(61.5, ','), "00:01:01,500");
+        assert_eq!(format_timestamp(3725.042, '.'), "01:02:05.042");
+        assert_eq!(format_timestamp(-1.0, ','), "00:00:00,000");
+    }
+
+    #[test]
+    fn test_to_srt() {
+        let srt = to_srt(&[
+            segment(0.0, 1.4, " Hello there."),
+            segment(1.4, 1.5, "  "),
+            segment(1.5, 3.25, " General Kenobi."),
+        ]);
+        assert_eq!(
+            srt,
+            "1\n00:00:00,000 --> 00:00:01,400\nHello there.\n\n\
+             2\n00:00:01,500 --> 00:00:03,250\nGeneral Kenobi.\n"
+        );
+    }
+
+    #[test]
+    fn test_to_vtt() {
+        let vtt = to_vtt(&[segment(0.0, 1.4, " Hello there.")]);
+        assert_eq!(
+            vtt,
+            "WEBVTT\n\n00:00:00.000 --> 00:00:01.400\nHello there.\n"
+        );
+        assert_eq!(to_vtt(&[]), "WEBVTT\n");
+    }
+
+    #[test]
+    fn test_to_json_keeps_confidence_fields() {
+        let transcript = Transcript {
+            text: "Hello there.".to_string(),
+            segments: vec![segment(0.0, 1.4, " Hello there.")],
+            ..Transcript::default()
+        };
+        let json: serde_json::Value = serde_json::from_str(&to_json(&transcript).unwrap()).unwrap();
+        assert_eq!(json["text"], "Hello there.");
+        assert_eq!(json["segments"][0]["avg_logprob"], -0.2);
+        assert_eq!(json["segments"][0]["no_speech_prob"], 0.01);
+    }
+}
@@ -0,0 +1,270 @@
+//! Filtering of Whisper hallucinations.
+//!
+//! Whisper invents text for silence ("Thank you.", "Thanks for watching.").
+//! Three signals are combined, and filtering happens per segment so real
+//! speech around a hallucinated tail is kept:
+//! - per-segment `no_speech_prob` / `avg_logprob` from `verbose_json`
+//! - the speech energy measured on the recording itself
+//! - a phrase list (built-in plus user additions from settings)
+
+use crate::audio::SpeechStats;
+use crate::transcription::{Segment, Transcript};
+
+/// Phrases Whisper produces on silence that are almost never an intentional
+/// single utterance. Matched after normalization (case, trailing punctuation).
+pub const DEFAULT_PHRASES: &[&str] = &["you", "thank you", "thanks for watching", "subscribe"];
+
+/// Whisper's own silence heuristic: a segment is silence when it is both
+/// likely no-speech and decoded with low confidence.
+const NO_SPEECH_PROB: f64 = 0.6;
+const LOW_AVG_LOGPROB: f64 = -1.0;
+
+/// A known phrase is dropped once its no-speech probability exceeds this,
+/// even though that alone wouldn't mark a segment as silence.
+const PHRASE_NO_SPEECH_PROB: f64 = 0.2;
+
+/// Recordings with fewer active frames than this are treated as silent:
+/// whatever Whisper returned for them is invented.
+const SILENT_ACTIVE_RATIO: f32 = 0.02;
+
+/// Below this, the recording is mostly silence and known phrases are dropped
+/// regardless of their confidence.
+const MOSTLY_SILENT_ACTIVE_RATIO: f32 = 0.1;
+
+/// Error for a transcript with nothing real left. Callers that know how the
+/// audio was recorded can add a hint of their own.
+pub const NO_SPEECH_ERROR: &str = "No speech detected";
+
+pub struct HallucinationFilter {
+    phrases: Vec<String>,
+}
+
+impl Default for HallucinationFilter {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+/// Lowercase and strip surrounding whitespace and trailing punctuation.
+fn normalize(text: &str) -> String {
+    text.trim()
+        .trim_end_matches(|c: char| c.is_ascii_punctuation() || c == '…' || c.is_whitespace())
+        .to_lowercase()
+}
+
+impl HallucinationFilter {
+    /// Build a filter from the built-in phrases plus `extra_phrases` from settings.
+    pub fn new(extra_phrases: &[String]) -> Self {
+        let mut phrases: Vec<String> = DEFAULT_PHRASES
+            .iter()
+            .map(|p| p.to_string())
+            .chain(extra_phrases.iter().map(|p| normalize(p)))
+            .filter(|p| !p.is_empty())
+            .collect();
+        phrases.sort();
+        phrases.dedup();
+        Self { phrases }
+    }
+
+    fn is_phrase(&self, text: &str) -> bool {
+        self.phrases.contains(&normalize(text))
+    }
+
+    fn is_hallucinated(&self, segment: &Segment, mostly_silent: bool) -> bool {
+        if segment.text.trim().is_empty() {
+            return true;
+        }
+
+        if let (Some(no_speech), Some(logprob)) = (segment.no_speech_prob, segment.avg_logprob) {
+            if no_speech > NO_SPEECH_PROB && logprob < LOW_AVG_LOGPROB {
+                return true;
+            }
+        }
+
+        // A known phrase is kept only when Whisper is confident it was spoken
+        // and the recording actually contains speech.
+        self.is_phrase(&segment.text)
+            && (mostly_silent
+                || segment
+                    .no_speech_prob
+                    .is_none_or(|p| p > PHRASE_NO_SPEECH_PROB))
+    }
+
+    /// Drop hallucinated segments and rebuild the text from what is left.
+    /// `speech` is the recording's measured energy, when known.
+    /// Returns `NO_SPEECH_ERROR` when nothing real remains.
+    pub fn apply(
+        &self,
+        mut transcript: Transcript,
+        speech: Option<&SpeechStats>,
+    ) -> Result<Transcript, String> {
+        let active_ratio = speech.map(|s| s.active_ratio);
+        if active_ratio.is_some_and(|r| r < SILENT_ACTIVE_RATIO) {
+            if !transcript.text.is_empty() {
+                tracing::info!(
+                    "Filtered transcript of silent recording: {:?}",
+                    transcript.text
+                );
+            }
+            return Err(NO_SPEECH_ERROR.to_string());
+        }
+        let mostly_silent = active_ratio.is_some_and(|r| r < MOSTLY_SILENT_ACTIVE_RATIO);
+
+        // Plain `json` responses have no segments: judge the text as a whole
+        if transcript.segments.is_empty() {
+            let whole = Segment {
+                start: 0.0,
+                end: transcript.duration.unwrap_or(0.0),
+                text: transcript.text.clone(),
+                avg_logprob: None,
+                no_speech_prob: None,
+                compression_ratio: None,
+            };
+            if self.is_hallucinated(&whole, mostly_silent) {
+                tracing::info!(
+                    "Filtered likely Whisper hallucination: {:?}",
+                    transcript.text
+                );
+                return Err(NO_SPEECH_ERROR.to_string());
+            }
+            return Ok(transcript);
+        }
+
+        let (kept, dropped): (Vec<Segment>, Vec<Segment>) = transcript
+            .segments
+            .into_iter()
+            .partition(|segment| !self.is_hallucinated(segment, mostly_silent));
+
+        for segment in &dropped {
+            tracing::info!(
+                "Filtered likely Whisper hallucination: {:?} (no_speech_prob={:?}, avg_logprob={:?})",
+                segment.text,
+                segment.no_speech_prob,
+                segment.avg_logprob
+            );
+        }
+
+        if kept.is_empty() {
+            return Err(NO_SPEECH_ERROR.to_string());
+        }
+
+        if !dropped.is_empty() {
+            transcript.text = kept
+                .iter()
+                .map(|s| s.text.trim())
+                .filter(|t| !t.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            transcript
+                .words
+                .retain(|w| kept.iter().any(|s| w.start >= s.start && w.end <= s.end));
+        }
+        transcript.segments = kept;
+
+        Ok(transcript)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcription::parse_response;
+
+    fn fixture(name: &str) -> Transcript {
+        let body = match name {
+            "verbose_silence" => include_str!("../tests/fixtures/whisper/verbose_silence.json"),
+            "verbose_trailing_hallucination" => {
+                include_str!("../tests/fixtures/whisper/verbose_trailing_hallucination.json")
+            }
+            "verbose_confident_thanks" => {
+                include_str!("../tests/fixtures/whisper/verbose_confident_thanks.json")
+            }
+            "json_plain_you" => include_str!("../tests/fixtures/whisper/json_plain_you.json"),
+            _ => panic!("unknown fixture {}", name),
+        };
+        parse_response(body).unwrap()
+    }
+
+    fn speech(active_ratio: f32) -> SpeechStats {
+        SpeechStats {
+            rms: 0.05,
+            peak: 0.5,
+            active_ratio,
+        }
+    }
+
+    #[test]
+    fn test_low_confidence_silence_is_rejected() {
+        let filter = HallucinationFilter::default();
+        let result = filter.apply(fixture("verbose_silence"), Some(&speech(0.4)));
+        assert_eq!(result.unwrap_err(), NO_SPEECH_ERROR);
+    }
+
+    #[test]
+    fn test_trailing_phrase_dropped_per_segment() {
+        let filter = HallucinationFilter::default();
+        let transcript = filter
+            .apply(
+                fixture("verbose_trailing_hallucination"),
+                Some(&speech(0.6)),
+            )
+            .unwrap();
+        assert_eq!(transcript.text, "Let's move the standup to ten tomorrow.");
+        assert_eq!(transcript.segments.len(), 1);
+    }
+
+    #[test]
+    fn test_confident_phrase_in_speech_is_kept() {
+        let filter = HallucinationFilter::default();
+        let transcript = filter
+            .apply(fixture("verbose_confident_thanks"), Some(&speech(0.5)))
+            .unwrap();
+        assert_eq!(transcript.text, "Thank you.");
+    }
+
+    #[test]
+    fn test_confident_phrase_in_mostly_silent_recording_is_dropped() {
+        let filter = HallucinationFilter::default();
+        let result = filter.apply(fixture("verbose_confident_thanks"), Some(&speech(0.05)));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_silent_recording_rejects_any_text() {
+        let filter = HallucinationFilter::default();
+        let result = filter.apply(
+            fixture("verbose_trailing_hallucination"),
+            Some(&speech(0.0)),
+        );
+        assert_eq!(result.unwrap_err(), NO_SPEECH_ERROR);
+    }
+
+    #[test]
+    fn test_plain_json_phrase_is_rejected() {
+        let filter = HallucinationFilter::default();
+        assert!(filter.apply(fixture("json_plain_you"), None).is_err());
+
+        let hello = parse_response(r#"{"text": "Hello world"}"#).unwrap();
+        assert_eq!(filter.apply(hello, None).unwrap().text, "Hello world");
+    }
+
+    #[test]
+    fn test_user_phrases_extend_defaults() {
+        let filter = HallucinationFilter::new(&["Untertitel der Amara.org-Community".to_string()]);
+        let transcript =
+            parse_response(r#"{"text": " untertitel der amara.org-community"}"#).unwrap();
+        assert!(filter.apply(transcript, None).is_err());
+        assert!(filter.is_phrase("Thanks for watching!"));
+    }
+
+    #[test]
+    fn test_phrase_matching_is_normalized_and_exact() {
+        let filter = HallucinationFilter::default();
+        for text in ["you", "You", "YOU", "thank you", "Thank You.", "subscribe."] {
+            assert!(filter.is_phrase(text), "{:?} should match", text);
+        }
+        for text in ["Hello world", "you there", "thank you all"] {
+            assert!(!filter.is_phrase(text), "{:?} should not match", text);
+        }
+    }
+}
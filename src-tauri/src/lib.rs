@@ -1,16 +1,29 @@
+mod api_keys;
 mod audio;
+mod batch;
+mod cli;
 mod cursor;
-mod keychain;
+mod decode;
+mod diagnostics;
+mod dsp;
+mod export;
+mod hallucination;
+mod meter;
 mod paste;
+mod secrets;
 mod settings;
+mod telemetry;
 mod transcription;
+mod usage;
 
+use api_keys::ApiKeyCache;
 use audio::{AudioPreviewHandle, RecordingHandle};
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use settings::{Settings, ShortcutConfig, TranscriptionProvider};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+#[cfg(target_os = "macos")]
 use tauri::ActivationPolicy;
 use tauri::{
     menu::{MenuBuilder, MenuItemBuilder},
@@ -19,40 +32,61 @@ use tauri::{
 };
 use tauri_plugin_autostart::ManagerExt;
 use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Shortcut, ShortcutState};
+use tauri_plugin_opener::OpenerExt;
+use tracing::Instrument;
 
 #[derive(Default, Serialize, Deserialize, Clone)]
 pub struct AppState {
     pub last_transcription: String,
+    /// Input devices the last transcription was recorded from, in order
+    pub last_input_devices: Vec<String>,
     pub is_recording: bool,
 }
 
 struct RecorderState {
     handle: Option<RecordingHandle>,
     stop_polling: Arc<AtomicBool>,
-    original_app: Option<String>,
+    original_app: Option<cursor::FrontmostApp>,
+    /// Timing span of the dictation being recorded
+    dictation: tracing::Span,
+}
+
+const TRAY_ID: &str = "main";
+
+/// What the tray icon shows.
+#[derive(Clone, Copy)]
+enum TrayState {
+    Idle,
+    /// Not recording, but the microphone is held open for pre-roll
+    Listening,
+    Recording,
 }
 
 #[derive(Clone)]
 struct TrayIcons {
     idle_1x: tauri::image::Image<'static>,
     idle_2x: tauri::image::Image<'static>,
+    listening_1x: tauri::image::Image<'static>,
+    listening_2x: tauri::image::Image<'static>,
     recording_1x: tauri::image::Image<'static>,
     recording_2x: tauri::image::Image<'static>,
 }
 
 impl TrayIcons {
-    fn select(&self, app: &AppHandle, recording: bool) -> tauri::image::Image<'static> {
+    fn select(&self, app: &AppHandle, state: TrayState) -> tauri::image::Image<'static> {
         let scale_factor = app
             .get_webview_window("main")
             .and_then(|window| window.scale_factor().ok())
             .unwrap_or(1.0);
         let use_retina = scale_factor >= 2.0;
 
-        match (recording, use_retina) {
-            (false, false) => self.idle_1x.clone(),
-            (false, true) => self.idle_2x.clone(),
-            (true, false) => self.recording_1x.clone(),
-            (true, true) => self.recording_2x.clone(),
+        match (state, use_retina) {
+            (TrayState::Idle, false) => self.idle_1x.clone(),
+            (TrayState::Idle, true) => self.idle_2x.clone(),
+            (TrayState::Listening, false) => self.listening_1x.clone(),
+            (TrayState::Listening, true) => self.listening_2x.clone(),
+            (TrayState::Recording, false) => self.recording_1x.clone(),
+            (TrayState::Recording, true) => self.recording_2x.clone(),
         }
     }
 }
@@ -62,55 +96,42 @@ struct ShortcutSettings {
     config: ShortcutConfig,
 }
 
+/// The app's settings. Commands change `settings` and call `save` while
+/// holding the lock, so the file always matches what is in memory.
 struct SettingsState {
     settings: Settings,
+    /// Problems found and repaired when the settings file was last loaded
+    warnings: Vec<String>,
 }
 
-/// In-memory cache for API keys so the OS keychain is only read once (at first
-/// setup or, for signed builds, at first launch after storing).  Every
-/// subsequent access — status checks, provider lists, transcriptions — reads
-/// from this cache instead of hitting the keychain again.
-struct ApiKeyCache {
-    keys: std::collections::HashMap<String, String>,
-}
-
-impl ApiKeyCache {
-    /// Build the cache by reading each provider from the keychain once.
-    fn load_from_keychain() -> Self {
-        let mut keys = std::collections::HashMap::new();
-        for provider in &["openai", "groq"] {
-            if let Some(key) = keychain::get_api_key(provider) {
-                keys.insert(provider.to_string(), key);
-            }
+impl SettingsState {
+    /// Persist the settings. A change to a managed setting is undone and
+    /// reported as an error; other changes are still saved.
+    fn save(&mut self) -> Result<(), String> {
+        let overridden = settings::enforce_managed_settings(&mut self.settings);
+        settings::save_settings(&self.settings)?;
+        if overridden.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Managed by your organization and can't be changed: {}",
+                overridden.join(", ")
+            ))
         }
-        Self { keys }
-    }
-
-    fn get(&self, provider: &str) -> Option<String> {
-        self.keys.get(provider).cloned()
-    }
-
-    fn has(&self, provider: &str) -> bool {
-        self.keys.contains_key(provider)
-    }
-
-    fn set(&mut self, provider: &str, key: String) {
-        self.keys.insert(provider.to_string(), key);
-    }
-
-    fn remove(&mut self, provider: &str) {
-        self.keys.remove(provider);
     }
 }
 
-/// Resolve an API key for the given provider from the in-memory cache.
+/// Resolve an API key for the given provider from the in-memory cache, using
+/// the credential selected for `app` (the frontmost app when recording began).
 /// This never touches the keychain.
-fn get_api_key_from_cache(cache: &ApiKeyCache, provider: &TranscriptionProvider) -> Option<String> {
-    let provider_key = match provider {
-        TranscriptionProvider::OpenAI => "openai",
-        TranscriptionProvider::Groq => "groq",
-    };
-    cache.get(provider_key)
+fn get_api_key_from_cache(
+    cache: &ApiKeyCache,
+    settings: &Settings,
+    provider: &TranscriptionProvider,
+    app: Option<&str>,
+) -> Option<String> {
+    let id = settings::get_provider_id(provider);
+    cache.get(id, settings::selected_credential(settings, id, app))
 }
 
 #[tauri::command]
@@ -118,6 +139,11 @@ fn get_transcription(state: tauri::State<'_, Mutex<AppState>>) -> String {
     state.lock().last_transcription.clone()
 }
 
+#[tauri::command]
+fn get_transcription_devices(state: tauri::State<'_, Mutex<AppState>>) -> Vec<String> {
+    state.lock().last_input_devices.clone()
+}
+
 #[tauri::command]
 fn get_recording_status(state: tauri::State<'_, Mutex<AppState>>) -> bool {
     state.lock().is_recording
@@ -191,6 +217,40 @@ fn get_shortcut(state: tauri::State<'_, Mutex<ShortcutSettings>>) -> ShortcutInf
     }
 }
 
+/// Replace the registered global shortcut with `config`. If the new one
+/// can't be registered, the old one stays active.
+fn register_shortcut(app: &AppHandle, config: &ShortcutConfig) -> Result<(), String> {
+    let parsed_key =
+        settings::parse_key(&config.key).ok_or_else(|| format!("Invalid key: {}", config.key))?;
+    let parsed_modifiers = settings::parse_modifiers(&config.modifiers);
+    let mods = if parsed_modifiers.is_empty() {
+        None
+    } else {
+        Some(parsed_modifiers)
+    };
+    let new_shortcut = Shortcut::new(mods, parsed_key);
+
+    let shortcut_state = app.state::<Mutex<ShortcutSettings>>();
+    let mut state = shortcut_state.lock();
+
+    // Unregister the old shortcut
+    if let Some(old_shortcut) = &state.current_shortcut {
+        let _ = app.global_shortcut().unregister(*old_shortcut);
+    }
+
+    // Register the new shortcut
+    if let Err(e) = app.global_shortcut().register(new_shortcut) {
+        if let Some(old_shortcut) = &state.current_shortcut {
+            let _ = app.global_shortcut().register(*old_shortcut);
+        }
+        return Err(format!("Failed to register shortcut: {}", e));
+    }
+
+    state.current_shortcut = Some(new_shortcut);
+    state.config = config.clone();
+    Ok(())
+}
+
 #[tauri::command]
 fn set_shortcut(
     app: AppHandle,
@@ -202,52 +262,16 @@ fn set_shortcut(
         return Err("Multi-key shortcuts (e.g., R+L) are not supported. Use modifier keys (⌘⇧⌃⌥) with a single key.".to_string());
     }
 
-    // Validate the key
-    if settings::parse_key(&key).is_none() {
-        return Err(format!("Invalid key: {}", key));
-    }
-
     let new_config = ShortcutConfig {
         modifiers: modifiers.clone(),
         key: key.clone(),
     };
+    register_shortcut(&app, &new_config)?;
 
-    // Build the new shortcut
-    let parsed_modifiers = settings::parse_modifiers(&modifiers);
-    let parsed_key = settings::parse_key(&key).unwrap();
-    let mods = if parsed_modifiers.is_empty() {
-        None
-    } else {
-        Some(parsed_modifiers)
-    };
-    let new_shortcut = Shortcut::new(mods, parsed_key);
-
-    // Unregister the old shortcut
-    {
-        let shortcut_state = app.state::<Mutex<ShortcutSettings>>();
-        let state = shortcut_state.lock();
-        if let Some(old_shortcut) = &state.current_shortcut {
-            let _ = app.global_shortcut().unregister(*old_shortcut);
-        }
-    }
-
-    // Register the new shortcut
-    app.global_shortcut()
-        .register(new_shortcut)
-        .map_err(|e| format!("Failed to register shortcut: {}", e))?;
-
-    // Update the state
-    {
-        let shortcut_state = app.state::<Mutex<ShortcutSettings>>();
-        let mut state = shortcut_state.lock();
-        state.current_shortcut = Some(new_shortcut);
-        state.config = new_config.clone();
-    }
-
-    // Save to settings file
-    let mut full_settings = settings::load_settings();
-    full_settings.shortcut = new_config.clone();
-    settings::save_settings(&full_settings)?;
+    let settings_state = app.state::<Mutex<SettingsState>>();
+    let mut state_guard = settings_state.lock();
+    state_guard.settings.shortcut = new_config.clone();
+    state_guard.save()?;
 
     Ok(ShortcutInfo {
         modifiers,
@@ -264,76 +288,249 @@ fn set_shortcut(
 struct ApiKeyStatus {
     openai_configured: bool,
     groq_configured: bool,
-    openai_source: Option<String>,
-    groq_source: Option<String>,
+    openai_source: Option<api_keys::ApiKeySource>,
+    groq_source: Option<api_keys::ApiKeySource>,
 }
 
-fn get_api_key_status_internal(cache: &ApiKeyCache) -> ApiKeyStatus {
-    let openai_configured = cache.has("openai");
-    let groq_configured = cache.has("groq");
-
+/// Status of each provider's selected credential.
+fn get_api_key_status_internal(cache: &ApiKeyCache, settings: &Settings) -> ApiKeyStatus {
+    let openai = settings::selected_credential(settings, "openai", None);
+    let groq = settings::selected_credential(settings, "groq", None);
     ApiKeyStatus {
-        openai_configured,
-        groq_configured,
-        openai_source: if openai_configured {
-            Some("keychain".to_string())
-        } else {
-            None
-        },
-        groq_source: if groq_configured {
-            Some("keychain".to_string())
-        } else {
-            None
-        },
+        openai_configured: cache.has("openai", openai),
+        groq_configured: cache.has("groq", groq),
+        openai_source: cache.source("openai", openai),
+        groq_source: cache.source("groq", groq),
     }
 }
 
 #[tauri::command]
-fn get_api_key_status(cache: tauri::State<'_, Mutex<ApiKeyCache>>) -> ApiKeyStatus {
-    get_api_key_status_internal(&cache.lock())
+fn get_api_key_status(
+    state: tauri::State<'_, Mutex<SettingsState>>,
+    cache: tauri::State<'_, Mutex<ApiKeyCache>>,
+) -> ApiKeyStatus {
+    let state_guard = state.lock();
+    get_api_key_status_internal(&cache.lock(), &state_guard.settings)
 }
 
+/// Store (or, when blank, delete) the key of the provider's selected credential.
 #[tauri::command]
 fn set_api_key(
     provider: String,
     api_key: String,
+    state: tauri::State<'_, Mutex<SettingsState>>,
     cache: tauri::State<'_, Mutex<ApiKeyCache>>,
 ) -> Result<ApiKeyStatus, String> {
-    let provider_key = match provider.to_lowercase().as_str() {
-        "openai" => "openai",
-        "groq" => "groq",
-        _ => return Err(format!("Unknown provider: {}", provider)),
-    };
+    let provider_key = parse_provider_id(&provider)?;
 
+    let state_guard = state.lock();
     let mut cache = cache.lock();
+    let credential = settings::selected_credential(&state_guard.settings, provider_key, None);
+    cache.save(provider_key, credential, &api_key)?;
+    Ok(get_api_key_status_internal(&cache, &state_guard.settings))
+}
 
-    if api_key.trim().is_empty() {
-        // Delete from both keychain and cache
-        keychain::delete_api_key(provider_key)?;
-        cache.remove(provider_key);
-    } else {
-        // Validate API key format
-        let trimmed = api_key.trim();
-        match provider_key {
-            "openai" => {
-                if !trimmed.starts_with("sk-") {
-                    return Err("OpenAI API keys should start with 'sk-'".to_string());
-                }
-            }
-            "groq" => {
-                if !trimmed.starts_with("gsk_") {
-                    return Err("Groq API keys should start with 'gsk_'".to_string());
-                }
+fn parse_provider_id(provider: &str) -> Result<&'static str, String> {
+    settings::parse_provider(provider)
+        .map(|parsed| settings::get_provider_id(&parsed))
+        .ok_or_else(|| format!("Unknown provider: {}", provider))
+}
+
+/// A provider's named credentials. Never includes the keys themselves.
+#[derive(Serialize, Clone)]
+struct ProviderCredentialList {
+    provider: String,
+    credentials: Vec<CredentialInfo>,
+    /// Credential in use unless an app overrides it
+    selected: String,
+    /// Overrides keyed by app bundle id
+    apps: std::collections::BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Clone)]
+struct CredentialInfo {
+    name: String,
+    /// Whether a key is stored for it
+    configured: bool,
+}
+
+fn list_credentials_internal(
+    cache: &ApiKeyCache,
+    settings: &Settings,
+) -> Vec<ProviderCredentialList> {
+    ["openai", "groq"]
+        .into_iter()
+        .map(|provider| {
+            let config = settings
+                .credentials
+                .get(provider)
+                .cloned()
+                .unwrap_or_default();
+            let credentials = std::iter::once(settings::DEFAULT_CREDENTIAL.to_string())
+                .chain(config.names)
+                .map(|name| CredentialInfo {
+                    configured: cache.has_stored(provider, &name),
+                    name,
+                })
+                .collect();
+            ProviderCredentialList {
+                provider: provider.to_string(),
+                credentials,
+                selected: settings::selected_credential(settings, provider, None).to_string(),
+                apps: config.apps,
             }
-            _ => {}
+        })
+        .collect()
+}
+
+#[tauri::command]
+fn list_credentials(
+    state: tauri::State<'_, Mutex<SettingsState>>,
+    cache: tauri::State<'_, Mutex<ApiKeyCache>>,
+) -> Vec<ProviderCredentialList> {
+    let state_guard = state.lock();
+    list_credentials_internal(&cache.lock(), &state_guard.settings)
+}
+
+/// Store a key under a new name for the provider.
+#[tauri::command]
+fn add_credential(
+    provider: String,
+    name: String,
+    api_key: String,
+    state: tauri::State<'_, Mutex<SettingsState>>,
+    cache: tauri::State<'_, Mutex<ApiKeyCache>>,
+) -> Result<Vec<ProviderCredentialList>, String> {
+    let provider_key = parse_provider_id(&provider)?;
+    let name = name.trim().to_string();
+    api_keys::validate_credential_name(&name)?;
+    if api_key.trim().is_empty() {
+        return Err(format!("Enter a key for \"{}\"", name));
+    }
+
+    let mut state_guard = state.lock();
+    let mut cache = cache.lock();
+    let credentials = state_guard
+        .settings
+        .credentials
+        .entry(provider_key.to_string())
+        .or_default();
+    if credentials.contains(&name) {
+        return Err(format!(
+            "There already is a {} credential named \"{}\"",
+            provider_key, name
+        ));
+    }
+    cache.save(provider_key, &name, &api_key)?;
+    credentials.names.push(name.clone());
+    if let Err(e) = state_guard.save() {
+        // Don't leave a key behind that no name refers to
+        let _ = cache.save(provider_key, &name, "");
+        if let Some(credentials) = state_guard.settings.credentials.get_mut(provider_key) {
+            credentials.names.retain(|n| *n != name);
         }
+        return Err(e);
+    }
+    Ok(list_credentials_internal(&cache, &state_guard.settings))
+}
 
-        // Store in both keychain and cache
-        keychain::store_api_key(provider_key, trimmed)?;
-        cache.set(provider_key, trimmed.to_string());
+/// Delete a named credential's key, and stop using it wherever it was selected.
+#[tauri::command]
+fn remove_credential(
+    provider: String,
+    name: String,
+    state: tauri::State<'_, Mutex<SettingsState>>,
+    cache: tauri::State<'_, Mutex<ApiKeyCache>>,
+) -> Result<Vec<ProviderCredentialList>, String> {
+    let provider_key = parse_provider_id(&provider)?;
+    if name == settings::DEFAULT_CREDENTIAL {
+        return Err("The default credential can't be removed; clear its key instead".to_string());
     }
 
-    Ok(get_api_key_status_internal(&cache))
+    let mut state_guard = state.lock();
+    let mut cache = cache.lock();
+    let credentials = state_guard
+        .settings
+        .credentials
+        .get_mut(provider_key)
+        .filter(|credentials| credentials.contains(&name))
+        .ok_or_else(|| format!("No {} credential named \"{}\"", provider_key, name))?;
+    cache.save(provider_key, &name, "")?;
+    credentials.names.retain(|n| *n != name);
+    if credentials.selected.as_deref() == Some(name.as_str()) {
+        credentials.selected = None;
+    }
+    credentials.apps.retain(|_, n| *n != name);
+    state_guard.save()?;
+    Ok(list_credentials_internal(&cache, &state_guard.settings))
+}
+
+/// Switch the provider's credential. With `app`, only dictation into that
+/// app uses it, and no `name` removes the app's override.
+#[tauri::command]
+fn select_credential(
+    provider: String,
+    name: Option<String>,
+    app: Option<String>,
+    state: tauri::State<'_, Mutex<SettingsState>>,
+    cache: tauri::State<'_, Mutex<ApiKeyCache>>,
+) -> Result<Vec<ProviderCredentialList>, String> {
+    let provider_key = parse_provider_id(&provider)?;
+
+    let mut state_guard = state.lock();
+    let credentials = state_guard
+        .settings
+        .credentials
+        .entry(provider_key.to_string())
+        .or_default();
+    if let Some(name) = &name {
+        if !credentials.contains(name) {
+            return Err(format!("No {} credential named \"{}\"", provider_key, name));
+        }
+    }
+    match (app, name) {
+        (Some(app), Some(name)) => {
+            credentials.apps.insert(app, name);
+        }
+        (Some(app), None) => {
+            credentials.apps.remove(&app);
+        }
+        (None, name) => {
+            credentials.selected = name.filter(|name| name != settings::DEFAULT_CREDENTIAL);
+        }
+    }
+    state_guard.save()?;
+    Ok(list_credentials_internal(
+        &cache.lock(),
+        &state_guard.settings,
+    ))
+}
+
+/// Check a key with the provider without saving it. Without `api_key`, the
+/// key currently in use for the provider is checked.
+#[tauri::command]
+async fn test_api_key(
+    provider: String,
+    api_key: Option<String>,
+    app: AppHandle,
+) -> Result<transcription::KeyCheck, String> {
+    let parsed = settings::parse_provider(&provider)
+        .ok_or_else(|| format!("Unknown provider: {}", provider))?;
+    let api_key = match api_key.filter(|key| !key.trim().is_empty()) {
+        Some(key) => key.trim().to_string(),
+        None => {
+            let settings_state = app.state::<Mutex<SettingsState>>();
+            let state_guard = settings_state.lock();
+            let cache = app.state::<Mutex<ApiKeyCache>>();
+            let key = get_api_key_from_cache(&cache.lock(), &state_guard.settings, &parsed, None);
+            key.ok_or_else(|| format!("No API key configured for {}", provider))?
+        }
+    };
+    Ok(transcription::check_api_key(
+        settings::get_models_endpoint_for_provider(&parsed),
+        &api_key,
+    )
+    .await)
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -345,24 +542,70 @@ struct ProviderInfo {
 }
 
 #[tauri::command]
-fn get_available_providers(cache: tauri::State<'_, Mutex<ApiKeyCache>>) -> Vec<ProviderInfo> {
+fn get_available_providers(
+    state: tauri::State<'_, Mutex<SettingsState>>,
+    cache: tauri::State<'_, Mutex<ApiKeyCache>>,
+) -> Vec<ProviderInfo> {
+    let settings = &state.lock().settings;
     let cache = cache.lock();
     vec![
         ProviderInfo {
             id: "openai".to_string(),
             name: "OpenAI Whisper".to_string(),
             model: "whisper-1".to_string(),
-            available: cache.has("openai"),
+            available: cache.has(
+                "openai",
+                settings::selected_credential(settings, "openai", None),
+            ),
         },
         ProviderInfo {
             id: "groq".to_string(),
             name: "Groq Whisper".to_string(),
             model: "whisper-large-v3-turbo".to_string(),
-            available: cache.has("groq"),
+            available: cache.has(
+                "groq",
+                settings::selected_credential(settings, "groq", None),
+            ),
         },
     ]
 }
 
+/// Problems found and repaired in the settings file at startup.
+#[tauri::command]
+fn get_settings_warnings(state: tauri::State<'_, Mutex<SettingsState>>) -> Vec<String> {
+    state.lock().warnings.clone()
+}
+
+/// All settings as a JSON bundle (no API keys) for setting up other machines.
+#[tauri::command]
+fn export_settings(state: tauri::State<'_, Mutex<SettingsState>>) -> Result<String, String> {
+    settings::export_settings(&state.lock().settings)
+}
+
+/// Apply a bundle from `export_settings`; settings it leaves out are kept.
+/// The UI hears about the result through `settings-changed`.
+#[tauri::command]
+fn import_settings(app: AppHandle, bundle: String) -> Result<(), String> {
+    let settings_state = app.state::<Mutex<SettingsState>>();
+    let mut state_guard = settings_state.lock();
+    let imported = settings::import_settings(&state_guard.settings, &bundle)?;
+    let old = std::mem::replace(&mut state_guard.settings, imported.settings);
+    let saved = state_guard.save();
+    drop(state_guard);
+
+    let mut warnings = imported.warnings;
+    apply_settings_change(&app, &old, &mut warnings);
+    let _ = app.emit("settings-changed", warnings);
+    saved
+}
+
+/// Where each setting comes from ("user" or "managed"), keyed by dotted
+/// path. Settings that aren't listed use their defaults.
+#[tauri::command]
+fn get_settings_provenance() -> std::collections::BTreeMap<String, settings::SettingSource> {
+    settings::settings_provenance()
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct TranscriptionSettings {
     provider: String,
@@ -397,13 +640,13 @@ fn set_transcription_provider(
         _ => return Err(format!("Unknown provider: {}", provider)),
     };
 
-    // Validate that the provider has an API key configured (via cache + env)
-    if get_api_key_from_cache(&cache.lock(), &new_provider).is_none() {
+    // Validate that the provider has an API key configured (from any source)
+    if get_api_key_from_cache(&cache.lock(), &state_guard.settings, &new_provider, None).is_none() {
         return Err(format!("No API key configured for {}", provider));
     }
 
     state_guard.settings.transcription.provider = new_provider.clone();
-    settings::save_settings(&state_guard.settings)?;
+    state_guard.save()?;
 
     Ok(TranscriptionSettings {
         provider,
@@ -418,13 +661,21 @@ fn set_transcription_provider(
 #[derive(Serialize, Deserialize, Clone)]
 struct AudioDeviceInfo {
     name: String,
+    /// Stable identity used to key per-device settings
+    id: String,
     is_default: bool,
 }
 
 struct AudioPreviewState {
     handle: Option<AudioPreviewHandle>,
-    levels: Arc<Mutex<Vec<f32>>>,
+    levels: Arc<Mutex<meter::AudioLevels>>,
     stop_polling: Arc<AtomicBool>,
+    /// Last microphone test recording, kept for playback until the preview stops
+    test_recording: Option<std::path::PathBuf>,
+}
+
+struct PreRollState {
+    handle: Option<audio::PreRollHandle>,
 }
 
 #[tauri::command]
@@ -435,33 +686,136 @@ fn list_audio_input_devices() -> Vec<AudioDeviceInfo> {
         .into_iter()
         .map(|name| AudioDeviceInfo {
             is_default: default_name.as_deref() == Some(&name),
+            id: audio::device_id(&name),
             name,
         })
         .collect()
 }
 
+/// The most preferred input device (`None` means the system default).
 #[tauri::command]
 fn get_audio_input_device(state: tauri::State<'_, Mutex<SettingsState>>) -> Option<String> {
-    state.lock().settings.audio_input_device.clone()
+    state.lock().settings.audio_input_devices.first().cloned()
 }
 
+/// Select a device; previously selected devices are kept as fallbacks.
 #[tauri::command]
 fn set_audio_input_device(
     device_name: Option<String>,
+    app: AppHandle,
+    state: tauri::State<'_, Mutex<SettingsState>>,
+) -> Result<(), String> {
+    let mut state_guard = state.lock();
+    state_guard.settings.prefer_audio_input_device(device_name);
+    state_guard.save()?;
+    drop(state_guard);
+    apply_pre_roll(&app);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_preferred_audio_devices(state: tauri::State<'_, Mutex<SettingsState>>) -> Vec<String> {
+    state.lock().settings.audio_input_devices.clone()
+}
+
+#[tauri::command]
+fn set_preferred_audio_devices(
+    devices: Vec<String>,
+    app: AppHandle,
     state: tauri::State<'_, Mutex<SettingsState>>,
 ) -> Result<(), String> {
     let mut state_guard = state.lock();
-    state_guard.settings.audio_input_device = device_name;
-    settings::save_settings(&state_guard.settings)
+    state_guard.settings.audio_input_devices = devices;
+    state_guard.save()?;
+    drop(state_guard);
+    apply_pre_roll(&app);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_audio_device_config(
+    device_name: String,
+    state: tauri::State<'_, Mutex<SettingsState>>,
+) -> settings::AudioDeviceConfig {
+    state
+        .lock()
+        .settings
+        .audio_device_configs
+        .get(&audio::device_id(&device_name))
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+fn set_audio_device_config(
+    device_name: String,
+    config: settings::AudioDeviceConfig,
+    app: AppHandle,
+    state: tauri::State<'_, Mutex<SettingsState>>,
+) -> Result<(), String> {
+    let mut state_guard = state.lock();
+    let configs = &mut state_guard.settings.audio_device_configs;
+    if config == settings::AudioDeviceConfig::default() {
+        configs.remove(&audio::device_id(&device_name));
+    } else {
+        configs.insert(audio::device_id(&device_name), config);
+    }
+    state_guard.save()?;
+    drop(state_guard);
+    // A pre-roll stream on this device must reopen with the new format
+    apply_pre_roll(&app);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_audio_processing(
+    state: tauri::State<'_, Mutex<SettingsState>>,
+) -> settings::AudioProcessingConfig {
+    state.lock().settings.audio_processing.clone()
+}
+
+#[tauri::command]
+fn set_audio_processing(
+    config: settings::AudioProcessingConfig,
+    state: tauri::State<'_, Mutex<SettingsState>>,
+) -> Result<(), String> {
+    let mut state_guard = state.lock();
+    state_guard.settings.audio_processing = config;
+    state_guard.save()
+}
+
+#[tauri::command]
+fn get_pre_roll(state: tauri::State<'_, Mutex<SettingsState>>) -> bool {
+    state.lock().settings.pre_roll
+}
+
+/// Keep the microphone open between recordings (or stop doing so) so that
+/// recordings start with the audio from just before the key press.
+#[tauri::command]
+fn set_pre_roll(
+    enabled: bool,
+    app: AppHandle,
+    state: tauri::State<'_, Mutex<SettingsState>>,
+) -> Result<(), String> {
+    {
+        let mut state_guard = state.lock();
+        state_guard.settings.pre_roll = enabled;
+        state_guard.save()?;
+    }
+    apply_pre_roll(&app);
+    Ok(())
 }
 
+/// Start streaming input levels; with `monitor`, also play the processed
+/// input back so filter settings can be heard.
 #[tauri::command]
 fn start_audio_preview(
+    monitor: Option<bool>,
     app: AppHandle,
     state: tauri::State<'_, Mutex<AudioPreviewState>>,
     settings_state: tauri::State<'_, Mutex<SettingsState>>,
 ) -> Result<(), String> {
-    let device_name = settings_state.lock().settings.audio_input_device.clone();
+    let input = audio::InputSettings::from_settings(&settings_state.lock().settings);
 
     let mut preview = state.lock();
 
@@ -474,11 +828,16 @@ fn start_audio_preview(
     // Reset levels
     {
         let mut levels = preview.levels.lock();
-        *levels = vec![0.15; 3];
+        *levels = meter::AudioLevels::idle(input.meter_bars);
     }
 
     let levels = Arc::clone(&preview.levels);
-    let handle = audio::start_preview(device_name.as_deref(), Arc::clone(&levels))?;
+    let handle = audio::start_preview(
+        Arc::new(audio::CpalSource),
+        &input,
+        Arc::clone(&levels),
+        monitor.unwrap_or(false),
+    )?;
 
     let stop_flag = Arc::new(AtomicBool::new(false));
     preview.stop_polling = Arc::clone(&stop_flag);
@@ -504,10 +863,314 @@ fn stop_audio_preview(state: tauri::State<'_, Mutex<AudioPreviewState>>) {
     if let Some(handle) = preview.handle.take() {
         handle.stop();
     }
+    if let Some(path) = preview.test_recording.take() {
+        let _ = std::fs::remove_file(path);
+    }
     // Reset levels — clone the Arc first so we can drop the outer guard
     let levels_arc = Arc::clone(&preview.levels);
     drop(preview);
-    *levels_arc.lock() = vec![0.15; 3];
+    let mut levels = levels_arc.lock();
+    *levels = meter::AudioLevels::idle(levels.bars.len());
+}
+
+/// Outcome of a microphone test, for the settings UI.
+#[derive(Serialize)]
+struct MicTestResult {
+    /// What the transcription service heard
+    text: String,
+    /// Speech level over the background noise in dB
+    snr_db: f32,
+    peak_dbfs: f32,
+    /// Fraction (0–1) of the test that contained speech
+    active_ratio: f32,
+}
+
+/// Record a few seconds from the running preview and transcribe them, so a
+/// microphone setup can be checked end to end before relying on it. The
+/// recording is kept for `play_mic_test` until the preview stops.
+#[tauri::command]
+async fn run_mic_test(app: AppHandle) -> Result<MicTestResult, String> {
+    let handle = app
+        .state::<Mutex<AudioPreviewState>>()
+        .lock()
+        .handle
+        .clone()
+        .ok_or("Start the level monitor before testing")?;
+    let test =
+        tauri::async_runtime::spawn_blocking(move || handle.record_test(audio::MIC_TEST_DURATION))
+            .await
+            .map_err(|e| format!("Microphone test failed: {}", e))??;
+
+    let previous = app
+        .state::<Mutex<AudioPreviewState>>()
+        .lock()
+        .test_recording
+        .replace(test.path.clone());
+    if let Some(path) = previous {
+        let _ = std::fs::remove_file(path);
+    }
+
+    let setup = transcription_setup(&app, None);
+    let api_key = setup
+        .api_key
+        .ok_or("No API key configured. Please add an API key in Settings.")?;
+    if let Some(warning) = usage::check_budget(&setup.usage)? {
+        let _ = app.emit("budget-warning", warning);
+    }
+    let request = transcription::TranscriptionRequest {
+        audio_path: &test.path,
+        provider: setup.provider,
+        api_key: &api_key,
+        endpoint: setup.endpoint,
+        model: setup.model,
+        language: transcription::DEFAULT_LANGUAGE,
+        timestamps: transcription::Timestamps::Segments,
+    };
+    // Filtered like a real recording, so a test that only picks up noise
+    // comes back empty rather than as a hallucinated sentence
+    let text = transcription::transcribe_audio(request)
+        .await
+        .and_then(|transcript| setup.filter.apply(transcript, Some(&test.speech)))
+        .map(|transcript| transcript.text)
+        .or_else(|e| {
+            if e == hallucination::NO_SPEECH_ERROR {
+                Ok(String::new())
+            } else {
+                Err(e)
+            }
+        })?;
+
+    Ok(MicTestResult {
+        text,
+        snr_db: test.snr_db,
+        peak_dbfs: 20.0 * test.speech.peak.max(1e-5).log10(),
+        active_ratio: test.speech.active_ratio,
+    })
+}
+
+/// Play back the last microphone test recording.
+#[tauri::command]
+async fn play_mic_test(app: AppHandle) -> Result<(), String> {
+    let path = app
+        .state::<Mutex<AudioPreviewState>>()
+        .lock()
+        .test_recording
+        .clone()
+        .ok_or("No test recording to play")?;
+    tauri::async_runtime::spawn_blocking(move || audio::play_file(&path))
+        .await
+        .map_err(|e| format!("Playback failed: {}", e))?
+}
+
+// ============================================================================
+// Batch File Transcription Commands
+// ============================================================================
+
+#[derive(Default)]
+struct BatchState {
+    next_id: u64,
+    cancel_flags: std::collections::HashMap<u64, Arc<AtomicBool>>,
+}
+
+/// Queue audio files for transcription. Returns the batch id immediately;
+/// progress arrives as `batch-progress` events and the totals as `batch-complete`.
+#[tauri::command]
+fn transcribe_files(
+    app: AppHandle,
+    paths: Vec<String>,
+    output: Option<batch::BatchOutput>,
+    max_concurrency: Option<usize>,
+    language: Option<String>,
+    settings_state: tauri::State<'_, Mutex<SettingsState>>,
+    cache: tauri::State<'_, Mutex<ApiKeyCache>>,
+) -> Result<u64, String> {
+    if paths.is_empty() {
+        return Err("No files to transcribe".to_string());
+    }
+
+    let paths: Vec<std::path::PathBuf> = paths.into_iter().map(Into::into).collect();
+    let unsupported: Vec<String> = paths
+        .iter()
+        .filter(|p| !p.is_file() || !decode::is_supported(p))
+        .map(|p| p.display().to_string())
+        .collect();
+    if !unsupported.is_empty() {
+        return Err(format!(
+            "Unsupported or missing files (expected {}): {}",
+            decode::SUPPORTED_EXTENSIONS.join(", "),
+            unsupported.join(", ")
+        ));
+    }
+
+    let (provider, filter, api_key, usage_config) = {
+        let settings = &settings_state.lock().settings;
+        let provider = settings.transcription.provider.clone();
+        let api_key = get_api_key_from_cache(&cache.lock(), settings, &provider, None);
+        (
+            provider,
+            hallucination::HallucinationFilter::new(&settings.hallucination_phrases),
+            api_key,
+            settings.usage.clone(),
+        )
+    };
+    let api_key = api_key.ok_or("No API key configured. Please add an API key in Settings.")?;
+    // Checked once for the whole batch; a batch that crosses the budget finishes
+    if let Some(warning) = usage::check_budget(&usage_config)? {
+        let _ = app.emit("budget-warning", warning);
+    }
+
+    let config = batch::BatchConfig {
+        provider: settings::get_provider_id(&provider),
+        api_key,
+        endpoint: settings::get_endpoint_for_provider(&provider),
+        model: settings::get_model_for_provider(&provider),
+        language: language.unwrap_or_else(|| transcription::DEFAULT_LANGUAGE.to_string()),
+        output: output.unwrap_or_default(),
+        max_concurrency: max_concurrency.unwrap_or(batch::DEFAULT_MAX_CONCURRENCY),
+        filter: Arc::new(filter),
+    };
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let batch_id = {
+        let batch_state = app.state::<Mutex<BatchState>>();
+        let mut state = batch_state.lock();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.cancel_flags.insert(id, Arc::clone(&cancel));
+        id
+    };
+
+    let app_for_progress = app.clone();
+    let on_progress: batch::ProgressFn = Arc::new(move |progress| {
+        let _ = app_for_progress.emit("batch-progress", &progress);
+    });
+
+    tauri::async_runtime::spawn(async move {
+        let summary = batch::run_batch(batch_id, paths, config, cancel, on_progress).await;
+        app.state::<Mutex<BatchState>>()
+            .lock()
+            .cancel_flags
+            .remove(&batch_id);
+        let _ = app.emit("batch-complete", &summary);
+    });
+
+    Ok(batch_id)
+}
+
+/// Cancel the queued (not yet started) jobs of a batch.
+#[tauri::command]
+fn cancel_batch_transcription(batch_id: u64, batch_state: tauri::State<'_, Mutex<BatchState>>) {
+    if let Some(flag) = batch_state.lock().cancel_flags.get(&batch_id) {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
+
+// ============================================================================
+// Usage Commands
+// ============================================================================
+
+/// Requests, audio minutes and cost over `range`, per provider and per day
+/// and month, plus where this month stands against the budget.
+#[tauri::command]
+fn get_usage_stats(
+    range: usage::UsageRange,
+    state: tauri::State<'_, Mutex<SettingsState>>,
+) -> usage::UsageStats {
+    let config = state.lock().settings.usage.clone();
+    usage::get_usage_stats(range, &config)
+}
+
+/// Set the monthly budget (`None` for no budget) and whether going over it
+/// stops transcription or only warns.
+#[tauri::command]
+fn set_usage_budget(
+    budget: Option<f64>,
+    block: bool,
+    state: tauri::State<'_, Mutex<SettingsState>>,
+) -> Result<(), String> {
+    if budget.is_some_and(|b| !b.is_finite() || b < 0.0) {
+        return Err("The budget must be zero or more".to_string());
+    }
+    let mut state_guard = state.lock();
+    state_guard.settings.usage.monthly_budget = budget;
+    state_guard.settings.usage.block_over_budget = block;
+    state_guard.save()
+}
+
+// ============================================================================
+// Diagnostics Commands
+// ============================================================================
+
+/// Stage timings of the last few dictations, newest first.
+#[tauri::command]
+fn get_recent_timings() -> Vec<telemetry::DictationTiming> {
+    telemetry::recent_timings()
+}
+
+/// Whether each provider has a key, for the diagnostics bundle.
+fn key_status_for_diagnostics(
+    cache: &ApiKeyCache,
+    settings: &Settings,
+) -> std::collections::BTreeMap<String, diagnostics::ProviderKeyStatus> {
+    list_credentials_internal(cache, settings)
+        .into_iter()
+        .map(|list| {
+            let selected = list.selected.as_str();
+            let status = diagnostics::ProviderKeyStatus {
+                configured: cache.has(&list.provider, selected),
+                source: cache.source(&list.provider, selected),
+                selected: list.selected.clone(),
+                credentials: list
+                    .credentials
+                    .into_iter()
+                    .map(|c| (c.name, c.configured))
+                    .collect(),
+            };
+            (list.provider, status)
+        })
+        .collect()
+}
+
+/// Save a zip for bug reports to the downloads folder and show it in the
+/// file manager. Holds redacted settings, the input devices, permissions,
+/// whether each provider has a key (never the key), timings and recent logs.
+#[tauri::command]
+async fn export_diagnostics(app: AppHandle) -> Result<String, String> {
+    let (settings, warnings, api_keys) = {
+        let settings_state = app.state::<Mutex<SettingsState>>();
+        let state_guard = settings_state.lock();
+        let cache = app.state::<Mutex<ApiKeyCache>>();
+        let api_keys = key_status_for_diagnostics(&cache.lock(), &state_guard.settings);
+        (
+            state_guard.settings.clone(),
+            state_guard.warnings.clone(),
+            api_keys,
+        )
+    };
+    let app_version = app.package_info().version.to_string();
+
+    let path = tauri::async_runtime::spawn_blocking(move || {
+        let report = diagnostics::SystemReport {
+            app_version,
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            accessibility: cursor::accessibility_granted(),
+            api_keys,
+            devices: audio::describe_input_devices(),
+            settings_warnings: warnings,
+        };
+        let path = diagnostics::default_bundle_path();
+        diagnostics::write_bundle(&path, &report, &settings, &telemetry::recent_timings())
+            .map(|()| path)
+    })
+    .await
+    .map_err(|e| format!("Failed to export diagnostics: {}", e))??;
+
+    tracing::info!("Diagnostics saved to {}", path.display());
+    if let Err(e) = app.opener().reveal_item_in_dir(&path) {
+        tracing::warn!("Failed to show {}: {}", path.display(), e);
+    }
+    Ok(path.display().to_string())
 }
 
 // ============================================================================
@@ -570,16 +1233,12 @@ fn create_indicator_window(app: &AppHandle) -> (Option<tauri::WebviewWindow>, bo
     };
 
     // Clamp to screen bounds
-    let (pos_x, pos_y) = {
-        use core_graphics::display::CGDisplay;
-        let bounds = CGDisplay::main().bounds();
-        let screen_w = bounds.size.width as i32;
-        let screen_h = bounds.size.height as i32;
-
-        (
+    let (pos_x, pos_y) = match cursor::get_screen_size() {
+        Some((screen_w, screen_h)) => (
             pos_x.max(4).min(screen_w - width - 4),
             pos_y.max(4).min(screen_h - height - 4),
-        )
+        ),
+        None => (pos_x, pos_y),
     };
 
     // Reuse existing indicator window if it exists (avoids destroy/create race)
@@ -610,7 +1269,7 @@ fn create_indicator_window(app: &AppHandle) -> (Option<tauri::WebviewWindow>, bo
     {
         Ok(window) => (Some(window), true),
         Err(e) => {
-            eprintln!("Failed to create indicator window: {}", e);
+            tracing::error!("Failed to create indicator window: {}", e);
             (None, false)
         }
     }
@@ -622,40 +1281,263 @@ fn destroy_indicator_window(app: &AppHandle) {
     }
 }
 
+/// Update the tray icon to show whether we are recording, or holding the
+/// microphone open for pre-roll.
+fn update_tray(app: &AppHandle, recording: bool) {
+    let listening = app
+        .state::<Mutex<PreRollState>>()
+        .lock()
+        .handle
+        .as_ref()
+        .is_some_and(|handle| handle.is_open());
+    let state = if recording {
+        TrayState::Recording
+    } else if listening {
+        TrayState::Listening
+    } else {
+        TrayState::Idle
+    };
+
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let icon = app.state::<TrayIcons>().select(app, state);
+        let _ = tray.set_icon(Some(icon));
+        let tooltip = if listening {
+            "Scrivano (microphone open for pre-roll)"
+        } else {
+            "Scrivano"
+        };
+        let _ = tray.set_tooltip(Some(tooltip));
+    }
+}
+
+/// Open or close the always-on pre-roll input to match the settings. Also
+/// called when the device selection changes, so the stream follows it.
+fn apply_pre_roll(app: &AppHandle) {
+    let settings = app.state::<Mutex<SettingsState>>().lock().settings.clone();
+    {
+        let state = app.state::<Mutex<PreRollState>>();
+        let mut pre_roll = state.lock();
+        if let Some(handle) = pre_roll.handle.take() {
+            handle.stop();
+        }
+        if settings.pre_roll {
+            let input = audio::InputSettings::from_settings(&settings);
+            let length = std::time::Duration::from_millis(settings.pre_roll_ms as u64);
+            match audio::start_pre_roll(Arc::new(audio::CpalSource), &input, length) {
+                Ok(handle) => pre_roll.handle = Some(handle),
+                Err(e) => {
+                    tracing::warn!("Failed to open pre-roll input: {}", e);
+                    let _ = app.emit("error", format!("Pre-roll is off: {}", e));
+                }
+            }
+        }
+    }
+
+    let recording = app.state::<Mutex<AppState>>().lock().is_recording;
+    update_tray(app, recording);
+}
+
+/// Try pre-roll again if it is on but its input couldn't be opened, e.g.
+/// because no microphone was connected at startup.
+fn retry_pre_roll(app: &AppHandle) {
+    let wanted = app.state::<Mutex<SettingsState>>().lock().settings.pre_roll;
+    let missing = app.state::<Mutex<PreRollState>>().lock().handle.is_none();
+    let recording = app.state::<Mutex<AppState>>().lock().is_recording;
+    if wanted && missing && !recording {
+        apply_pre_roll(app);
+    }
+}
+
+/// Pick up an edit to the settings file made outside the app: apply what
+/// changed and tell the UI to refresh. The app's own saves reload to the
+/// settings already in memory and are ignored.
+fn reload_settings(app: &AppHandle) {
+    let settings_state = app.state::<Mutex<SettingsState>>();
+    let mut state_guard = settings_state.lock();
+
+    // Read while holding the lock so no command can save in between
+    let loaded = match settings::reload_settings() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            tracing::warn!("Not reloading settings: {}", e);
+            let _ = app.emit(
+                "error",
+                format!("Settings file has errors and was not reloaded: {}", e),
+            );
+            return;
+        }
+    };
+    if loaded.settings == state_guard.settings {
+        return;
+    }
+    tracing::info!("Settings file changed on disk, reloading");
+
+    let mut warnings = loaded.warnings;
+    let old = std::mem::replace(&mut state_guard.settings, loaded.settings);
+    drop(state_guard);
+
+    apply_settings_change(app, &old, &mut warnings);
+    settings_state.lock().warnings = warnings.clone();
+    let _ = app.emit("settings-changed", warnings);
+}
+
+/// Bring the running app in line with settings that were replaced wholesale
+/// (reloaded from disk or imported), adding any problems to `warnings`.
+fn apply_settings_change(app: &AppHandle, old: &Settings, warnings: &mut Vec<String>) {
+    let settings_state = app.state::<Mutex<SettingsState>>();
+    let new = settings_state.lock().settings.clone();
+
+    // Registering may need the main thread, so it happens outside the lock
+    if new.shortcut != old.shortcut {
+        if let Err(e) = register_shortcut(app, &new.shortcut) {
+            warnings.push(format!(
+                "{}; keeping {}.",
+                e,
+                settings::format_shortcut_display(&old.shortcut)
+            ));
+            settings_state.lock().settings.shortcut = old.shortcut.clone();
+        }
+    }
+    if new.secret_store != old.secret_store {
+        app.state::<Mutex<ApiKeyCache>>()
+            .lock()
+            .replace_store(secrets::open_store(new.secret_store), &new);
+    } else if new.api_key_sources != old.api_key_sources || new.credentials != old.credentials {
+        app.state::<Mutex<ApiKeyCache>>()
+            .lock()
+            .reload_external(&new);
+    }
+    if new.transcription.provider != old.transcription.provider {
+        let cache = app.state::<Mutex<ApiKeyCache>>();
+        if get_api_key_from_cache(&cache.lock(), &new, &new.transcription.provider, None).is_none()
+        {
+            warnings.push(format!(
+                "No API key configured for {}.",
+                settings::get_provider_id(&new.transcription.provider)
+            ));
+        }
+    }
+
+    let input_changed = new.pre_roll != old.pre_roll
+        || new.pre_roll_ms != old.pre_roll_ms
+        || new.audio_input_devices != old.audio_input_devices
+        || new.audio_device_configs != old.audio_device_configs;
+    if input_changed {
+        apply_pre_roll(app);
+    }
+}
+
+/// End the current recording (key released, or the length limit reached)
+/// and transcribe it in the background.
+fn stop_recording(app: &AppHandle) {
+    // Stop audio level polling and get original app.
+    // Lock ordering: recorder_state before app_state, as in the shortcut handler.
+    let (handle, original_app, dictation) = {
+        let recorder_state = app.state::<Mutex<RecorderState>>();
+        let mut state = recorder_state.lock();
+        state.stop_polling.store(true, Ordering::Relaxed);
+        (
+            state.handle.take(),
+            state.original_app.clone(),
+            std::mem::replace(&mut state.dictation, tracing::Span::none()),
+        )
+    };
+
+    app.state::<Mutex<AppState>>().lock().is_recording = false;
+    update_tray(app, false);
+    let _ = app.emit("recording-status", false);
+
+    if let Some(handle) = handle {
+        let app_clone = app.clone();
+        let file_write = tracing::info_span!(
+            target: telemetry::TIMING_TARGET,
+            parent: &dictation,
+            "file_write"
+        );
+        std::thread::spawn(move || match handle.stop() {
+            Ok(recording) => {
+                drop(file_write);
+                tauri::async_runtime::block_on(
+                    handle_recording_stop(app_clone, recording, original_app).instrument(dictation),
+                );
+            }
+            Err(e) => {
+                dictation.record("outcome", "recording_failed");
+                tracing::error!("Failed to stop recording: {}", e);
+                let _ = app_clone.emit("error", format!("Failed to stop recording: {}", e));
+                destroy_indicator_window(&app_clone);
+            }
+        });
+    }
+}
+
+/// What a transcription needs from settings for the selected provider.
+struct TranscriptionSetup {
+    provider: &'static str,
+    api_key: Option<String>,
+    endpoint: &'static str,
+    model: &'static str,
+    filter: hallucination::HallucinationFilter,
+    usage: settings::UsageConfig,
+}
+
+/// Get settings and API key for the selected provider (from cache, never
+/// keychain), using the credential picked for `original_app` if any.
+fn transcription_setup(app: &AppHandle, original_app: Option<&str>) -> TranscriptionSetup {
+    let settings_state = app.state::<Mutex<SettingsState>>();
+    let settings = &settings_state.lock().settings;
+
+    let provider = &settings.transcription.provider;
+    let cache = app.state::<Mutex<ApiKeyCache>>();
+    let api_key = get_api_key_from_cache(&cache.lock(), settings, provider, original_app);
+    TranscriptionSetup {
+        provider: settings::get_provider_id(provider),
+        api_key,
+        endpoint: settings::get_endpoint_for_provider(provider),
+        model: settings::get_model_for_provider(provider),
+        filter: hallucination::HallucinationFilter::new(&settings.hallucination_phrases),
+        usage: settings.usage.clone(),
+    }
+}
+
 async fn handle_recording_stop(
     app: AppHandle,
-    audio_path: std::path::PathBuf,
-    original_app: Option<String>,
+    recording: audio::Recording,
+    original_app: Option<cursor::FrontmostApp>,
 ) {
+    let audio_path = recording.path;
+    tracing::info!("Recorded from {}", recording.devices.join(" -> "));
+
     // Helper: check if a NEW recording is in progress (our indicator may have been reused).
     // When true, we must not modify the indicator or paste — the user is re-recording.
     let new_recording_active = || -> bool { app.state::<Mutex<AppState>>().lock().is_recording };
 
     // Update indicator to processing state (only if no new recording started)
     if !new_recording_active() {
-        eprintln!("[Scrivano] Emitting indicator-state: processing");
+        tracing::debug!("Emitting indicator-state: processing");
         let _ = app.emit("indicator-state", "processing");
     }
 
-    // Get settings and API key for the selected provider (from cache, never keychain)
-    let (api_key, endpoint, model) = {
-        let settings_state = app.state::<Mutex<SettingsState>>();
-        let settings = &settings_state.lock().settings;
-
-        let provider = &settings.transcription.provider;
-        let cache = app.state::<Mutex<ApiKeyCache>>();
-        let api_key = get_api_key_from_cache(&cache.lock(), provider);
-        let endpoint = settings::get_endpoint_for_provider(provider);
-        let model = settings::get_model_for_provider(provider);
+    let TranscriptionSetup {
+        provider,
+        api_key,
+        endpoint,
+        model,
+        filter,
+        usage: usage_config,
+    } = transcription_setup(&app, original_app.as_ref().map(|a| a.app_id.as_str()));
 
-        (api_key, endpoint, model)
-    };
+    // The timing span this future was instrumented with
+    let dictation = tracing::Span::current();
+    dictation.record("provider", provider);
+    dictation.record("model", model);
 
     let api_key = match api_key {
         Some(key) => key,
         None => {
             let err = "No API key configured. Please add an API key in Settings.";
-            eprintln!("API key error: {}", err);
+            dictation.record("outcome", "no_api_key");
+            tracing::error!("API key error: {}", err);
             let _ = app.emit("error", err);
             if !new_recording_active() {
                 destroy_indicator_window(&app);
@@ -664,24 +1546,54 @@ async fn handle_recording_stop(
         }
     };
 
+    match usage::check_budget(&usage_config) {
+        Ok(None) => {}
+        Ok(Some(warning)) => {
+            let _ = app.emit("budget-warning", warning);
+        }
+        Err(e) => {
+            dictation.record("outcome", "over_budget");
+            tracing::warn!("Budget exceeded: {}", e);
+            let _ = app.emit("error", e);
+            if !new_recording_active() {
+                destroy_indicator_window(&app);
+            }
+            return;
+        }
+    }
+
     // Log audio file info for debugging
     if let Ok(meta) = std::fs::metadata(&audio_path) {
         let size_kb = meta.len() as f64 / 1024.0;
-        eprintln!("[Scrivano] Audio file: {:.1} KB", size_kb);
+        tracing::info!(bytes = meta.len(), "Audio file: {:.1} KB", size_kb);
     }
 
     let _ = app.emit("transcription-status", "Transcribing...");
 
     let request = transcription::TranscriptionRequest {
         audio_path: &audio_path,
+        provider,
         api_key: &api_key,
         endpoint,
         model,
+        language: transcription::DEFAULT_LANGUAGE,
+        // Segments carry the confidence fields the hallucination filter needs
+        timestamps: transcription::Timestamps::Segments,
     };
 
-    match transcription::transcribe_audio(request).await {
-        Ok(text) => {
-            app.state::<Mutex<AppState>>().lock().last_transcription = text.clone();
+    let result = transcription::transcribe_audio(request)
+        .await
+        .and_then(|transcript| filter.apply(transcript, Some(&recording.speech)));
+
+    match result {
+        Ok(transcript) => {
+            let text = transcript.text;
+            {
+                let app_state = app.state::<Mutex<AppState>>();
+                let mut state = app_state.lock();
+                state.last_transcription = text.clone();
+                state.last_input_devices = recording.devices.clone();
+            }
             let _ = app.emit("transcription", text.clone());
 
             // Only hide indicator and paste if no new recording started
@@ -691,34 +1603,52 @@ async fn handle_recording_stop(
                 // Paste to the original app (this will re-activate it).
                 // The paste functions save and restore the clipboard so the
                 // transcription text does not remain in the user's clipboard.
-                let paste_result = if let Some(ref bundle_id) = original_app {
-                    paste::paste_to_app(&text, bundle_id)
+                let paste_result = tracing::info_span!(target: telemetry::TIMING_TARGET, "paste")
+                    .in_scope(|| {
+                        if let Some(ref original) = original_app {
+                            paste::paste_to_app(&text, &original.target)
+                        } else {
+                            paste::set_clipboard_and_paste(&text)
+                        }
+                    });
+                let outcome = if paste_result.is_ok() {
+                    "ok"
                 } else {
-                    paste::set_clipboard_and_paste(&text)
+                    "paste_failed"
                 };
+                dictation.record("outcome", outcome);
 
                 match &paste_result {
                     Ok(()) => {
+                        let target_app = original_app.as_ref().map(|a| a.app_id.as_str());
                         let _ = app.emit(
                             "paste",
                             serde_json::json!({
                                 "text_length": text.len(),
-                                "target_app": original_app.as_deref().unwrap_or("unknown"),
+                                "target_app": target_app.unwrap_or("unknown"),
+                                "input_devices": recording.devices,
                             }),
                         );
                     }
                     Err(e) => {
-                        eprintln!("Failed to paste: {}", e);
+                        tracing::error!("Failed to paste: {}", e);
                         let _ = app.emit("error", format!("Failed to paste: {}", e));
                     }
                 }
             } else {
-                eprintln!("[Scrivano] Skipping paste — new recording in progress");
+                dictation.record("outcome", "paste_skipped");
+                tracing::info!("Skipping paste — new recording in progress");
             }
         }
         Err(e) => {
-            eprintln!("Transcription failed: {}", e);
-            let _ = app.emit("error", format!("Transcription failed: {}", e));
+            dictation.record("outcome", "transcription_failed");
+            tracing::error!("Transcription failed: {}", e);
+            let message = if e == hallucination::NO_SPEECH_ERROR {
+                format!("{} — hold the key longer and speak clearly", e)
+            } else {
+                format!("Transcription failed: {}", e)
+            };
+            let _ = app.emit("error", message);
             if !new_recording_active() {
                 destroy_indicator_window(&app);
             }
@@ -727,23 +1657,34 @@ async fn handle_recording_stop(
 
     // Clean up the recording file
     if let Err(e) = std::fs::remove_file(&audio_path) {
-        eprintln!(
-            "[Scrivano] Failed to delete recording {}: {}",
-            audio_path.display(),
-            e
-        );
+        tracing::warn!("Failed to delete recording {}: {}", audio_path.display(), e);
     }
 }
 
+/// Entry point for the headless `scrivano-cli` binary.
+pub fn run_cli(args: &[String]) -> std::process::ExitCode {
+    cli::run(args)
+}
+
 pub fn run() {
+    // Kept until the app exits so buffered log lines are flushed
+    let _log_guard = telemetry::init();
+
     // Load settings at startup
-    let loaded_settings = settings::load_settings();
+    let settings::LoadedSettings {
+        settings: loaded_settings,
+        warnings: settings_warnings,
+    } = settings::load_settings_with_warnings();
     let shortcut_config = loaded_settings.shortcut.clone();
 
-    // Read API keys from keychain exactly once, cache in memory for the
-    // lifetime of the process.  This is the single point where keychain
-    // access happens — all later reads go through the cache.
-    let api_key_cache = ApiKeyCache::load_from_keychain();
+    // Resolve API keys (environment, key command or file, then the secret
+    // store) once and cache them in memory for the lifetime of the process.
+    // This is the single point where keychain access happens — all later
+    // reads go through the cache.
+    let api_key_cache = ApiKeyCache::load(
+        secrets::open_store(loaded_settings.secret_store),
+        &loaded_settings,
+    );
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
@@ -757,6 +1698,7 @@ pub fn run() {
             handle: None,
             stop_polling: Arc::new(AtomicBool::new(false)),
             original_app: None,
+            dictation: tracing::Span::none(),
         }))
         .manage(Mutex::new(ShortcutSettings {
             current_shortcut: None,
@@ -764,15 +1706,22 @@ pub fn run() {
         }))
         .manage(Mutex::new(SettingsState {
             settings: loaded_settings,
+            warnings: settings_warnings,
         }))
         .manage(Mutex::new(AudioPreviewState {
             handle: None,
-            levels: Arc::new(Mutex::new(vec![0.15; 3])),
+            levels: Arc::new(Mutex::new(meter::AudioLevels::idle(
+                meter::DEFAULT_BAR_COUNT,
+            ))),
             stop_polling: Arc::new(AtomicBool::new(false)),
+            test_recording: None,
         }))
+        .manage(Mutex::new(PreRollState { handle: None }))
         .manage(Arc::new(AtomicBool::new(false)))
         .manage(Mutex::new(api_key_cache))
+        .manage(Mutex::new(BatchState::default()))
         .setup(move |app| {
+            #[cfg(target_os = "macos")]
             app.set_activation_policy(ActivationPolicy::Accessory);
 
             // Hide window when it loses focus (click outside),
@@ -801,6 +1750,14 @@ pub fn run() {
                     "../icons/tray-idle-44.png"
                 ))
                 .expect("Failed to load tray idle 44px icon"),
+                listening_1x: tauri::image::Image::from_bytes(include_bytes!(
+                    "../icons/tray-listening-22.png"
+                ))
+                .expect("Failed to load tray listening 22px icon"),
+                listening_2x: tauri::image::Image::from_bytes(include_bytes!(
+                    "../icons/tray-listening-44.png"
+                ))
+                .expect("Failed to load tray listening 44px icon"),
                 recording_1x: tauri::image::Image::from_bytes(include_bytes!(
                     "../icons/tray-recording-22.png"
                 ))
@@ -811,8 +1768,8 @@ pub fn run() {
                 .expect("Failed to load tray recording 44px icon"),
             };
 
-            let tray = TrayIconBuilder::new()
-                .icon(tray_icons.select(app.handle(), false))
+            TrayIconBuilder::with_id(TRAY_ID)
+                .icon(tray_icons.select(app.handle(), TrayState::Idle))
                 .menu(&menu)
                 .show_menu_on_left_click(false)
                 .on_menu_event(|app, event| {
@@ -853,9 +1810,10 @@ pub fn run() {
                     }
                 })
                 .build(app)?;
+            app.manage(tray_icons);
 
-            let tray_handle = tray.clone();
-            let tray_icons_for_handler = tray_icons.clone();
+            // Open the pre-roll input if the user opted in; the tray shows it
+            apply_pre_roll(app.handle());
 
             // Build shortcut from loaded config
             let parsed_modifiers = settings::parse_modifiers(&shortcut_config.modifiers);
@@ -877,11 +1835,6 @@ pub fn run() {
                         let recorder_state = app.state::<Mutex<RecorderState>>();
                         let app_state = app.state::<Mutex<AppState>>();
 
-                        let set_tray_icon = |recording: bool| {
-                            let icon = tray_icons_for_handler.select(app, recording);
-                            let _ = tray_handle.set_icon(Some(icon));
-                        };
-
                         match event.state() {
                             ShortcutState::Pressed => {
                                 // Save the frontmost app for later focus restoration.
@@ -889,35 +1842,85 @@ pub fn run() {
                                 // the global shortcut can briefly activate Scrivano, and
                                 // trying to send AppleScript to ourselves deadlocks.
                                 let own_bundle_id = "com.tommyross.scrivano";
-                                let original_app = cursor::get_frontmost_bundle_id()
-                                    .filter(|id| id != own_bundle_id);
+                                let original_app = cursor::get_frontmost_app()
+                                    .filter(|a| a.app_id != own_bundle_id);
 
-                                let audio_device = {
+                                let audio_input = {
                                     let ss = app.state::<Mutex<SettingsState>>();
                                     let guard = ss.lock();
-                                    guard.settings.audio_input_device.clone()
+                                    audio::InputSettings::from_settings(&guard.settings)
                                 };
 
-                                match audio::start_recording(audio_device.as_deref()) {
-                                    Ok(handle) => {
+                                let dictation = tracing::info_span!(
+                                    target: telemetry::TIMING_TARGET,
+                                    telemetry::DICTATION_SPAN,
+                                    provider = tracing::field::Empty,
+                                    model = tracing::field::Empty,
+                                    outcome = tracing::field::Empty,
+                                );
+                                let stream_start = Mutex::new(Some(tracing::info_span!(
+                                    target: telemetry::TIMING_TARGET,
+                                    parent: &dictation,
+                                    "stream_start"
+                                )));
+
+                                let app_for_events = app.clone();
+                                let on_event = move |event: audio::RecordingEvent| match event {
+                                    audio::RecordingEvent::Started => {
+                                        drop(stream_start.lock().take());
+                                    }
+                                    audio::RecordingEvent::DeviceSwitched(switch) => {
+                                        tracing::warn!(
+                                            "Input device '{}' lost, now recording from {:?}",
+                                            switch.from,
+                                            switch.to
+                                        );
+                                        let _ =
+                                            app_for_events.emit("audio-device-switched", &switch);
+                                    }
+                                    audio::RecordingEvent::Elapsed(elapsed) => {
+                                        let _ = app_for_events.emit("recording-elapsed", &elapsed);
+                                    }
+                                    audio::RecordingEvent::LimitWarning { remaining_secs } => {
+                                        let _ = app_for_events
+                                            .emit("recording-limit-warning", remaining_secs);
+                                    }
+                                    audio::RecordingEvent::LimitReached => {
+                                        tracing::info!("Recording limit reached, stopping");
+                                        stop_recording(&app_for_events);
+                                    }
+                                };
 
+                                // With pre-roll on, the recording takes over the open
+                                // stream and starts with the audio before the press
+                                let source: Arc<dyn audio::AudioSource> =
+                                    match &app.state::<Mutex<PreRollState>>().lock().handle {
+                                        Some(pre_roll) => {
+                                            pre_roll.source(Arc::new(audio::CpalSource))
+                                        }
+                                        None => Arc::new(audio::CpalSource),
+                                    };
+                                match audio::start_recording(source, &audio_input, on_event) {
+                                    Ok(handle) => {
                                         // Create or reuse indicator window at mouse position.
                                         // If reused, listeners are already mounted (skip ready handshake).
                                         // Window ref is unused — Tauri owns the window lifecycle internally.
-                                        let (_indicator_window, is_new_window) = create_indicator_window(app);
+                                        let (_indicator_window, is_new_window) =
+                                            create_indicator_window(app);
 
                                         // Register the ready listener BEFORE the window can emit.
                                         // If reusing, mark ready immediately — the window is already live.
                                         let ready = Arc::new(AtomicBool::new(!is_new_window));
                                         let ready_clone = Arc::clone(&ready);
-                                        let listener_id = app.listen("indicator-ready", move |_| {
-                                            ready_clone.store(true, Ordering::Relaxed);
-                                        });
+                                        let listener_id =
+                                            app.listen("indicator-ready", move |_| {
+                                                ready_clone.store(true, Ordering::Relaxed);
+                                            });
 
                                         // Immediately re-activate the original app so focus isn't stolen.
                                         // Use the fast variant (no 50ms sleep) since we're not pasting.
-                                        if let Some(ref bundle_id) = original_app {
-                                            let _ = paste::activate_app_fast(bundle_id);
+                                        if let Some(ref original) = original_app {
+                                            let _ = paste::activate_app_fast(&original.target);
                                         }
 
                                         // Get the audio levels Arc before storing the handle
@@ -930,6 +1933,7 @@ pub fn run() {
                                             state.stop_polling = Arc::clone(&stop_flag);
                                             state.handle = Some(handle);
                                             state.original_app = original_app;
+                                            state.dictation = dictation;
                                         }
 
                                         // Start polling thread for audio levels.
@@ -950,18 +1954,28 @@ pub fn run() {
                                                 && start.elapsed().as_millis() < 3000
                                                 && !stop_flag.load(Ordering::Relaxed)
                                             {
-                                                std::thread::sleep(std::time::Duration::from_millis(20));
+                                                std::thread::sleep(
+                                                    std::time::Duration::from_millis(20),
+                                                );
                                             }
+                                            let waited_ms = start.elapsed().as_millis() as u64;
                                             if ready.load(Ordering::Relaxed) {
-                                                eprintln!("[Scrivano] Indicator signaled ready after {}ms", start.elapsed().as_millis());
+                                                tracing::info!(
+                                                    waited_ms,
+                                                    "Indicator signaled ready after {}ms",
+                                                    waited_ms
+                                                );
                                             } else {
-                                                eprintln!("[Scrivano] Indicator ready timeout after {}ms", start.elapsed().as_millis());
+                                                tracing::warn!(
+                                                    waited_ms,
+                                                    "Indicator ready timeout after {}ms",
+                                                    waited_ms
+                                                );
                                             }
                                             app_for_unlisten.unlisten(listener_id);
 
                                             while !stop_flag.load(Ordering::Relaxed) {
-                                                let levels =
-                                                    audio_levels_arc.lock().clone();
+                                                let levels = audio_levels_arc.lock().clone();
                                                 let _ = app_clone.emit("audio-levels", &levels);
                                                 std::thread::sleep(
                                                     std::time::Duration::from_millis(50),
@@ -970,11 +1984,12 @@ pub fn run() {
                                         });
 
                                         app_state.lock().is_recording = true;
-                                        set_tray_icon(true);
+                                        update_tray(app, true);
                                         let _ = app.emit("recording-status", true);
                                     }
                                     Err(e) => {
-                                        eprintln!("Failed to start recording: {}", e);
+                                        dictation.record("outcome", "recording_failed");
+                                        tracing::error!("Failed to start recording: {}", e);
                                         let _ = app.emit(
                                             "error",
                                             format!("Failed to start recording: {}", e),
@@ -982,41 +1997,7 @@ pub fn run() {
                                     }
                                 }
                             }
-                            ShortcutState::Released => {
-                                // Stop audio level polling and get original app
-                                let original_app;
-                                {
-                                    let state = recorder_state.lock();
-                                    state.stop_polling.store(true, Ordering::Relaxed);
-                                    original_app = state.original_app.clone();
-                                }
-
-                                let handle = recorder_state.lock().handle.take();
-                                app_state.lock().is_recording = false;
-                                set_tray_icon(false);
-                                let _ = app.emit("recording-status", false);
-
-                                if let Some(handle) = handle {
-                                    let app_clone = app.clone();
-                                    std::thread::spawn(move || match handle.stop() {
-                                        Ok(path) => {
-                                            tauri::async_runtime::block_on(handle_recording_stop(
-                                                app_clone,
-                                                path,
-                                                original_app,
-                                            ));
-                                        }
-                                        Err(e) => {
-                                            eprintln!("Failed to stop recording: {}", e);
-                                            let _ = app_clone.emit(
-                                                "error",
-                                                format!("Failed to stop recording: {}", e),
-                                            );
-                                            destroy_indicator_window(&app_clone);
-                                        }
-                                    });
-                                }
-                            }
+                            ShortcutState::Released => stop_recording(app),
                         }
                     })
                     .build(),
@@ -1025,6 +2006,18 @@ pub fn run() {
             // Prompt for accessibility permission once at startup
             cursor::prompt_accessibility_once();
 
+            // Let the settings UI refresh its device list on hot-plug, and
+            // open pre-roll once a device it couldn't open turns up
+            let devices_handle = app.handle().clone();
+            audio::watch_input_devices(move |_| {
+                let _ = devices_handle.emit("devices-changed", list_audio_input_devices());
+                retry_pre_roll(&devices_handle);
+            });
+
+            // Follow edits to settings.json made outside the app
+            let settings_handle = app.handle().clone();
+            settings::watch_settings_file(move || reload_settings(&settings_handle));
+
             // Register the shortcut and store it in state
             app.global_shortcut().register(shortcut)?;
             {
@@ -1036,6 +2029,7 @@ pub fn run() {
         })
         .invoke_handler(tauri::generate_handler![
             get_transcription,
+            get_transcription_devices,
             get_recording_status,
             copy_to_clipboard,
             paste_text,
@@ -1045,15 +2039,40 @@ pub fn run() {
             get_shortcut,
             set_shortcut,
             get_api_key_status,
+            test_api_key,
             set_api_key,
+            list_credentials,
+            add_credential,
+            remove_credential,
+            select_credential,
             get_available_providers,
             get_transcription_settings,
+            get_settings_warnings,
+            export_settings,
+            import_settings,
+            get_settings_provenance,
             set_transcription_provider,
             list_audio_input_devices,
             get_audio_input_device,
             set_audio_input_device,
+            get_preferred_audio_devices,
+            set_preferred_audio_devices,
+            get_audio_device_config,
+            set_audio_device_config,
+            get_audio_processing,
+            set_audio_processing,
+            get_pre_roll,
+            set_pre_roll,
             start_audio_preview,
             stop_audio_preview,
+            run_mic_test,
+            play_mic_test,
+            transcribe_files,
+            cancel_batch_transcription,
+            get_usage_stats,
+            set_usage_budget,
+            get_recent_timings,
+            export_diagnostics,
             get_open_on_login,
             set_open_on_login,
         ])
@@ -0,0 +1,439 @@
+//! Spectral level meter for the recording indicator and the settings preview.
+//!
+//! Mono samples are windowed in blocks of `FFT_SIZE`, transformed, and the
+//! bin energies summed into log-spaced bands across the speech range. Bars
+//! rise instantly and fall at `DECAY_DB_PER_SEC`, with a peak-hold marker
+//! per bar and a clipping flag that stays lit for `CLIP_HOLD_SECS`.
+//!
+//! For setting up a microphone the meter also estimates the noise floor
+//! (minimum block level, rising by at most `NOISE_FLOOR_RISE_DB_PER_SEC`
+//! so speech pauses keep pulling it back down) and counts clipped samples.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Samples per analysis block (~11 ms at 48 kHz, 32 ms at 16 kHz).
+pub const FFT_SIZE: usize = 512;
+
+/// Number of bars when not configured.
+pub const DEFAULT_BAR_COUNT: usize = 3;
+
+/// Frequency range the bars cover; speech energy sits inside it.
+const MIN_FREQ: f32 = 80.0;
+const MAX_FREQ: f32 = 8000.0;
+
+/// Levels at or below this show as an empty bar.
+pub const FLOOR_DBFS: f32 = -60.0;
+
+const DECAY_DB_PER_SEC: f32 = 30.0;
+const PEAK_HOLD_SECS: f32 = 1.0;
+const CLIP_HOLD_SECS: f32 = 1.0;
+
+/// Sample magnitude treated as clipping (the last step below full scale,
+/// since 16-bit input tops out at 32767/32768).
+const CLIP_THRESHOLD: f32 = 0.999;
+
+/// How fast the noise floor estimate may rise when the input gets louder.
+/// Slow enough that speech barely lifts it between pauses.
+const NOISE_FLOOR_RISE_DB_PER_SEC: f32 = 2.0;
+
+/// Snapshot emitted with `audio-levels` and `audio-preview-levels`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AudioLevels {
+    /// Bar heights (0–1, mapped from `FLOOR_DBFS`..0 dBFS), lowest band first.
+    pub bars: Vec<f32>,
+    /// Peak-hold position of each bar, same scale as `bars`.
+    pub peaks: Vec<f32>,
+    /// RMS level of the latest block in dBFS.
+    pub rms_dbfs: f32,
+    /// Largest sample magnitude of the latest block in dBFS.
+    pub peak_dbfs: f32,
+    /// Whether a sample reached full scale within the last `CLIP_HOLD_SECS`.
+    pub clipping: bool,
+    /// Estimated background noise level in dBFS.
+    pub noise_floor_dbfs: f32,
+    /// Samples at full scale since the meter started.
+    pub clipped_samples: u64,
+}
+
+impl AudioLevels {
+    /// Levels for silence, shown before any audio arrives.
+    pub fn idle(bar_count: usize) -> Self {
+        Self {
+            bars: vec![0.0; bar_count],
+            peaks: vec![0.0; bar_count],
+            rms_dbfs: FLOOR_DBFS,
+            peak_dbfs: FLOOR_DBFS,
+            clipping: false,
+            noise_floor_dbfs: FLOOR_DBFS,
+            clipped_samples: 0,
+        }
+    }
+}
+
+fn to_dbfs(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return FLOOR_DBFS;
+    }
+    (20.0 * amplitude.log10()).max(FLOOR_DBFS)
+}
+
+fn bar_height(dbfs: f32) -> f32 {
+    ((dbfs - FLOOR_DBFS) / -FLOOR_DBFS).clamp(0.0, 1.0)
+}
+
+/// In-place iterative radix-2 FFT. `re.len()` must be a power of two.
+pub fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -std::f32::consts::TAU / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// FFT bin ranges (`start..end`) for `bar_count` log-spaced bands between
+/// `MIN_FREQ` and `MAX_FREQ` (or Nyquist). Every band gets at least one bin.
+fn band_bins(sample_rate: u32, bar_count: usize) -> Vec<(usize, usize)> {
+    let bin_hz = sample_rate as f32 / FFT_SIZE as f32;
+    let max_freq = MAX_FREQ.min(sample_rate as f32 / 2.0);
+    let ratio = (max_freq / MIN_FREQ).powf(1.0 / bar_count as f32);
+
+    let mut bands = Vec::with_capacity(bar_count);
+    let mut start = ((MIN_FREQ / bin_hz).round() as usize).max(1);
+    for i in 1..=bar_count {
+        let edge = MIN_FREQ * ratio.powi(i as i32);
+        let end = ((edge / bin_hz).round() as usize)
+            .max(start + 1)
+            .min(FFT_SIZE / 2);
+        bands.push((start, end.max(start + 1)));
+        start = end;
+    }
+    bands
+}
+
+pub struct LevelMeter {
+    bands: Vec<(usize, usize)>,
+    hann: Vec<f32>,
+    /// Sum of squared window coefficients, to normalize band energy.
+    window_power: f32,
+    block: Vec<f32>,
+    /// Displayed level of each bar in dBFS.
+    bar_dbfs: Vec<f32>,
+    /// Held peak (dBFS) and remaining hold time in seconds, per bar.
+    peak_hold: Vec<(f32, f32)>,
+    clip_remaining: f32,
+    clipped_samples: u64,
+    /// Noise floor estimate in dBFS, once a block has been seen.
+    noise_floor: Option<f32>,
+    block_secs: f32,
+    levels: Arc<Mutex<AudioLevels>>,
+}
+
+impl LevelMeter {
+    /// Meter publishing into `levels`, which the event poller reads.
+    pub fn new(sample_rate: u32, bar_count: usize, levels: Arc<Mutex<AudioLevels>>) -> Self {
+        let bar_count = bar_count.max(1);
+        let hann: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        *levels.lock() = AudioLevels::idle(bar_count);
+
+        Self {
+            bands: band_bins(sample_rate, bar_count),
+            window_power: hann.iter().map(|w| w * w).sum(),
+            hann,
+            block: Vec::with_capacity(FFT_SIZE),
+            bar_dbfs: vec![FLOOR_DBFS; bar_count],
+            peak_hold: vec![(FLOOR_DBFS, 0.0); bar_count],
+            clip_remaining: 0.0,
+            clipped_samples: 0,
+            noise_floor: None,
+            block_secs: FFT_SIZE as f32 / sample_rate.max(1) as f32,
+            levels,
+        }
+    }
+
+    /// Add one mono sample; levels are published once per full block.
+    pub fn push(&mut self, sample: f32) {
+        self.block.push(sample);
+        if self.block.len() == FFT_SIZE {
+            self.analyze();
+            self.block.clear();
+        }
+    }
+
+    fn analyze(&mut self) {
+        let peak = self.block.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+        let rms = (self.block.iter().map(|s| s * s).sum::<f32>() / FFT_SIZE as f32).sqrt();
+
+        let mut re: Vec<f32> = self
+            .block
+            .iter()
+            .zip(&self.hann)
+            .map(|(s, w)| s * w)
+            .collect();
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im);
+
+        // Band RMS via Parseval: positive-frequency power counted twice,
+        // normalized by the window so a sine reads its own RMS
+        let decay = DECAY_DB_PER_SEC * self.block_secs;
+        for (i, &(start, end)) in self.bands.iter().enumerate() {
+            let power: f32 = (start..end).map(|k| re[k] * re[k] + im[k] * im[k]).sum();
+            let band_rms = (2.0 * power / (FFT_SIZE as f32 * self.window_power)).sqrt();
+            let dbfs = to_dbfs(band_rms);
+
+            let bar = &mut self.bar_dbfs[i];
+            *bar = dbfs.max(*bar - decay);
+
+            let (held, remaining) = &mut self.peak_hold[i];
+            if *bar >= *held {
+                *held = *bar;
+                *remaining = PEAK_HOLD_SECS;
+            } else if *remaining > 0.0 {
+                *remaining -= self.block_secs;
+            } else {
+                *held = (*held - decay).max(*bar);
+            }
+        }
+
+        if peak >= CLIP_THRESHOLD {
+            self.clip_remaining = CLIP_HOLD_SECS;
+            self.clipped_samples += self
+                .block
+                .iter()
+                .filter(|s| s.abs() >= CLIP_THRESHOLD)
+                .count() as u64;
+        } else {
+            self.clip_remaining = (self.clip_remaining - self.block_secs).max(0.0);
+        }
+
+        let rms_dbfs = to_dbfs(rms);
+        let noise_floor = match self.noise_floor {
+            Some(floor) => rms_dbfs.min(floor + NOISE_FLOOR_RISE_DB_PER_SEC * self.block_secs),
+            None => rms_dbfs,
+        };
+        self.noise_floor = Some(noise_floor);
+
+        *self.levels.lock() = AudioLevels {
+            bars: self.bar_dbfs.iter().map(|&db| bar_height(db)).collect(),
+            peaks: self
+                .peak_hold
+                .iter()
+                .map(|&(db, _)| bar_height(db))
+                .collect(),
+            rms_dbfs,
+            peak_dbfs: to_dbfs(peak),
+            clipping: self.clip_remaining > 0.0,
+            noise_floor_dbfs: noise_floor,
+            clipped_samples: self.clipped_samples,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn meter(bar_count: usize) -> (LevelMeter, Arc<Mutex<AudioLevels>>) {
+        let levels = Arc::new(Mutex::new(AudioLevels::idle(0)));
+        (
+            LevelMeter::new(RATE, bar_count, Arc::clone(&levels)),
+            levels,
+        )
+    }
+
+    fn feed_tone(meter: &mut LevelMeter, freq: f32, amplitude: f32, secs: f32) {
+        for i in 0..(RATE as f32 * secs) as usize {
+            let t = i as f32 / RATE as f32;
+            meter.push(amplitude * (std::f32::consts::TAU * freq * t).sin());
+        }
+    }
+
+    fn loudest_bar(levels: &AudioLevels) -> usize {
+        (0..levels.bars.len())
+            .max_by(|&a, &b| levels.bars[a].total_cmp(&levels.bars[b]))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fft_single_bin() {
+        let mut re: Vec<f32> = (0..64)
+            .map(|i| (std::f32::consts::TAU * 4.0 * i as f32 / 64.0).cos())
+            .collect();
+        let mut im = vec![0.0; 64];
+        fft(&mut re, &mut im);
+        assert!((re[4] - 32.0).abs() < 1e-3);
+        assert!((re[60] - 32.0).abs() < 1e-3);
+        assert!(re[5].abs() < 1e-3 && im[4].abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_band_bins_cover_range_without_gaps() {
+        for rate in [16000, 44100, 48000] {
+            for count in [1, 3, 8, 16] {
+                let bands = band_bins(rate, count);
+                assert_eq!(bands.len(), count);
+                for pair in bands.windows(2) {
+                    assert!(pair[0].1 <= pair[1].0, "{:?} at {} Hz", bands, rate);
+                }
+                assert!(bands
+                    .iter()
+                    .all(|&(start, end)| start < end && end <= FFT_SIZE / 2));
+            }
+        }
+    }
+
+    #[test]
+    fn test_tones_light_matching_band() {
+        for (freq, band) in [(150.0, 0), (1000.0, 1), (5000.0, 2)] {
+            let (mut meter, levels) = meter(3);
+            feed_tone(&mut meter, freq, 0.5, 0.2);
+            let levels = levels.lock().clone();
+            assert_eq!(loudest_bar(&levels), band, "{} Hz: {:?}", freq, levels);
+            for (i, &bar) in levels.bars.iter().enumerate() {
+                if i != band {
+                    assert!(
+                        bar < levels.bars[band] - 0.2,
+                        "{} Hz leaked into bar {}: {:?}",
+                        freq,
+                        i,
+                        levels
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_configurable_bar_count() {
+        let (mut meter, levels) = meter(8);
+        feed_tone(&mut meter, 6000.0, 0.5, 0.1);
+        let levels = levels.lock().clone();
+        assert_eq!(levels.bars.len(), 8);
+        assert_eq!(loudest_bar(&levels), 7);
+    }
+
+    #[test]
+    fn test_dbfs_readings() {
+        let (mut meter, levels) = meter(3);
+        feed_tone(&mut meter, 1000.0, 0.5, 0.1);
+        let levels = levels.lock().clone();
+        // Sine RMS is amplitude / sqrt(2): 0.354 -> -9.0 dBFS
+        assert!((levels.rms_dbfs + 9.03).abs() < 0.2, "{:?}", levels);
+        assert!((levels.peak_dbfs + 6.02).abs() < 0.1, "{:?}", levels);
+        // The band holding the tone reads the tone's RMS too
+        assert!(
+            (levels.bars[1] - bar_height(-9.03)).abs() < 0.02,
+            "{:?}",
+            levels
+        );
+        assert!(!levels.clipping);
+    }
+
+    #[test]
+    fn test_clipping_indicator_holds_then_clears() {
+        let (mut meter, levels) = meter(3);
+        feed_tone(&mut meter, 1000.0, 1.0, 0.05);
+        assert!(levels.lock().clipping);
+
+        feed_tone(&mut meter, 1000.0, 0.1, CLIP_HOLD_SECS / 2.0);
+        assert!(levels.lock().clipping);
+
+        feed_tone(&mut meter, 1000.0, 0.1, CLIP_HOLD_SECS);
+        assert!(!levels.lock().clipping);
+    }
+
+    #[test]
+    fn test_clipped_samples_are_counted() {
+        let (mut meter, levels) = meter(3);
+        for _ in 0..FFT_SIZE * 2 {
+            meter.push(0.9);
+        }
+        assert_eq!(levels.lock().clipped_samples, 0);
+
+        // A square wave at full scale clips on every sample
+        for i in 0..FFT_SIZE * 2 {
+            meter.push(if i % 16 < 8 { 1.0 } else { -1.0 });
+        }
+        assert_eq!(levels.lock().clipped_samples, FFT_SIZE as u64 * 2);
+    }
+
+    #[test]
+    fn test_noise_floor_ignores_speech_bursts() {
+        let (mut meter, levels) = meter(3);
+        // Background hiss at about -46 dBFS RMS
+        feed_tone(&mut meter, 3000.0, 0.007, 1.0);
+        let quiet = levels.lock().noise_floor_dbfs;
+        assert!((quiet + 46.1).abs() < 0.5, "{}", quiet);
+
+        // Half a second of loud speech lifts the estimate only slightly,
+        // and the next pause brings it straight back
+        feed_tone(&mut meter, 300.0, 0.5, 0.5);
+        let during = levels.lock().noise_floor_dbfs;
+        assert!(
+            during - quiet <= NOISE_FLOOR_RISE_DB_PER_SEC * 0.6,
+            "{}",
+            during
+        );
+        feed_tone(&mut meter, 3000.0, 0.007, 0.2);
+        assert!((levels.lock().noise_floor_dbfs - quiet).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_peak_hold_and_decay() {
+        let (mut meter, levels) = meter(3);
+        feed_tone(&mut meter, 1000.0, 0.5, 0.2);
+        let (loud, held) = {
+            let levels = levels.lock();
+            (levels.bars[1], levels.peaks[1])
+        };
+
+        // Bars fall gradually after the tone stops while the peak holds
+        feed_tone(&mut meter, 1000.0, 0.0, 0.25);
+        let after = levels.lock().clone();
+        assert!(after.bars[1] < loud && after.bars[1] > 0.0, "{:?}", after);
+        assert_eq!(after.peaks[1], held);
+
+        // After the hold time the peak falls too, and eventually both empty
+        feed_tone(&mut meter, 1000.0, 0.0, PEAK_HOLD_SECS + 2.5);
+        let silent = levels.lock().clone();
+        assert_eq!(silent.bars[1], 0.0);
+        assert_eq!(silent.peaks[1], 0.0);
+        assert_eq!(silent.rms_dbfs, FLOOR_DBFS);
+    }
+}
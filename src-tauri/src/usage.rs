@@ -0,0 +1,478 @@
+//! Usage and cost tracking. Every successful transcription appends a record
+//! to `usage/YYYY-MM.jsonl` in the config directory; costs are worked out
+//! when stats are read, from the price table in the settings, so changing a
+//! price re-prices past usage too. Days and months are UTC.
+
+use crate::settings::UsageConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// One successful transcription request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// Unix time the request finished
+    pub time: u64,
+    pub provider: String,
+    pub model: String,
+    pub audio_secs: f64,
+    /// Size of the uploaded file
+    pub bytes: u64,
+    pub latency_ms: u64,
+}
+
+impl UsageRecord {
+    pub fn new(
+        provider: &str,
+        model: &str,
+        audio_secs: f64,
+        bytes: u64,
+        latency: Duration,
+    ) -> Self {
+        Self {
+            time: unix_now(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            audio_secs,
+            bytes,
+            latency_ms: latency.as_millis() as u64,
+        }
+    }
+}
+
+/// Period `get_usage_stats` covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageRange {
+    Today,
+    /// Today and the six days before it
+    Week,
+    /// The current calendar month
+    Month,
+    /// The current calendar month and the eleven before it
+    Year,
+    All,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub audio_secs: f64,
+    pub bytes: u64,
+    /// In the currency of the price table; models without a price count as free
+    pub cost: f64,
+    pub avg_latency_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeriodTotals {
+    /// `YYYY-MM-DD` for days, `YYYY-MM` for months
+    pub period: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetStatus {
+    pub budget: f64,
+    /// Cost so far this calendar month
+    pub spent: f64,
+    pub exceeded: bool,
+    /// Whether transcription stops once the budget is exceeded
+    pub block: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageStats {
+    pub range: UsageRange,
+    pub total: UsageTotals,
+    /// Totals keyed by provider id
+    pub providers: BTreeMap<String, UsageTotals>,
+    /// Oldest first; only days with usage are listed
+    pub daily: Vec<PeriodTotals>,
+    pub monthly: Vec<PeriodTotals>,
+    pub budget: Option<BudgetStatus>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn usage_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("scrivano")
+        .join("usage")
+}
+
+/// Append a record to this month's file. Failing to record never fails the
+/// transcription, so problems are only logged.
+pub fn record(record: &UsageRecord) {
+    if let Err(e) = record_in(&usage_dir(), record) {
+        tracing::warn!("Failed to record usage: {}", e);
+    }
+}
+
+fn record_in(dir: &Path, record: &UsageRecord) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let path = dir.join(format!("{}.jsonl", month_of(record.time)));
+    let line =
+        serde_json::to_string(record).map_err(|e| format!("Failed to serialize usage: {}", e))?;
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Records from the month files starting at `from_month` (`YYYY-MM`) or later.
+fn load_in(dir: &Path, from_month: Option<&str>) -> Vec<UsageRecord> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut months: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "jsonl"))
+        .filter(|path| {
+            let month = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+            from_month.is_none_or(|from| month >= from)
+        })
+        .collect();
+    months.sort();
+
+    let mut records = Vec::new();
+    for path in months {
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        // A torn last line (e.g. from a crash) is skipped, not fatal
+        records.extend(
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<UsageRecord>(line).ok()),
+        );
+    }
+    records
+}
+
+/// Usage over `range`, priced with `config`.
+pub fn get_usage_stats(range: UsageRange, config: &UsageConfig) -> UsageStats {
+    let now = unix_now();
+    let records = load_in(&usage_dir(), first_month(range, now).as_deref());
+    stats_from(&records, range, config, now)
+}
+
+/// Check this month's spending before a transcription. Returns an error when
+/// the budget is spent and blocking is on, and a warning when it is spent
+/// but transcription may go on.
+pub fn check_budget(config: &UsageConfig) -> Result<Option<String>, String> {
+    let Some(budget) = config.monthly_budget else {
+        return Ok(None);
+    };
+    let now = unix_now();
+    let records = load_in(&usage_dir(), Some(&month_of(now)));
+    budget_message(&budget_status(&records, config, budget, now))
+}
+
+fn budget_message(status: &BudgetStatus) -> Result<Option<String>, String> {
+    if !status.exceeded {
+        return Ok(None);
+    }
+    let message = format!(
+        "This month's transcription budget of {:.2} is used up ({:.2} spent).",
+        status.budget, status.spent
+    );
+    if status.block {
+        Err(format!(
+            "{} Raise the budget in settings to keep transcribing.",
+            message
+        ))
+    } else {
+        Ok(Some(message))
+    }
+}
+
+fn stats_from(
+    records: &[UsageRecord],
+    range: UsageRange,
+    config: &UsageConfig,
+    now: u64,
+) -> UsageStats {
+    let start = range_start(range, now);
+    let mut total = Accumulator::default();
+    let mut providers: BTreeMap<String, Accumulator> = BTreeMap::new();
+    let mut daily: BTreeMap<String, Accumulator> = BTreeMap::new();
+    let mut monthly: BTreeMap<String, Accumulator> = BTreeMap::new();
+
+    for record in records.iter().filter(|r| r.time >= start && r.time <= now) {
+        let cost = cost_of(record, config);
+        total.add(record, cost);
+        providers
+            .entry(record.provider.clone())
+            .or_default()
+            .add(record, cost);
+        daily
+            .entry(day_of(record.time))
+            .or_default()
+            .add(record, cost);
+        monthly
+            .entry(month_of(record.time))
+            .or_default()
+            .add(record, cost);
+    }
+
+    let periods = |map: BTreeMap<String, Accumulator>| {
+        map.into_iter()
+            .map(|(period, acc)| PeriodTotals {
+                period,
+                totals: acc.totals(),
+            })
+            .collect()
+    };
+    UsageStats {
+        range,
+        total: total.totals(),
+        providers: providers
+            .into_iter()
+            .map(|(provider, acc)| (provider, acc.totals()))
+            .collect(),
+        daily: periods(daily),
+        monthly: periods(monthly),
+        budget: config
+            .monthly_budget
+            .map(|budget| budget_status(records, config, budget, now)),
+    }
+}
+
+fn budget_status(
+    records: &[UsageRecord],
+    config: &UsageConfig,
+    budget: f64,
+    now: u64,
+) -> BudgetStatus {
+    let month = month_of(now);
+    let spent: f64 = records
+        .iter()
+        .filter(|r| month_of(r.time) == month)
+        .map(|r| cost_of(r, config))
+        .sum();
+    BudgetStatus {
+        budget,
+        spent,
+        exceeded: spent >= budget,
+        block: config.block_over_budget,
+    }
+}
+
+fn cost_of(record: &UsageRecord, config: &UsageConfig) -> f64 {
+    let per_minute = config.prices.get(&record.model).copied().unwrap_or(0.0);
+    record.audio_secs / 60.0 * per_minute
+}
+
+#[derive(Default)]
+struct Accumulator {
+    requests: u64,
+    audio_secs: f64,
+    bytes: u64,
+    cost: f64,
+    latency_ms: u64,
+}
+
+impl Accumulator {
+    fn add(&mut self, record: &UsageRecord, cost: f64) {
+        self.requests += 1;
+        self.audio_secs += record.audio_secs;
+        self.bytes += record.bytes;
+        self.cost += cost;
+        self.latency_ms += record.latency_ms;
+    }
+
+    fn totals(&self) -> UsageTotals {
+        UsageTotals {
+            requests: self.requests,
+            audio_secs: self.audio_secs,
+            bytes: self.bytes,
+            cost: self.cost,
+            avg_latency_ms: self.latency_ms.checked_div(self.requests).unwrap_or(0),
+        }
+    }
+}
+
+/// First second (unix time) that `range` covers.
+fn range_start(range: UsageRange, now: u64) -> u64 {
+    let today = now - now % SECS_PER_DAY;
+    match range {
+        UsageRange::Today => today,
+        UsageRange::Week => today.saturating_sub(6 * SECS_PER_DAY),
+        UsageRange::Month => month_start(now, 0),
+        UsageRange::Year => month_start(now, 11),
+        UsageRange::All => 0,
+    }
+}
+
+/// Oldest month file `range` needs, or `None` for all of them.
+fn first_month(range: UsageRange, now: u64) -> Option<String> {
+    match range {
+        UsageRange::All => None,
+        _ => Some(month_of(range_start(range, now))),
+    }
+}
+
+/// Start of the calendar month `months_back` months before the one `time` is in.
+fn month_start(time: u64, months_back: u32) -> u64 {
+    let date = utc_date(time);
+    let months = date.year() * 12 + u8::from(date.month()) as i32 - 1 - months_back as i32;
+    let first = time::Date::from_calendar_date(
+        months.div_euclid(12),
+        time::Month::try_from(months.rem_euclid(12) as u8 + 1).unwrap_or(time::Month::January),
+        1,
+    )
+    .unwrap_or(date);
+    (first.midnight().assume_utc().unix_timestamp()).max(0) as u64
+}
+
+fn utc_date(time: u64) -> time::Date {
+    time::OffsetDateTime::from_unix_timestamp(time as i64)
+        .unwrap_or(time::OffsetDateTime::UNIX_EPOCH)
+        .date()
+}
+
+fn day_of(time: u64) -> String {
+    let date = utc_date(time);
+    format!(
+        "{:04}-{:02}-{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day()
+    )
+}
+
+fn month_of(time: u64) -> String {
+    let date = utc_date(time);
+    format!("{:04}-{:02}", date.year(), u8::from(date.month()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2026-03-15 12:00:00 UTC
+    const NOW: u64 = 1_773_576_000;
+
+    fn usage(time: u64, provider: &str, model: &str, audio_secs: f64) -> UsageRecord {
+        UsageRecord {
+            time,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            audio_secs,
+            bytes: 1000,
+            latency_ms: 400,
+        }
+    }
+
+    fn config() -> UsageConfig {
+        UsageConfig {
+            prices: BTreeMap::from([("whisper-1".to_string(), 0.006)]),
+            monthly_budget: Some(0.05),
+            block_over_budget: false,
+        }
+    }
+
+    #[test]
+    fn test_calendar_helpers() {
+        assert_eq!(day_of(NOW), "2026-03-15");
+        assert_eq!(month_of(NOW), "2026-03");
+        assert_eq!(day_of(range_start(UsageRange::Week, NOW)), "2026-03-09");
+        assert_eq!(day_of(range_start(UsageRange::Month, NOW)), "2026-03-01");
+        // Twelve months back crosses the year boundary
+        assert_eq!(day_of(range_start(UsageRange::Year, NOW)), "2025-04-01");
+        assert_eq!(
+            first_month(UsageRange::Year, NOW).as_deref(),
+            Some("2025-04")
+        );
+        assert_eq!(first_month(UsageRange::All, NOW), None);
+    }
+
+    #[test]
+    fn test_stats_aggregate_and_price_usage() {
+        let day = SECS_PER_DAY;
+        let records = vec![
+            usage(NOW - 40 * day, "openai", "whisper-1", 600.0),
+            usage(NOW - 2 * day, "openai", "whisper-1", 120.0),
+            usage(NOW - 60, "openai", "whisper-1", 60.0),
+            usage(NOW - 30, "groq", "whisper-large-v3-turbo", 300.0),
+        ];
+
+        let stats = stats_from(&records, UsageRange::Week, &config(), NOW);
+        assert_eq!(stats.total.requests, 3);
+        assert_eq!(stats.total.audio_secs, 480.0);
+        assert!((stats.total.cost - 0.018).abs() < 1e-9);
+        assert_eq!(stats.total.avg_latency_ms, 400);
+        // Models without a price cost nothing
+        assert_eq!(stats.providers["groq"].cost, 0.0);
+        assert_eq!(stats.providers["openai"].requests, 2);
+        let days: Vec<&str> = stats.daily.iter().map(|d| d.period.as_str()).collect();
+        assert_eq!(days, vec!["2026-03-13", "2026-03-15"]);
+
+        let stats = stats_from(&records, UsageRange::Year, &config(), NOW);
+        let months: Vec<&str> = stats.monthly.iter().map(|m| m.period.as_str()).collect();
+        assert_eq!(months, vec!["2026-02", "2026-03"]);
+        assert!((stats.monthly[0].totals.cost - 0.06).abs() < 1e-9);
+
+        // The budget only counts this month
+        let budget = stats.budget.unwrap();
+        assert!((budget.spent - 0.018).abs() < 1e-9);
+        assert!(!budget.exceeded);
+    }
+
+    #[test]
+    fn test_budget_warns_or_blocks() {
+        let records = vec![usage(NOW - 60, "openai", "whisper-1", 600.0)];
+        let mut config = config();
+        let status = budget_status(&records, &config, 0.05, NOW);
+        assert!(status.exceeded);
+        assert!(budget_message(&status).unwrap().is_some());
+
+        config.block_over_budget = true;
+        let status = budget_status(&records, &config, 0.05, NOW);
+        assert!(budget_message(&status).is_err());
+
+        let status = budget_status(&records, &config, 1.0, NOW);
+        assert_eq!(budget_message(&status), Ok(None));
+    }
+
+    #[test]
+    fn test_records_round_trip_through_month_files() {
+        let dir = std::env::temp_dir().join(format!("scrivano_usage_{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+
+        let old = usage(NOW - 60 * SECS_PER_DAY, "openai", "whisper-1", 30.0);
+        let recent = usage(NOW, "groq", "whisper-large-v3-turbo", 10.0);
+        record_in(&dir, &old).unwrap();
+        record_in(&dir, &recent).unwrap();
+        assert!(dir.join("2026-01.jsonl").exists());
+        assert!(dir.join("2026-03.jsonl").exists());
+
+        // Torn lines are skipped
+        fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join("2026-03.jsonl"))
+            .unwrap()
+            .write_all(b"{\"time\": 17")
+            .unwrap();
+
+        assert_eq!(load_in(&dir, None), vec![old, recent.clone()]);
+        assert_eq!(load_in(&dir, Some("2026-02")), vec![recent]);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -88,6 +88,7 @@ describe("App", () => {
       expect(mockedListen).toHaveBeenCalledWith("transcription", expect.any(Function));
       expect(mockedListen).toHaveBeenCalledWith("transcription-status", expect.any(Function));
       expect(mockedListen).toHaveBeenCalledWith("error", expect.any(Function));
+      expect(mockedListen).toHaveBeenCalledWith("devices-changed", expect.any(Function));
     });
   });
 
@@ -10,13 +10,23 @@ import {
 } from "./shortcutUtils";
 import { CollapsibleSection } from "./components/CollapsibleSection";
 import { ApiKeyEditor } from "./components/ApiKeyEditor";
+import { CredentialPicker } from "./components/CredentialPicker";
+import { DiagnosticsPanel } from "./components/DiagnosticsPanel";
+import { UsagePanel } from "./components/UsagePanel";
 import type {
   ShortcutInfo,
+  ApiKeyCheck,
   ApiKeyStatus,
   ProviderInfo,
+  ProviderCredentialList,
   TranscriptionSettings,
   AudioDeviceInfo,
+  AudioLevels,
+  AudioProcessingConfig,
+  DeviceSwitch,
+  MicTestResult,
   SectionId,
+  SettingSource,
 } from "./types";
 
 // Dev tools - only loaded in development builds
@@ -31,8 +41,18 @@ const STATUS_DISPLAY_DURATION = 1500;
 const SETTINGS_HEIGHT = 580;
 const MAIN_HEIGHT = 340;
 
+/** Rough verdict on a microphone test's signal-to-noise ratio. */
+function snrRating(snrDb: number): string {
+  if (snrDb >= 30) return "excellent";
+  if (snrDb >= 20) return "good";
+  if (snrDb >= 12) return "noisy";
+  return "too noisy to transcribe reliably";
+}
+
 function App() {
   const [text, setText] = useState("");
+  // Input devices the last transcription was recorded from
+  const [recordedWith, setRecordedWith] = useState<string[]>([]);
   const [isRecording, setIsRecording] = useState(false);
   const [status, setStatus] = useState("Ready");
   const [error, setError] = useState("");
@@ -54,6 +74,7 @@ function App() {
   const [apiKeyStatus, setApiKeyStatus] = useState<ApiKeyStatus | null>(null);
   const [apiKeySaving, setApiKeySaving] = useState(false);
   const [editingProvider, setEditingProvider] = useState<"openai" | "groq" | null>(null);
+  const [credentials, setCredentials] = useState<ProviderCredentialList[]>([]);
 
   // Provider/Model state
   const [providers, setProviders] = useState<ProviderInfo[]>([]);
@@ -65,11 +86,19 @@ function App() {
   const [audioDevices, setAudioDevices] = useState<AudioDeviceInfo[]>([]);
   const [selectedAudioDevice, setSelectedAudioDevice] = useState<string | null>(null);
   const [isPreviewActive, setIsPreviewActive] = useState(false);
-  const [previewLevels, setPreviewLevels] = useState<number[]>([0.15, 0.15, 0.15]);
+  const [previewLevels, setPreviewLevels] = useState<AudioLevels | null>(null);
+  const [micTest, setMicTest] = useState<MicTestResult | null>(null);
+  const [micTestRunning, setMicTestRunning] = useState(false);
   const previewActiveRef = useRef(false);
+  const [audioProcessing, setAudioProcessing] = useState<AudioProcessingConfig | null>(null);
+  const [monitorPreview, setMonitorPreview] = useState(false);
+  const monitorPreviewRef = useRef(false);
+  const [preRoll, setPreRoll] = useState(false);
 
   // Open on Login state
   const [openOnLogin, setOpenOnLogin] = useState(false);
+  const [provenance, setProvenance] = useState<Record<string, SettingSource>>({});
+  const [bundleText, setBundleText] = useState<string | null>(null);
   const suppressBlurRef = useRef(false);
 
   // Dev tools state (only used in dev mode)
@@ -90,13 +119,21 @@ function App() {
     invoke<string>("get_transcription").then((t) => {
       if (t) setText(t);
     });
+    invoke<string[]>("get_transcription_devices").then((devices) => setRecordedWith(devices || []));
     invoke<boolean>("get_recording_status").then(setIsRecording);
     invoke<ShortcutInfo>("get_shortcut").then(setCurrentShortcut);
     invoke<ApiKeyStatus>("get_api_key_status").then(setApiKeyStatus);
+    invoke<ProviderCredentialList[]>("list_credentials").then(setCredentials);
     invoke<ProviderInfo[]>("get_available_providers").then(setProviders);
     invoke<TranscriptionSettings>("get_transcription_settings").then(setTranscriptionSettings);
     invoke<AudioDeviceInfo[]>("list_audio_input_devices").then(setAudioDevices);
     invoke<string | null>("get_audio_input_device").then(setSelectedAudioDevice);
+    invoke<AudioProcessingConfig>("get_audio_processing").then(setAudioProcessing);
+    invoke<boolean>("get_pre_roll").then(setPreRoll);
+    invoke<Record<string, SettingSource>>("get_settings_provenance").then(setProvenance);
+    invoke<string[]>("get_settings_warnings").then((warnings) => {
+      if (warnings.length > 0) setError(warnings.join(" "));
+    });
     invoke<boolean>("get_open_on_login")
       .then(setOpenOnLogin)
       .catch(() => {});
@@ -110,13 +147,40 @@ function App() {
       listen<string>("transcription", (e) => {
         setText(e.payload);
         setStatus("Ready");
+        invoke<string[]>("get_transcription_devices").then((devices) =>
+          setRecordedWith(devices || []),
+        );
       }),
       listen<string>("transcription-status", (e) => setStatus(e.payload)),
       listen<string>("error", (e) => {
         setError(e.payload);
         setStatus("Error");
       }),
-      listen<number[]>("audio-preview-levels", (e) => {
+      listen<string[]>("settings-changed", (e) => {
+        // settings.json was edited outside the app, or settings were imported
+        invoke<ShortcutInfo>("get_shortcut").then(setCurrentShortcut);
+        invoke<TranscriptionSettings>("get_transcription_settings").then(setTranscriptionSettings);
+        invoke<string | null>("get_audio_input_device").then(setSelectedAudioDevice);
+        invoke<AudioProcessingConfig>("get_audio_processing").then(setAudioProcessing);
+        invoke<boolean>("get_pre_roll").then(setPreRoll);
+        invoke<Record<string, SettingSource>>("get_settings_provenance").then(setProvenance);
+        invoke<ApiKeyStatus>("get_api_key_status").then(setApiKeyStatus);
+        invoke<ProviderCredentialList[]>("list_credentials").then(setCredentials);
+        invoke<ProviderInfo[]>("get_available_providers").then(setProviders);
+        setError(e.payload.join(" "));
+      }),
+      listen<AudioDeviceInfo[]>("devices-changed", (e) => setAudioDevices(e.payload)),
+      listen<DeviceSwitch>("audio-device-switched", (e) => {
+        const { from, to } = e.payload;
+        setStatus(
+          to ? `"${from}" disconnected, recording from "${to}"` : `"${from}" disconnected`,
+        );
+      }),
+      listen<string>("budget-warning", (e) => setError(e.payload)),
+      listen<number>("recording-limit-warning", (e) => {
+        setStatus(`Recording stops in ${Math.ceil(e.payload)}s (length limit)`);
+      }),
+      listen<AudioLevels>("audio-preview-levels", (e) => {
         if (previewActiveRef.current) {
           setPreviewLevels(e.payload);
         }
@@ -142,7 +206,7 @@ function App() {
         if (previewActiveRef.current) {
           previewActiveRef.current = false;
           setIsPreviewActive(false);
-          setPreviewLevels([0.15, 0.15, 0.15]);
+          setPreviewLevels(null);
           invoke("stop_audio_preview").catch(() => {});
         }
         invoke("resize_window", { height: MAIN_HEIGHT }).catch(() => {});
@@ -205,7 +269,7 @@ function App() {
     if (previewActiveRef.current) {
       previewActiveRef.current = false;
       setIsPreviewActive(false);
-      setPreviewLevels([0.15, 0.15, 0.15]);
+      setPreviewLevels(null);
       invoke("stop_audio_preview").catch(() => {});
     }
     invoke("resize_window", { height: MAIN_HEIGHT }).catch(() => {});
@@ -332,6 +396,14 @@ function App() {
   const handleSaveApiKey = async (provider: "openai" | "groq", apiKey: string) => {
     setApiKeySaving(true);
     try {
+      // Catch wrong or revoked keys now rather than on the first dictation
+      const check = await invoke<ApiKeyCheck>("test_api_key", { provider, apiKey });
+      if (check.status === "invalid") {
+        setError("The provider rejected this API key. Check that it was copied completely.");
+        setApiKeySaving(false);
+        return;
+      }
+
       const result = await invoke<ApiKeyStatus>("set_api_key", {
         provider,
         apiKey,
@@ -341,8 +413,15 @@ function App() {
 
       const updatedProviders = await invoke<ProviderInfo[]>("get_available_providers");
       setProviders(updatedProviders);
+      setCredentials(await invoke<ProviderCredentialList[]>("list_credentials"));
 
-      setError("");
+      if (check.status === "no_quota") {
+        setError("Key saved, but the account has no quota left. Check your billing.");
+      } else if (check.status === "network_error") {
+        setError(`Key saved, but it could not be checked: ${check.detail}`);
+      } else {
+        setError("");
+      }
     } catch (e) {
       setError(String(e));
     }
@@ -360,6 +439,7 @@ function App() {
 
       const updatedProviders = await invoke<ProviderInfo[]>("get_available_providers");
       setProviders(updatedProviders);
+      setCredentials(await invoke<ProviderCredentialList[]>("list_credentials"));
 
       if (transcriptionSettings?.provider === provider) {
         const otherProvider = updatedProviders.find((p) => p.available && p.id !== provider);
@@ -375,6 +455,64 @@ function App() {
     setApiKeySaving(false);
   };
 
+  /** Re-read everything that depends on which credential is selected. */
+  const refreshApiKeyStatus = async () => {
+    setApiKeyStatus(await invoke<ApiKeyStatus>("get_api_key_status"));
+    setProviders(await invoke<ProviderInfo[]>("get_available_providers"));
+  };
+
+  const handleSelectCredential = async (provider: "openai" | "groq", name: string) => {
+    try {
+      setCredentials(
+        await invoke<ProviderCredentialList[]>("select_credential", { provider, name }),
+      );
+      setEditingProvider(null);
+      await refreshApiKeyStatus();
+      setError("");
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  const handleAddCredential = async (
+    provider: "openai" | "groq",
+    name: string,
+    apiKey: string,
+  ): Promise<boolean> => {
+    setApiKeySaving(true);
+    try {
+      const check = await invoke<ApiKeyCheck>("test_api_key", { provider, apiKey });
+      if (check.status === "invalid") {
+        setError("The provider rejected this API key. Check that it was copied completely.");
+        return false;
+      }
+      setCredentials(
+        await invoke<ProviderCredentialList[]>("add_credential", { provider, name, apiKey }),
+      );
+      setError("");
+      return true;
+    } catch (e) {
+      setError(String(e));
+      return false;
+    } finally {
+      setApiKeySaving(false);
+    }
+  };
+
+  const handleRemoveCredential = async (provider: "openai" | "groq", name: string) => {
+    setApiKeySaving(true);
+    try {
+      setCredentials(
+        await invoke<ProviderCredentialList[]>("remove_credential", { provider, name }),
+      );
+      await refreshApiKeyStatus();
+      setError("");
+    } catch (e) {
+      setError(String(e));
+    }
+    setApiKeySaving(false);
+  };
+
   const handleProviderChange = async (providerId: string) => {
     try {
       const result = await invoke<TranscriptionSettings>("set_transcription_provider", {
@@ -389,32 +527,116 @@ function App() {
 
   // ── Audio input device handlers ──
 
+  const restartAudioPreview = async () => {
+    if (previewActiveRef.current) {
+      await invoke("stop_audio_preview");
+      await invoke("start_audio_preview", { monitor: monitorPreviewRef.current });
+    }
+  };
+
   const handleAudioDeviceChange = async (deviceName: string | null) => {
     try {
       await invoke("set_audio_input_device", { deviceName });
       setSelectedAudioDevice(deviceName);
       // If preview is active, restart it with the new device
-      if (previewActiveRef.current) {
-        await invoke("stop_audio_preview");
-        await invoke("start_audio_preview");
-      }
+      await restartAudioPreview();
+      setError("");
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  const handleAudioProcessingChange = async (changes: Partial<AudioProcessingConfig>) => {
+    if (!audioProcessing) return;
+    const config = { ...audioProcessing, ...changes };
+    try {
+      await invoke("set_audio_processing", { config });
+      setAudioProcessing(config);
+      // Restart the preview so the new filters can be heard
+      await restartAudioPreview();
+      setError("");
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  const handleMonitorToggle = async () => {
+    monitorPreviewRef.current = !monitorPreviewRef.current;
+    setMonitorPreview(monitorPreviewRef.current);
+    try {
+      await restartAudioPreview();
+      setError("");
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  const runMicTest = async () => {
+    setMicTestRunning(true);
+    setMicTest(null);
+    try {
+      setMicTest(await invoke<MicTestResult>("run_mic_test"));
+      setError("");
+    } catch (e) {
+      setError(String(e));
+    } finally {
+      setMicTestRunning(false);
+    }
+  };
+
+  const playMicTest = async () => {
+    try {
+      await invoke("play_mic_test");
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  const handlePreRollToggle = async () => {
+    try {
+      await invoke("set_pre_roll", { enabled: !preRoll });
+      setPreRoll(!preRoll);
       setError("");
     } catch (e) {
       setError(String(e));
     }
   };
 
+  const handleExportSettings = async () => {
+    try {
+      const bundle = await invoke<string>("export_settings");
+      await invoke("copy_to_clipboard", { text: bundle });
+      setError("");
+      setStatus("Settings copied");
+      setTimeout(() => setStatus("Ready"), STATUS_DISPLAY_DURATION);
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  const handleImportSettings = async () => {
+    if (!bundleText) return;
+    try {
+      // Warnings arrive with the settings-changed event
+      await invoke("import_settings", { bundle: bundleText });
+      setBundleText(null);
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
   const toggleAudioPreview = async () => {
     try {
       if (previewActiveRef.current) {
         previewActiveRef.current = false;
         setIsPreviewActive(false);
-        setPreviewLevels([0.15, 0.15, 0.15]);
+        setPreviewLevels(null);
+        setMicTest(null);
         await invoke("stop_audio_preview");
       } else {
         previewActiveRef.current = true;
         setIsPreviewActive(true);
-        await invoke("start_audio_preview");
+        await invoke("start_audio_preview", { monitor: monitorPreviewRef.current });
       }
       setError("");
     } catch (e) {
@@ -441,6 +663,7 @@ function App() {
   };
 
   const hasAnyApiKey = apiKeyStatus?.openai_configured || apiKeyStatus?.groq_configured;
+  const managedSettings = Object.keys(provenance).filter((path) => provenance[path] === "managed");
 
   // ═══════════════════════════════════════════════════════════════════════════
   // SETTINGS VIEW
@@ -473,6 +696,11 @@ function App() {
             No API keys configured. Transcription will not function.
           </div>
         )}
+        {managedSettings.length > 0 && (
+          <div className="warning settings-warning">
+            Managed by your organization: {managedSettings.join(", ")}
+          </div>
+        )}
 
         <div className="content settings-content">
           {/* ── Model Section ── */}
@@ -579,18 +807,127 @@ function App() {
                 </button>
               </div>
               <div className="audio-level-meter">
-                {previewLevels.map((level, i) => (
+                {(previewLevels?.bars ?? [0, 0, 0]).map((level, i) => (
                   <div key={i} className="audio-level-bar-track">
                     <div
                       className={`audio-level-bar-fill ${isPreviewActive ? "active" : ""}`}
-                      style={{ width: `${(isPreviewActive ? level : 0.15) * 100}%` }}
+                      style={{
+                        width: `${(isPreviewActive ? Math.max(level, 0.02) : 0.15) * 100}%`,
+                      }}
                     />
+                    {isPreviewActive && previewLevels && (
+                      <div
+                        className="audio-level-peak"
+                        style={{ left: `${previewLevels.peaks[i] * 100}%` }}
+                      />
+                    )}
                   </div>
                 ))}
               </div>
+              {isPreviewActive && previewLevels && (
+                <p className="audio-level-readout">
+                  {previewLevels.rms_dbfs.toFixed(0)} dBFS RMS · peak{" "}
+                  {previewLevels.peak_dbfs.toFixed(0)} dBFS · noise floor{" "}
+                  {previewLevels.noise_floor_dbfs.toFixed(0)} dBFS
+                  {previewLevels.clipping && (
+                    <span className="audio-level-clipping">Clipping</span>
+                  )}
+                  {previewLevels.clipped_samples > 0 && (
+                    <span className="audio-level-clip-count">
+                      {previewLevels.clipped_samples} clipped samples
+                    </span>
+                  )}
+                </p>
+              )}
               {isPreviewActive && (
                 <p className="audio-preview-hint">Speak to see your audio levels</p>
               )}
+              {isPreviewActive && (
+                <div className="mic-test-actions">
+                  <button className="btn small" onClick={runMicTest} disabled={micTestRunning}>
+                    {micTestRunning ? "Recording… speak now" : "Record 5 s test"}
+                  </button>
+                  {micTest && (
+                    <button className="btn small" onClick={playMicTest}>
+                      Play back
+                    </button>
+                  )}
+                </div>
+              )}
+              {micTest && (
+                <div className="mic-test-result">
+                  <p className="mic-test-text">
+                    {micTest.text ? `“${micTest.text}”` : "No speech was recognized"}
+                  </p>
+                  <p className="audio-level-readout">
+                    SNR {micTest.snr_db.toFixed(0)} dB ({snrRating(micTest.snr_db)}) · peak{" "}
+                    {micTest.peak_dbfs.toFixed(0)} dBFS
+                  </p>
+                </div>
+              )}
+            </div>
+
+            {audioProcessing && (
+              <div className="audio-processing-section">
+                <span className="audio-preview-label">Processing</span>
+                {(
+                  [
+                    ["high_pass", "Remove rumble (high-pass)"],
+                    ["noise_suppression", "Noise suppression"],
+                    ["agc", "Automatic gain"],
+                  ] as const
+                ).map(([key, label]) => (
+                  <div key={key} className="audio-processing-row">
+                    <span className="audio-processing-label">{label}</span>
+                    <button
+                      className={`toggle-switch ${audioProcessing[key] ? "active" : ""}`}
+                      onClick={() => handleAudioProcessingChange({ [key]: !audioProcessing[key] })}
+                      role="switch"
+                      aria-checked={audioProcessing[key]}
+                      aria-label={label}
+                    >
+                      <span className="toggle-knob" />
+                    </button>
+                  </div>
+                ))}
+                <div className="audio-processing-row">
+                  <span className="audio-processing-label">
+                    Hear processed audio while testing (use headphones)
+                  </span>
+                  <button
+                    className={`toggle-switch ${monitorPreview ? "active" : ""}`}
+                    onClick={handleMonitorToggle}
+                    role="switch"
+                    aria-checked={monitorPreview}
+                    aria-label="Hear processed audio"
+                  >
+                    <span className="toggle-knob" />
+                  </button>
+                </div>
+              </div>
+            )}
+
+            <div className="audio-processing-section">
+              <span className="audio-preview-label">Pre-roll</span>
+              <div className="audio-processing-row">
+                <span className="audio-processing-label">
+                  Catch the first word by keeping the microphone open between recordings
+                </span>
+                <button
+                  className={`toggle-switch ${preRoll ? "active" : ""}`}
+                  onClick={handlePreRollToggle}
+                  role="switch"
+                  aria-checked={preRoll}
+                  aria-label="Keep microphone open for pre-roll"
+                >
+                  <span className="toggle-knob" />
+                </button>
+              </div>
+              {preRoll && (
+                <p className="audio-preview-hint">
+                  The menu bar icon shows a ring while the microphone is open
+                </p>
+              )}
             </div>
           </CollapsibleSection>
 
@@ -607,25 +944,116 @@ function App() {
               label="OpenAI"
               placeholder="sk-..."
               configured={apiKeyStatus?.openai_configured ?? false}
+              source={apiKeyStatus?.openai_source ?? null}
+              envVar="OPENAI_API_KEY"
               saving={apiKeySaving}
               isEditing={editingProvider === "openai"}
               onStartEdit={() => setEditingProvider("openai")}
               onCancelEdit={() => setEditingProvider(null)}
               onSave={(key) => handleSaveApiKey("openai", key)}
               onClear={() => handleClearApiKey("openai")}
-            />
+            >
+              <CredentialPicker
+                provider="openai"
+                credentials={credentials}
+                placeholder="sk-..."
+                saving={apiKeySaving}
+                onSelect={(name) => handleSelectCredential("openai", name)}
+                onAdd={(name, key) => handleAddCredential("openai", name, key)}
+                onRemove={(name) => handleRemoveCredential("openai", name)}
+              />
+            </ApiKeyEditor>
 
             <ApiKeyEditor
               label="Groq"
               placeholder="gsk_..."
               configured={apiKeyStatus?.groq_configured ?? false}
+              source={apiKeyStatus?.groq_source ?? null}
+              envVar="GROQ_API_KEY"
               saving={apiKeySaving}
               isEditing={editingProvider === "groq"}
               onStartEdit={() => setEditingProvider("groq")}
               onCancelEdit={() => setEditingProvider(null)}
               onSave={(key) => handleSaveApiKey("groq", key)}
               onClear={() => handleClearApiKey("groq")}
-            />
+            >
+              <CredentialPicker
+                provider="groq"
+                credentials={credentials}
+                placeholder="gsk_..."
+                saving={apiKeySaving}
+                onSelect={(name) => handleSelectCredential("groq", name)}
+                onAdd={(name, key) => handleAddCredential("groq", name, key)}
+                onRemove={(name) => handleRemoveCredential("groq", name)}
+              />
+            </ApiKeyEditor>
+          </CollapsibleSection>
+
+          {/* ── Usage Section ── */}
+          <CollapsibleSection
+            id="usage"
+            title="Usage"
+            openSection={openSection}
+            onToggle={handleSectionToggle}
+          >
+            <p className="settings-description">Transcription usage and estimated cost</p>
+            <UsagePanel onError={setError} />
+          </CollapsibleSection>
+
+          {/* ── Import & Export Section ── */}
+          <CollapsibleSection
+            id="sharing"
+            title="Import & Export"
+            openSection={openSection}
+            onToggle={handleSectionToggle}
+          >
+            <p className="settings-description">
+              Copy these settings to other machines. API keys and where they come from are not
+              included.
+            </p>
+            <div className="mic-test-actions">
+              <button className="btn small" onClick={handleExportSettings}>
+                Copy Settings
+              </button>
+              <button
+                className="btn small"
+                onClick={() => setBundleText(bundleText === null ? "" : null)}
+              >
+                {bundleText === null ? "Import" : "Cancel"}
+              </button>
+            </div>
+            {bundleText !== null && (
+              <>
+                <textarea
+                  className="settings-bundle-input"
+                  value={bundleText}
+                  onChange={(e) => setBundleText(e.target.value)}
+                  placeholder="Paste copied settings"
+                  aria-label="Settings to import"
+                  spellCheck={false}
+                />
+                <div className="mic-test-actions">
+                  <button
+                    className="btn small"
+                    onClick={handleImportSettings}
+                    disabled={!bundleText.trim()}
+                  >
+                    Apply
+                  </button>
+                </div>
+              </>
+            )}
+          </CollapsibleSection>
+
+          {/* ── Diagnostics Section ── */}
+          <CollapsibleSection
+            id="diagnostics"
+            title="Diagnostics"
+            openSection={openSection}
+            onToggle={handleSectionToggle}
+          >
+            <p className="settings-description">Time taken by each step of recent dictations</p>
+            <DiagnosticsPanel onError={setError} />
           </CollapsibleSection>
         </div>
 
@@ -724,6 +1152,9 @@ function App() {
               <button className="current-model" onClick={openSettings}>
                 {transcriptionSettings.model}
               </button>
+              {recordedWith.length > 0 && text && (
+                <span className="recorded-with">recorded with {recordedWith.join(" → ")}</span>
+              )}
             </div>
           )}
         </>
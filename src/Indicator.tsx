@@ -1,20 +1,32 @@
 import { useState, useEffect } from "react";
 import { emit, listen } from "@tauri-apps/api/event";
 import "./Indicator.css";
+import type { AudioLevels, RecordingElapsed } from "./types";
+
+/** Seconds before the recording limit at which the indicator shows a countdown. */
+const COUNTDOWN_SECS = 10;
 
 type IndicatorState = "recording" | "processing";
 
 function Indicator() {
   const [state, setState] = useState<IndicatorState>("recording");
   const [audioLevels, setAudioLevels] = useState<number[]>([0.2, 0.3, 0.2]);
+  const [clipping, setClipping] = useState(false);
+  const [remaining, setRemaining] = useState<number | null>(null);
 
   useEffect(() => {
     const unlisteners = [
-      listen<number[]>("audio-levels", (e) => {
-        setAudioLevels(e.payload);
+      listen<AudioLevels>("audio-levels", (e) => {
+        setAudioLevels(e.payload.bars);
+        setClipping(e.payload.clipping);
+      }),
+      listen<RecordingElapsed>("recording-elapsed", (e) => {
+        const { seconds, limit_seconds } = e.payload;
+        setRemaining(limit_seconds > 0 ? Math.max(0, limit_seconds - seconds) : null);
       }),
       listen<string>("indicator-state", (e) => {
         setState(e.payload as IndicatorState);
+        setRemaining(null);
       }),
     ];
 
@@ -28,14 +40,17 @@ function Indicator() {
     };
   }, []);
 
+  const countdown = state === "recording" && remaining !== null && remaining <= COUNTDOWN_SECS;
+
   return (
-    <div className="indicator-circle">
-      {state === "recording" && (
+    <div className={`indicator-circle ${countdown ? "ending" : ""}`}>
+      {countdown && <div className="countdown">{Math.ceil(remaining)}</div>}
+      {state === "recording" && !countdown && (
         <div className="audio-bars">
           {audioLevels.map((level, i) => (
             <div
               key={i}
-              className="audio-bar"
+              className={`audio-bar ${clipping ? "clipping" : ""}`}
               style={{
                 height: `${Math.max(15, Math.min(100, level * 100))}%`,
               }}
@@ -1,27 +1,36 @@
-import { useState } from "react";
+import React, { useState } from "react";
+import type { ApiKeySource } from "../types";
 
 interface ApiKeyEditorProps {
   label: string;
   placeholder: string;
   configured: boolean;
+  source: ApiKeySource | null;
+  /** Environment variable that overrides the stored key, e.g. OPENAI_API_KEY */
+  envVar: string;
   saving: boolean;
   isEditing: boolean;
   onStartEdit: () => void;
   onCancelEdit: () => void;
   onSave: (key: string) => void;
   onClear: () => void;
+  /** Shown between the label and the key, e.g. a credential picker */
+  children?: React.ReactNode;
 }
 
 export function ApiKeyEditor({
   label,
   placeholder,
   configured,
+  source,
+  envVar,
   saving,
   isEditing,
   onStartEdit,
   onCancelEdit,
   onSave,
   onClear,
+  children,
 }: ApiKeyEditorProps) {
   const [keyInput, setKeyInput] = useState("");
   const [showKey, setShowKey] = useState(false);
@@ -36,13 +45,34 @@ export function ApiKeyEditor({
     onCancelEdit();
   };
 
+  // Keys from outside the keychain take precedence, so editing here would have no effect
+  const externalSource =
+    source === "env"
+      ? envVar
+      : source === "command"
+        ? "key command"
+        : source === "file"
+          ? "key file"
+          : null;
+
   return (
     <div className="api-key-row">
       <div className="api-key-header">
         <span className="api-key-label">{label}</span>
-        {configured && <span className="api-key-status configured">configured</span>}
+        {configured && (
+          <span className="api-key-status configured">
+            {externalSource ? `from ${externalSource}` : "configured"}
+          </span>
+        )}
       </div>
-      {configured && !isEditing ? (
+      {children}
+      {externalSource ? (
+        <div className="api-key-input-row">
+          <div className="api-key-display">
+            &#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;
+          </div>
+        </div>
+      ) : configured && !isEditing ? (
         <div className="api-key-input-row">
           <div className="api-key-display">
             &#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;&#x2022;
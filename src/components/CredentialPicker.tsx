@@ -0,0 +1,110 @@
+import { useState } from "react";
+import type { ProviderCredentialList } from "../types";
+
+interface CredentialPickerProps {
+  provider: string;
+  /** Every provider's credentials, from `list_credentials` */
+  credentials: ProviderCredentialList[];
+  placeholder: string;
+  saving: boolean;
+  onSelect: (name: string) => void;
+  onAdd: (name: string, key: string) => Promise<boolean>;
+  onRemove: (name: string) => void;
+}
+
+/** Switch between a provider's named keys, e.g. "work" and "personal". */
+export function CredentialPicker({
+  provider,
+  credentials,
+  placeholder,
+  saving,
+  onSelect,
+  onAdd,
+  onRemove,
+}: CredentialPickerProps) {
+  const [adding, setAdding] = useState(false);
+  const [nameInput, setNameInput] = useState("");
+  const [keyInput, setKeyInput] = useState("");
+
+  const list = credentials.find((c) => c.provider === provider);
+
+  const handleAdd = async () => {
+    if (await onAdd(nameInput.trim(), keyInput)) {
+      setAdding(false);
+      setNameInput("");
+      setKeyInput("");
+    }
+  };
+
+  const handleCancel = () => {
+    setAdding(false);
+    setNameInput("");
+    setKeyInput("");
+  };
+
+  if (!list) return null;
+
+  if (adding) {
+    return (
+      <div className="api-key-input-row credential-row">
+        <input
+          className="api-key-input credential-name-input"
+          placeholder="Name, e.g. work"
+          value={nameInput}
+          onChange={(e) => setNameInput(e.target.value)}
+          autoFocus
+        />
+        <div className="api-key-input-wrapper">
+          <input
+            type="password"
+            className="api-key-input"
+            placeholder={placeholder}
+            value={keyInput}
+            onChange={(e) => setKeyInput(e.target.value)}
+          />
+        </div>
+        <button
+          className="btn small"
+          onClick={handleAdd}
+          disabled={saving || !nameInput.trim() || !keyInput.trim()}
+        >
+          Add
+        </button>
+        <button className="btn small" onClick={handleCancel}>
+          Cancel
+        </button>
+      </div>
+    );
+  }
+
+  return (
+    <div className="api-key-input-row credential-row">
+      <select
+        className="audio-device-select"
+        value={list.selected}
+        onChange={(e) => onSelect(e.target.value)}
+        disabled={saving}
+        title="Key in use"
+      >
+        {list.credentials.map((credential) => (
+          <option key={credential.name} value={credential.name}>
+            {credential.configured ? credential.name : `${credential.name} (no key)`}
+          </option>
+        ))}
+      </select>
+      <button className="btn small" onClick={() => setAdding(true)} disabled={saving}>
+        New
+      </button>
+      {list.selected !== "default" && (
+        <button
+          className="api-key-clear"
+          onClick={() => onRemove(list.selected)}
+          disabled={saving}
+          title="Remove this credential"
+        >
+          &#xD7;
+        </button>
+      )}
+    </div>
+  );
+}
@@ -0,0 +1,86 @@
+import { useCallback, useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import type { DictationTiming } from "../types";
+
+const STAGE_LABELS: Record<string, string> = {
+  stream_start: "mic",
+  file_write: "save",
+  upload: "upload",
+  server: "server",
+  paste: "paste",
+};
+
+interface DiagnosticsPanelProps {
+  onError: (message: string) => void;
+}
+
+function formatMs(ms: number): string {
+  return ms < 1000 ? `${Math.round(ms)}ms` : `${(ms / 1000).toFixed(1)}s`;
+}
+
+/** Where the time went in the last few dictations, and a bundle for bug reports. */
+export function DiagnosticsPanel({ onError }: DiagnosticsPanelProps) {
+  const [timings, setTimings] = useState<DictationTiming[]>([]);
+  const [exporting, setExporting] = useState(false);
+  const [exportedPath, setExportedPath] = useState<string | null>(null);
+
+  const refresh = useCallback(() => {
+    invoke<DictationTiming[]>("get_recent_timings")
+      .then(setTimings)
+      .catch(() => {});
+  }, []);
+
+  useEffect(refresh, [refresh]);
+
+  const exportDiagnostics = async () => {
+    setExporting(true);
+    try {
+      setExportedPath(await invoke<string>("export_diagnostics"));
+    } catch (e) {
+      onError(String(e));
+    } finally {
+      setExporting(false);
+    }
+  };
+
+  return (
+    <>
+      {timings.length === 0 ? (
+        <p className="audio-preview-hint">No dictations since Scrivano started</p>
+      ) : (
+        timings.map((timing) => (
+          <div key={timing.started_at} className="timing-row">
+            <div className="audio-processing-row">
+              <span className="audio-processing-label">
+                {new Date(timing.started_at).toLocaleTimeString()}
+                {timing.fields.provider ? ` · ${timing.fields.provider}` : ""}
+                {timing.fields.outcome && timing.fields.outcome !== "ok"
+                  ? ` · ${timing.fields.outcome.replace(/_/g, " ")}`
+                  : ""}
+              </span>
+              <span className="usage-figures">{formatMs(timing.total_ms)}</span>
+            </div>
+            <p className="timing-stages">
+              {timing.stages
+                .map((s) => `${STAGE_LABELS[s.stage] ?? s.stage} ${formatMs(s.ms)}`)
+                .join(" · ")}
+            </p>
+          </div>
+        ))
+      )}
+      <div className="mic-test-actions">
+        <button className="btn small" onClick={refresh}>
+          Refresh
+        </button>
+        <button className="btn small" onClick={exportDiagnostics} disabled={exporting}>
+          {exporting ? "Exporting..." : "Export Diagnostics"}
+        </button>
+      </div>
+      {exportedPath && <p className="audio-preview-hint">Saved to {exportedPath}</p>}
+      <p className="audio-preview-hint">
+        The export holds your settings, devices, logs and timings for a bug report. API keys are
+        never included.
+      </p>
+    </>
+  );
+}
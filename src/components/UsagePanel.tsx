@@ -0,0 +1,144 @@
+import { useCallback, useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import type { PeriodTotals, UsageRange, UsageStats, UsageTotals } from "../types";
+
+interface UsagePanelProps {
+  onError: (message: string) => void;
+}
+
+const RANGES: [UsageRange, string][] = [
+  ["today", "Today"],
+  ["week", "Last 7 days"],
+  ["month", "This month"],
+  ["year", "Last 12 months"],
+  ["all", "All time"],
+];
+
+function formatCost(cost: number): string {
+  return cost > 0 && cost < 0.01 ? "<0.01" : cost.toFixed(2);
+}
+
+function formatTotals(totals: UsageTotals): string {
+  const minutes = (totals.audio_secs / 60).toFixed(1);
+  const requests = `${totals.requests} ${totals.requests === 1 ? "request" : "requests"}`;
+  return `${requests} · ${minutes} min · ${formatCost(totals.cost)}`;
+}
+
+/** Transcription usage and cost over a chosen period, and the monthly budget. */
+export function UsagePanel({ onError }: UsagePanelProps) {
+  const [range, setRange] = useState<UsageRange>("month");
+  const [stats, setStats] = useState<UsageStats | null>(null);
+  const [budgetInput, setBudgetInput] = useState("");
+  const [block, setBlock] = useState(false);
+
+  const refresh = useCallback(() => {
+    invoke<UsageStats>("get_usage_stats", { range })
+      .then((stats) => {
+        setStats(stats);
+        setBudgetInput(stats.budget ? String(stats.budget.budget) : "");
+        setBlock(stats.budget?.block ?? false);
+      })
+      .catch((e) => onError(String(e)));
+  }, [range, onError]);
+
+  useEffect(refresh, [refresh]);
+
+  const saveBudget = async (block: boolean) => {
+    const trimmed = budgetInput.trim();
+    const budget = trimmed === "" ? null : Number(trimmed);
+    if (budget !== null && (!Number.isFinite(budget) || budget < 0)) {
+      onError("The budget must be zero or more");
+      return;
+    }
+    try {
+      await invoke("set_usage_budget", { budget, block });
+      setBlock(block);
+      refresh();
+    } catch (e) {
+      onError(String(e));
+    }
+  };
+
+  if (!stats) return null;
+
+  // Short ranges break down by day, long ones by month
+  const rows: PeriodTotals[] = range === "year" || range === "all" ? stats.monthly : stats.daily;
+
+  return (
+    <>
+      <div className="audio-device-select-wrapper">
+        <select
+          className="audio-device-select"
+          value={range}
+          onChange={(e) => setRange(e.target.value as UsageRange)}
+          aria-label="Usage period"
+        >
+          {RANGES.map(([value, label]) => (
+            <option key={value} value={value}>
+              {label}
+            </option>
+          ))}
+        </select>
+      </div>
+
+      <p className="usage-total">{formatTotals(stats.total)}</p>
+      {stats.total.requests > 0 && (
+        <p className="audio-level-readout">
+          average response {(stats.total.avg_latency_ms / 1000).toFixed(1)}s
+        </p>
+      )}
+
+      {Object.entries(stats.providers).map(([provider, totals]) => (
+        <div key={provider} className="audio-processing-row">
+          <span className="audio-processing-label">{provider}</span>
+          <span className="usage-figures">{formatTotals(totals)}</span>
+        </div>
+      ))}
+
+      {rows.length > 1 && (
+        <div className="audio-processing-section">
+          {rows.map((row) => (
+            <div key={row.period} className="audio-processing-row">
+              <span className="audio-processing-label">{row.period}</span>
+              <span className="usage-figures">{formatTotals(row)}</span>
+            </div>
+          ))}
+        </div>
+      )}
+
+      <div className="audio-processing-section">
+        <span className="audio-preview-label">Monthly budget</span>
+        {stats.budget && (
+          <p className={stats.budget.exceeded ? "settings-warning" : "audio-level-readout"}>
+            {formatCost(stats.budget.spent)} of {formatCost(stats.budget.budget)} spent this month
+          </p>
+        )}
+        <div className="api-key-input-row">
+          <input
+            className="api-key-input"
+            inputMode="decimal"
+            placeholder="No budget"
+            value={budgetInput}
+            onChange={(e) => setBudgetInput(e.target.value)}
+            aria-label="Monthly budget"
+          />
+          <button className="btn small" onClick={() => saveBudget(block)}>
+            Save
+          </button>
+        </div>
+        <div className="audio-processing-row">
+          <span className="audio-processing-label">Stop transcribing when it is used up</span>
+          <button
+            className={`toggle-switch ${block ? "active" : ""}`}
+            onClick={() => saveBudget(!block)}
+            role="switch"
+            aria-checked={block}
+            aria-label="Stop transcribing over budget"
+          >
+            <span className="toggle-knob" />
+          </button>
+        </div>
+      </div>
+    </>
+  );
+}
@@ -4,11 +4,36 @@ export interface ShortcutInfo {
   display: string;
 }
 
+/** Where an API key was found; see `api_keys.rs` for the order sources are tried in. */
+export type ApiKeySource = "env" | "command" | "file" | "keychain";
+
 export interface ApiKeyStatus {
   openai_configured: boolean;
   groq_configured: boolean;
+  openai_source: ApiKeySource | null;
+  groq_source: ApiKeySource | null;
+}
+
+export interface CredentialInfo {
+  name: string;
+  /** Whether a key is stored under this name */
+  configured: boolean;
+}
+
+/** A provider's named keys; the keys themselves never reach the webview. */
+export interface ProviderCredentialList {
+  provider: string;
+  credentials: CredentialInfo[];
+  selected: string;
+  /** Credential overrides keyed by app bundle id */
+  apps: Record<string, string>;
 }
 
+/** Result of `test_api_key`. */
+export type ApiKeyCheck =
+  | { status: "valid" | "invalid" | "no_quota" }
+  | { status: "network_error"; detail: string };
+
 export interface ProviderInfo {
   id: string;
   name: string;
@@ -23,7 +48,132 @@ export interface TranscriptionSettings {
 
 export interface AudioDeviceInfo {
   name: string;
+  id: string;
   is_default: boolean;
 }
 
-export type SectionId = "model" | "shortcut" | "apikeys" | "audio";
+/** Payload of the `recording-elapsed` event, sent about twice a second while recording. */
+export interface RecordingElapsed {
+  seconds: number;
+  estimated_bytes: number;
+  /** 0 when recordings have no time limit. */
+  limit_seconds: number;
+}
+
+export interface AudioLevels {
+  bars: number[];
+  peaks: number[];
+  rms_dbfs: number;
+  peak_dbfs: number;
+  clipping: boolean;
+  noise_floor_dbfs: number;
+  clipped_samples: number;
+}
+
+/** Result of `run_mic_test`: a short recording made from the level monitor. */
+export interface MicTestResult {
+  text: string;
+  snr_db: number;
+  peak_dbfs: number;
+  active_ratio: number;
+}
+
+export interface AudioDeviceConfig {
+  channel_mix: number[];
+  gain_db: number;
+  sample_rate: number | null;
+  buffer_size: number | null;
+}
+
+export interface AudioProcessingConfig {
+  high_pass: boolean;
+  high_pass_hz: number;
+  noise_suppression: boolean;
+  agc: boolean;
+  agc_target_dbfs: number;
+}
+
+export interface DeviceSwitch {
+  from: string;
+  to: string | null;
+}
+
+export type SectionId =
+  | "model"
+  | "shortcut"
+  | "apikeys"
+  | "audio"
+  | "usage"
+  | "sharing"
+  | "diagnostics";
+
+/** Where a setting's value comes from; settings not listed in the provenance use defaults. */
+export type SettingSource = "user" | "managed";
+
+export type BatchOutput = "events" | "txt" | "srt" | "vtt" | "json";
+
+export type BatchJobStatus =
+  | "queued"
+  | "decoding"
+  | "transcribing"
+  | "done"
+  | "failed"
+  | "cancelled";
+
+export interface BatchProgress {
+  batch_id: number;
+  index: number;
+  total: number;
+  path: string;
+  status: BatchJobStatus;
+  text: string | null;
+  output_path: string | null;
+  error: string | null;
+}
+
+export type UsageRange = "today" | "week" | "month" | "year" | "all";
+
+export interface UsageTotals {
+  requests: number;
+  audio_secs: number;
+  bytes: number;
+  /** In the currency of the price table */
+  cost: number;
+  avg_latency_ms: number;
+}
+
+export interface PeriodTotals extends UsageTotals {
+  /** `YYYY-MM-DD` for days, `YYYY-MM` for months (UTC) */
+  period: string;
+}
+
+export interface BudgetStatus {
+  budget: number;
+  spent: number;
+  exceeded: boolean;
+  block: boolean;
+}
+
+export interface UsageStats {
+  range: UsageRange;
+  total: UsageTotals;
+  providers: Record<string, UsageTotals>;
+  daily: PeriodTotals[];
+  monthly: PeriodTotals[];
+  budget: BudgetStatus | null;
+}
+
+export interface StageTiming {
+  /** stream_start, file_write, upload, server or paste */
+  stage: string;
+  ms: number;
+}
+
+export interface DictationTiming {
+  /** Unix time in milliseconds */
+  started_at: number;
+  /** provider, model and outcome of the dictation */
+  fields: Record<string, string>;
+  stages: StageTiming[];
+  total_ms: number;
+}